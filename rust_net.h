@@ -7,19 +7,171 @@
 /// client context
 struct ClientContext;
 
+/// 内嵌的HTTP服务器
+struct HttpServerContext;
+
+/// 拦截回调中的请求, 只在回调期间有效
+struct InterceptedRequest;
+
+/// 拦截回调中的响应, 只在回调期间有效
+struct InterceptedResponse;
+
+/// KCP会话
+struct KcpContext;
+
+/// TCP连接
+struct TcpContext;
+
+/// TCP监听
+struct TcpListenerContext;
+
 /// tokio context
 struct TokioContext;
 
+/// UDP socket
+struct UdpContext;
+
 struct WsContext;
 
+/// WebSocket服务器
+struct WsServerContext;
+
+/// 网络统计快照
+struct NetStats {
+  /// 发起的http请求数
+  uint64_t requests_started;
+  /// 成功的http请求数(状态码2xx)
+  uint64_t requests_succeeded;
+  /// 超时失败
+  uint64_t requests_failed_timeout;
+  /// 连接失败
+  uint64_t requests_failed_connect;
+  /// 响应状态码非2xx
+  uint64_t requests_failed_status;
+  /// 接收或解码body失败
+  uint64_t requests_failed_body;
+  /// 其它原因失败
+  uint64_t requests_failed_other;
+  /// http上传字节数(请求body)
+  uint64_t bytes_uploaded;
+  /// http下载字节数(响应body)
+  uint64_t bytes_downloaded;
+  /// 正在进行中的http请求数
+  uint64_t active_requests;
+  /// 当前打开的websocket连接数
+  uint64_t ws_open_connections;
+  /// websocket发送消息数
+  uint64_t ws_messages_sent;
+  /// websocket接收消息数
+  uint64_t ws_messages_received;
+  /// websocket发送字节数
+  uint64_t ws_bytes_sent;
+  /// websocket接收字节数
+  uint64_t ws_bytes_received;
+};
+
+/// 请求拦截回调, 在请求即将发送时(排队之后)于工作线程中调用
+/// 返回false时请求失败, 不再发送
+using RequestInterceptor = bool(*)(InterceptedRequest *request, void *user_data);
+
+/// 响应拦截回调, 在读取完body之后、写入请求结果之前于工作线程中调用
+using ResponseInterceptor = void(*)(InterceptedResponse *response, void *user_data);
+
+/// 刷新token的回调, 将新的access token写入buffer, 返回写入的字节数, 返回负数表示刷新失败
+/// 回调在阻塞线程池中调用, 可以同步请求
+using AuthRefreshCallback = int32_t(*)(char *buffer, uintptr_t buffer_len, void *user_data);
+
+/// 自定义DNS解析回调
+/// 将host解析出的IP以逗号分隔写入buffer(例如 "1.2.3.4,::1"), 返回写入的字节数
+/// 返回负数表示解析失败, 返回0表示交给系统解析
+/// 回调在阻塞线程池中调用, 可以同步查询
+using DnsResolveCallback = int32_t(*)(const char *host,
+                                      char *buffer,
+                                      uintptr_t buffer_len,
+                                      void *user_data);
+
 struct RequestResponse {
   const uint8_t *data;
   uintptr_t len;
   uintptr_t cap;
   uint32_t status;
+  /// 实际协商出的协议版本 9/10/11/20/30, 没有结果时为0
   int32_t version;
 };
 
+/// 请求各阶段耗时(毫秒), 无法获取的阶段为-1
+struct RequestTimings {
+  /// 排队等待并发名额
+  double queued;
+  /// DNS解析
+  double dns;
+  /// TCP连接, 仅https可以获取
+  double tcp_connect;
+  /// TLS握手
+  double tls_handshake;
+  /// 发送请求body, 仅有body的请求可以获取
+  /// 没有上传限速时body随请求头一次写出, 计为0
+  double request_sent;
+  /// 从开始发送到收到响应头(包含建立连接)
+  double ttfb;
+  /// 接收响应body
+  double body_complete;
+  /// 总耗时(包含排队)
+  double total;
+  /// 是否复用了已有连接, 无法获取连接信息(HTTP/3)时为false
+  bool connection_reused;
+};
+
+/// 日志回调
+/// level: 1 error, 2 warn, 3 info, 4 debug, 5 trace
+/// target: 模块名, 例如 rust_net::http、reqwest
+using LogCallback = void(*)(int32_t level, const char *target, const char *message, void *user_data);
+
+/// 请求body
+struct HttpServerBody {
+  const uint8_t *data;
+  uintptr_t len;
+  uintptr_t cap;
+};
+
+/// 连接事件
+/// message_type:
+/// 0: 没有事件
+/// 1: 连接成功, data为远端地址
+/// 2: 连接失败, data为原因
+/// 3: 断开连接, data为原因
+/// 7: 收到数据
+/// 8: 一条消息发送失败, data为原因, 例如开启 framing 时消息超过 max_frame_size
+/// 与 rust_net_ws_get_message 的 message_type 一致
+struct SocketMessageData {
+  int32_t message_type;
+  const uint8_t *data;
+  uintptr_t len;
+  uintptr_t cap;
+};
+
+/// KCP会话统计, 时间单位为毫秒
+struct KcpStats {
+  uint32_t srtt;
+  uint32_t rttvar;
+  uint32_t rto;
+  uint32_t wait_send;
+  uint32_t retransmits;
+};
+
+/// UDP事件
+/// message_type:
+/// 0: 没有数据报
+/// 3: 接收出错, data为原因, 之后不会再收到数据报
+/// 7: 收到数据报, remote_addr为来源地址
+struct UdpMessageData {
+  int32_t message_type;
+  const uint8_t *data;
+  uintptr_t len;
+  uintptr_t cap;
+  char *remote_addr;
+};
+
 struct WsMessageData {
   int32_t message_type;
   const uint8_t *data;
@@ -27,36 +179,332 @@ struct WsMessageData {
   uintptr_t cap;
 };
 
+/// 服务器事件
+/// event_type:
+/// 0: 没有事件
+/// 1: 新连接, data为客户端地址
+/// 3: 连接断开, data为原因
+/// 6: 收到文本消息
+/// 7: 收到二进制消息
+/// 与 rust_net_ws_get_message 的 message_type 一致
+struct WsServerEvent {
+  int32_t event_type;
+  uint64_t connection_id;
+  const uint8_t *data;
+  uintptr_t len;
+  uintptr_t cap;
+};
+
 extern "C" {
 
 TokioContext *rust_net_tokio_new(uint32_t thread_count);
 
+/// # Safety
+/// handler 必须是 rust_net_tokio_new 返回的指针, 释放后不能再使用
 void rust_net_tokio_free(TokioContext *handler);
 
+/// 获取该tokio context下所有http请求与websocket连接的统计
+NetStats rust_net_tokio_get_stats(TokioContext *context);
+
+/// 设置该tokio context下所有http请求的全局限速(字节/秒) 0表示不限速
+/// 可以在请求进行中调整
+void rust_net_tokio_set_bandwidth_limit(TokioContext *context,
+                                        uint64_t upload_bytes_per_sec,
+                                        uint64_t download_bytes_per_sec);
+
 ClientContext *rust_net_http_client_new(bool brotli, bool cookie_store);
 
+/// 使用json配置创建客户端, 配置错误时返回空指针
+/// {
+///     "brotli": true,
+///     "cookie_store": true,
+///     "http1_only": false,
+///     "http2_only": false,
+///     "http2_keep_alive_interval_ms": 0,
+///     "http2_keep_alive_timeout_ms": 0,
+///     "http2_keep_alive_while_idle": false,
+///     "http2_initial_stream_window_size": 0,
+///     "http2_initial_connection_window_size": 0,
+///     "http2_adaptive_window": false,
+///     "http3": false,
+///     "http3_timeout_ms": 3000,
+///     "root_certificates": [],
+///     "param_encoding": "form",
+///     "dns": {
+///         "hosts": {"api.example.com": ["10.0.0.1"]},
+///         "cache_ttl_ms": 60000,
+///         "ip_family": "auto",
+///         "doh": {
+///             "url": "https://1.1.1.1/dns-query",
+///             "bootstrap_ips": [],
+///             "timeout_ms": 5000,
+///             "fallback_system": true,
+///             "root_certificates": []
+///         }
+///     }
+/// }
+/// HTTP客户端不支持 happy_eyeballs_delay_ms, 双栈时固定在首选地址族连接300ms未成功后尝试另一地址族
+///
+/// # Safety
+/// config 必须是以\0结尾的UTF-8字符串
+ClientContext *rust_net_http_client_new_with_config(const char *config);
+
+/// # Safety
+/// handler 必须是 rust_net_http_client_new 或 rust_net_http_client_new_with_config 返回的指针, 释放后不能再使用
 void rust_net_http_client_free(ClientContext *handler);
 
+/// # Safety
+/// key、value 必须是以\0结尾的UTF-8字符串
 void rust_net_http_add_header(ClientContext *context, const char *key, const char *value);
 
 void rust_net_http_clear_header(ClientContext *context);
 
+/// 设置query参数, 替换所有同名参数
+///
+/// # Safety
+/// key、value 必须是以\0结尾的UTF-8字符串
 void rust_net_http_add_param(ClientContext *context, const char *key, const char *value);
 
+/// 追加query参数, 同名参数可以出现多次, 例如 ?id=1&id=2
+/// 参数按添加的顺序拼接
+///
+/// # Safety
+/// key、value 必须是以\0结尾的UTF-8字符串
+void rust_net_http_append_param(ClientContext *context, const char *key, const char *value);
+
+/// 删除该名称的所有query参数
+///
+/// # Safety
+/// key 必须是以\0结尾的UTF-8字符串
+void rust_net_http_remove_param(ClientContext *context, const char *key);
+
+/// 设置query参数编码
+/// form: application/x-www-form-urlencoded, 空格编码为+ (默认)
+/// rfc3986: 只保留非保留字符, 空格编码为%20
+/// raw: 参数已由调用方编码, 原样拼接
+///
+/// # Safety
+/// encoding 必须是以\0结尾的UTF-8字符串
+bool rust_net_http_set_param_encoding(ClientContext *context, const char *encoding);
+
 void rust_net_http_set_clear_expires_enabled(ClientContext *context, bool value);
 
 void rust_net_http_clear_param(ClientContext *context);
 
+/// 设置并发上限
+/// max_concurrency 全局最大并发数 0表示不限制
+/// max_per_host 单个host最大并发数 0表示不限制
+void rust_net_http_set_concurrency_limit(ClientContext *client_context,
+                                         uint32_t max_concurrency,
+                                         uint32_t max_per_host);
+
+/// 设置该客户端的限速(字节/秒) 0表示不限速, 可以在请求进行中调整
+void rust_net_http_set_bandwidth_limit(ClientContext *client_context,
+                                       uint64_t upload_bytes_per_sec,
+                                       uint64_t download_bytes_per_sec);
+
+/// 调整单个请求的限速(字节/秒) 0表示不限速
+void rust_net_http_set_request_bandwidth_limit(ClientContext *client_context,
+                                               uint64_t key,
+                                               uint64_t upload_bytes_per_sec,
+                                               uint64_t download_bytes_per_sec);
+
+/// 添加请求拦截器, 按添加顺序执行, 可以修改请求的method、url、请求头及body
+/// 只对之后发起的请求生效
+void rust_net_http_add_request_interceptor(ClientContext *client_context,
+                                           RequestInterceptor callback,
+                                           void *user_data);
+
+/// 添加响应拦截器, 按添加顺序执行, 可以查看或修改响应的状态码、响应头及body
+/// 只对之后发起的请求生效
+void rust_net_http_add_response_interceptor(ClientContext *client_context,
+                                            ResponseInterceptor callback,
+                                            void *user_data);
+
+/// 移除所有拦截器
+void rust_net_http_clear_interceptors(ClientContext *client_context);
+
+/// 设置请求签名, config 为空时取消签名, 配置错误时返回false
+/// {
+///     "key_id": "key-1",
+///     "secret": "******",
+///     "encoding": "hex",
+///     "signature_header": "X-Signature",
+///     "key_id_header": "X-Key-Id",
+///     "timestamp_header": "X-Timestamp",
+///     "timestamp_unit": "seconds",
+///     "nonce_header": "X-Nonce",
+///     "signed_headers": ["X-Device-Id"]
+/// }
+/// 只对之后发起的请求生效, 签名规则参考 SignerConfig
+///
+/// # Safety
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+bool rust_net_http_set_signer(ClientContext *client_context, const char *config);
+
+/// 设置Bearer token认证, config 为空时关闭认证, 参数为json:
+/// {
+///     "access_token": "xxx",
+///     "refresh_token": "yyy",
+///     "refresh_url": "https://example.com/oauth/token",
+///     "refresh_method": "POST",
+///     "refresh_body": "{\"refresh_token\": \"{refresh_token}\"}",
+///     "refresh_content_type": "application/json",
+///     "token_field": "data.access_token",
+///     "refresh_token_field": "data.refresh_token"
+/// }
+/// 请求携带 Authorization: Bearer <access_token>, 响应401时刷新token并重放请求
+/// 刷新期间发起的请求排队等待, 刷新失败时这些请求一起失败
+/// 流式body的请求无法重放, 直接返回401响应
+///
+/// # Safety
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+bool rust_net_http_set_auth(ClientContext *client_context, const char *config);
+
+/// 设置刷新token的回调, 设置后不再请求 refresh_url
+/// 需要先调用 rust_net_http_set_auth, 未启用认证时返回false
+bool rust_net_http_set_auth_refresh_callback(ClientContext *client_context,
+                                             AuthRefreshCallback callback,
+                                             void *user_data);
+
+/// 手动更新access token, 例如重新登录之后
+///
+/// # Safety
+/// token 必须是以\0结尾的UTF-8字符串
+bool rust_net_http_set_auth_token(ClientContext *client_context, const char *token);
+
+/// 当前的access token, 刷新之后可以用来持久化
+/// 未启用认证时返回空指针
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_http_get_auth_token(ClientContext *client_context);
+
+/// 设置Basic/Digest认证, config 为空时清除, 参数为json:
+/// {
+///     "username": "admin",
+///     "password": "xxx",
+///     "scheme": "auto"
+/// }
+/// scheme:
+/// auto: 先不带认证发送, 收到401时按质询使用Basic或Digest并重放请求
+/// basic: 直接携带Basic认证头
+/// digest: 只响应Digest质询(MD5、SHA-256, qop=auth)
+/// 单个请求也可以在请求参数的 credentials 中设置, 优先于客户端的设置
+/// 设置后不再使用 rust_net_http_set_auth 的Bearer认证
+///
+/// # Safety
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+bool rust_net_http_set_credentials(ClientContext *client_context, const char *config);
+
+/// 设置模拟传输, config 为空时恢复正常发送, 参数为json:
+/// {
+///     "mode": "replay",
+///     "path": "/tmp/interactions.jsonl",
+///     "match_method": true,
+///     "match_query": true,
+///     "ignore_params": ["timestamp"],
+///     "match_body": false
+/// }
+/// record: 正常发送请求, 将请求与响应写入 path (会清空原有内容)
+/// replay: 不发送请求, 从 path 中返回匹配的响应, 没有匹配时请求失败
+/// 文件每行一个json, 响应body为base64, 可以手动编辑
+/// 回放的结果同样通过 rust_net_http_get_request_state、rust_net_http_get_request_response 等获取
+///
+/// # Safety
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+bool rust_net_http_set_mock_transport(ClientContext *client_context,
+                                      const char *config);
+
+/// 开启HAR抓包, config 为空时关闭并丢弃已记录的请求, 参数为json:
+/// {
+///     "include_bodies": false,
+///     "max_body_size": 1048576,
+///     "max_entries": 1000,
+///     "path": "",
+///     "save_interval_ms": 0,
+///     "redact_headers": ["X-Signature"]
+/// }
+/// 记录实际发送的请求与拦截器修改之前的响应, authorization、cookie 等请求头会被脱敏
+/// path 不为空时每个请求完成后自动写入(至少间隔 save_interval_ms), 否则通过 rust_net_http_save_har 写入
+///
+/// # Safety
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+bool rust_net_http_set_har_capture(ClientContext *client_context,
+                                   const char *config);
+
+/// 将已记录的请求写入HAR 1.2文件, 未开启抓包或写入失败时返回false
+///
+/// # Safety
+/// path 必须是以\0结尾的UTF-8字符串
+bool rust_net_http_save_har(ClientContext *client_context, const char *path);
+
+/// 已记录请求的HAR 1.2 json, 未开启抓包时返回空指针
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_http_get_har(ClientContext *client_context);
+
+/// 丢弃已记录的请求
+void rust_net_http_clear_har(ClientContext *client_context);
+
+/// 设置自定义DNS解析回调, callback 为空时恢复系统解析
+/// 静态host映射优先于解析回调, 设置后会清空DNS缓存
+void rust_net_http_set_dns_resolver(ClientContext *client_context,
+                                    DnsResolveCallback callback,
+                                    void *user_data);
+
+/// 清空DNS缓存
+void rust_net_http_clear_dns_cache(ClientContext *client_context);
+
+/// # Safety
+/// url 必须是以\0结尾的UTF-8字符串
+/// data 必须指向至少 length 字节的可读内存
 uint64_t rust_net_http_post(TokioContext *tokio_context,
                             ClientContext *client_context,
                             const char *url,
                             const uint8_t *data,
                             uintptr_t length);
 
+/// # Safety
+/// url 必须是以\0结尾的UTF-8字符串
 uint64_t rust_net_http_get(TokioContext *tokio_context,
                            ClientContext *client_context,
                            const char *url);
 
+/// options 为json字符串, 可以为空
+/// {
+///     "priority": 0,
+///     "upload_bytes_per_sec": 0,
+///     "download_bytes_per_sec": 0,
+///     "credentials": {"username": "admin", "password": "xxx", "scheme": "auto"},
+///     "params": [["id", "1"], ["id", "2"]],
+///     "param_encoding": "form"
+/// }
+///
+/// # Safety
+/// url、options 必须是以\0结尾的UTF-8字符串
+/// data 可以为空, 不为空时必须指向至少 length 字节的可读内存
+uint64_t rust_net_http_post_with_options(TokioContext *tokio_context,
+                                         ClientContext *client_context,
+                                         const char *url,
+                                         const uint8_t *data,
+                                         uintptr_t length,
+                                         const char *options);
+
+/// options 为json字符串, 可以为空
+/// {
+///     "priority": 0,
+///     "upload_bytes_per_sec": 0,
+///     "download_bytes_per_sec": 0,
+///     "credentials": {"username": "admin", "password": "xxx", "scheme": "auto"},
+///     "params": [["id", "1"], ["id", "2"]],
+///     "param_encoding": "form"
+/// }
+///
+/// # Safety
+/// url、options 必须是以\0结尾的UTF-8字符串
+uint64_t rust_net_http_get_with_options(TokioContext *tokio_context,
+                                        ClientContext *client_context,
+                                        const char *url,
+                                        const char *options);
+
 void rust_net_http_remove_request(ClientContext *client_context, uint64_t key);
 
 /// 获取请求状态
@@ -82,20 +530,481 @@ char *rust_net_http_get_response_cookies(ClientContext *client_context, uint64_t
 /// 使用完成之后 调用 rust_net_http_free_string 释放内存
 char *rust_net_http_get_response_headers(ClientContext *client_context, uint64_t key);
 
+/// 获取实际连接的远端地址, 例如 "1.2.3.4:443"、"[::1]:8080"
+/// 请求未完成或地址未知时返回空指针
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_http_get_remote_addr(ClientContext *client_context, uint64_t key);
+
+/// 获取请求各阶段耗时
+/// 请求未完成或不存在时各项为-1
+RequestTimings rust_net_http_get_request_timings(ClientContext *client_context, uint64_t key);
+
+/// 获取该客户端的http统计, websocket相关项为0
+NetStats rust_net_http_get_stats(ClientContext *client_context);
+
+/// # Safety
+/// s 可以为空, 不为空时必须是本库返回的字符串, 且只能释放一次
 void rust_net_http_free_string(char *s);
 
 void rust_net_http_free_request_response(RequestResponse resp);
 
+/// 请求的key, 与 rust_net_http_get 等返回的key一致
+uint64_t rust_net_http_intercepted_request_key(InterceptedRequest *request);
+
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_http_intercepted_request_get_method(InterceptedRequest *request);
+
+/// # Safety
+/// method 必须是以\0结尾的UTF-8字符串
+bool rust_net_http_intercepted_request_set_method(InterceptedRequest *request, const char *method);
+
+/// 完整的url, 包含query参数
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_http_intercepted_request_get_url(InterceptedRequest *request);
+
+/// # Safety
+/// url 必须是以\0结尾的UTF-8字符串
+bool rust_net_http_intercepted_request_set_url(InterceptedRequest *request, const char *url);
+
+/// 请求头json
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_http_intercepted_request_get_headers(InterceptedRequest *request);
+
+/// 设置请求头, 覆盖同名的请求头
+///
+/// # Safety
+/// key、value 必须是以\0结尾的UTF-8字符串
+bool rust_net_http_intercepted_request_set_header(InterceptedRequest *request,
+                                                  const char *key,
+                                                  const char *value);
+
+/// # Safety
+/// key 必须是以\0结尾的UTF-8字符串
+void rust_net_http_intercepted_request_remove_header(InterceptedRequest *request, const char *key);
+
+/// 请求body, 返回的指针只在回调期间有效, 无需释放
+const uint8_t *rust_net_http_intercepted_request_get_body(InterceptedRequest *request,
+                                                          uintptr_t *len);
+
+/// # Safety
+/// data 可以为空, 不为空时必须指向至少 len 字节的可读内存
+void rust_net_http_intercepted_request_set_body(InterceptedRequest *request,
+                                                const uint8_t *data,
+                                                uintptr_t len);
+
+/// 请求的key, 与 rust_net_http_get 等返回的key一致
+uint64_t rust_net_http_intercepted_response_key(InterceptedResponse *response);
+
+uint16_t rust_net_http_intercepted_response_get_status(InterceptedResponse *response);
+
+void rust_net_http_intercepted_response_set_status(InterceptedResponse *response, uint16_t status);
+
+/// 响应头json
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_http_intercepted_response_get_headers(InterceptedResponse *response);
+
+/// 设置响应头, 覆盖同名的响应头
+///
+/// # Safety
+/// key、value 必须是以\0结尾的UTF-8字符串
+bool rust_net_http_intercepted_response_set_header(InterceptedResponse *response,
+                                                   const char *key,
+                                                   const char *value);
+
+/// # Safety
+/// key 必须是以\0结尾的UTF-8字符串
+void rust_net_http_intercepted_response_remove_header(InterceptedResponse *response,
+                                                      const char *key);
+
+/// 响应body, 返回的指针只在回调期间有效, 无需释放
+const uint8_t *rust_net_http_intercepted_response_get_body(InterceptedResponse *response,
+                                                           uintptr_t *len);
+
+/// # Safety
+/// data 可以为空, 不为空时必须指向至少 len 字节的可读内存
+void rust_net_http_intercepted_response_set_body(InterceptedResponse *response,
+                                                 const uint8_t *data,
+                                                 uintptr_t len);
+
+/// 设置日志回调, callback 为空时关闭日志
+/// level: 0 关闭, 1 error, 2 warn, 3 info, 4 debug, 5 trace
+/// 回调可能在任意线程中被调用, 回调中不能再调用日志设置相关的接口
+void rust_net_set_log_callback(int32_t level, LogCallback callback, void *user_data);
+
+/// 按模块设置日志等级, 覆盖 rust_net_set_log_callback 中的等级
+/// module 为模块前缀, 例如 rust_net::websocket、reqwest、hyper
+/// level 小于0时移除该模块的设置
+///
+/// # Safety
+/// module 必须是以\0结尾的UTF-8字符串
+void rust_net_set_log_module_level(const char *module, int32_t level);
+
+/// 启动HTTP服务器, addr 例如 "0.0.0.0:8080", 端口为0时由系统分配
+/// 绑定失败时返回空指针
+///
+/// # Safety
+/// addr 必须是以\0结尾的UTF-8字符串
+HttpServerContext *rust_net_http_server_new(TokioContext *context, const char *addr);
+
+/// 停止服务器, 未响应的请求返回503
+///
+/// # Safety
+/// server 必须是 rust_net_http_server_new 返回的指针, 释放后不能再使用
+void rust_net_http_server_free(HttpServerContext *server);
+
+/// 实际监听的地址, 例如 "0.0.0.0:8080"
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_http_server_local_addr(HttpServerContext *server);
+
+/// 将url前缀映射到本地目录, 该前缀下的GET/HEAD请求直接返回文件, 不经过宿主
+/// 例如 prefix "/static" dir "./web", 请求目录时返回其中的 index.html
+/// 目录不存在时返回false
+///
+/// # Safety
+/// prefix、dir 必须是以\0结尾的UTF-8字符串
+bool rust_net_http_server_serve_dir(HttpServerContext *server,
+                                    const char *prefix,
+                                    const char *dir);
+
+/// 取出一个待处理的请求, 返回请求id, 没有请求时返回0
+/// 取出的请求需要调用 rust_net_http_server_respond 响应, 否则60秒后返回504
+uint64_t rust_net_http_server_poll_request(HttpServerContext *server);
+
+/// 请求的method
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_http_server_get_method(HttpServerContext *server, uint64_t id);
+
+/// 请求的path, 不包含query
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_http_server_get_path(HttpServerContext *server, uint64_t id);
+
+/// 请求的query, 不包含?, 没有query时为空字符串
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_http_server_get_query(HttpServerContext *server, uint64_t id);
+
+/// 请求头json, 例如 {"host":"example.com","cookie":["a=1","b=2"]}
+/// 名称为小写, 只出现一次的请求头值为字符串, 重复出现的请求头值为按顺序排列的数组
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_http_server_get_headers(HttpServerContext *server,
+                                       uint64_t id);
+
+/// 客户端地址, 例如 "192.168.1.2:53000"
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_http_server_get_remote_addr(HttpServerContext *server, uint64_t id);
+
+/// 请求body的副本, 请求不存在时 data 为空
+/// 使用完成之后 调用 rust_net_http_server_free_body 释放内存
+HttpServerBody rust_net_http_server_get_body(HttpServerContext *server, uint64_t id);
+
+void rust_net_http_server_free_body(HttpServerBody body);
+
+/// 响应请求, headers 为json字符串, 可以为空
+/// {"Content-Type": "text/html", "Set-Cookie": ["a=1; Path=/", "b=2; Path=/"]}
+/// 同名的多个header以数组传入
+/// 请求不存在(已响应或超时)或 status 不是有效的状态码时返回false
+///
+/// # Safety
+/// headers 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+/// body 可以为空, 不为空时必须指向至少 len 字节的可读内存
+bool rust_net_http_server_respond(HttpServerContext *server,
+                                  uint64_t id,
+                                  uint16_t status,
+                                  const char *headers,
+                                  const uint8_t *body,
+                                  uintptr_t len);
+
+/// 连接 host:port, config 可以为空
+/// {
+///     "dns": {
+///         "hosts": {"game.example.com": ["10.0.0.1"]},
+///         "cache_ttl_ms": 0,
+///         "ip_family": "auto"
+///     },
+///     "happy_eyeballs_delay_ms": 250,
+///     "connect_timeout_ms": 5000,
+///     "nodelay": true,
+///     "keepalive_ms": 30000,
+///     "tls": {
+///         "server_name": "game.example.com",
+///         "root_certificates": [],
+///         "alpn_protocols": ["game/1"]
+///     },
+///     "framing": {
+///         "header_size": 4,
+///         "byte_order": "big",
+///         "length_includes_header": false,
+///         "max_frame_size": 8388608
+///     }
+/// }
+/// tls.server_name 为空时使用host, root_certificates 为PEM格式的自定义根证书, 与库内置的 Mozilla 根证书(webpki-roots)一起使用, 不读取操作系统的证书库
+/// 不需要TLS时不传 tls
+/// framing 开启后每个接收事件恰好是一条完整消息, rust_net_tcp_send 的每段数据作为一条消息发送
+/// byte_order: big、little
+/// 连接结果通过 rust_net_tcp_get_message 获取
+/// dns 配置相同的连接共享解析缓存
+/// config 解析失败或 dns、framing 配置无效时返回空指针
+///
+/// # Safety
+/// host 必须是以\0结尾的UTF-8字符串
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+TcpContext *rust_net_tcp_connect(TokioContext *context,
+                                 const char *host,
+                                 uint16_t port,
+                                 const char *config);
+
+/// # Safety
+/// data 可以为空, 不为空时必须指向至少 length 字节的可读内存
+void rust_net_tcp_send(TcpContext *tcp_context, const uint8_t *data, uintptr_t length);
+
+/// 取出一个事件, 没有事件时 message_type 为0
+/// 使用完成之后 调用 rust_net_tcp_free_message 释放内存
+SocketMessageData rust_net_tcp_get_message(TcpContext *tcp_context);
+
+/// TLS握手协商出的ALPN协议, 未使用TLS或未协商时返回空指针
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_tcp_get_alpn_protocol(TcpContext *tcp_context);
+
+void rust_net_tcp_close(TcpContext *tcp_context);
+
+/// # Safety
+/// tcp_context 必须是 rust_net_tcp_connect 或 rust_net_tcp_listener_accept 返回的指针, 释放后不能再使用
+void rust_net_tcp_free(TcpContext *tcp_context);
+
+void rust_net_tcp_free_message(SocketMessageData message);
+
+/// 与 addr(例如 "10.0.0.1:4000") 建立KCP会话, conv 为会话ID, 两端必须一致, config 可以为空
+/// {
+///     "local_addr": "0.0.0.0:4001",
+///     "nodelay": 1,
+///     "interval_ms": 10,
+///     "resend": 2,
+///     "no_congestion_window": true,
+///     "send_window": 128,
+///     "recv_window": 128,
+///     "mtu": 1400,
+///     "dead_link": 20
+/// }
+/// 以上为常用的极速模式, 默认为普通模式 nodelay 0、interval_ms 100、resend 0
+/// 绑定成功后收到连接成功事件, 重传达到 dead_link 次后收到断开事件
+/// 事件与 rust_net_tcp_get_message 一致
+/// config 解析失败、mtu 小于50或超过UDP数据报的最大长度时返回空指针
+///
+/// # Safety
+/// addr 必须是以\0结尾的UTF-8字符串
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+KcpContext *rust_net_kcp_connect(TokioContext *context,
+                                 const char *addr,
+                                 uint32_t conv,
+                                 const char *config);
+
+/// 发送一条消息, 对端收到的也是一条完整消息
+/// 消息分片数必须小于128, 即默认mtu下不超过约170KB
+/// 消息过大或会话已断开时返回false, 消息不会发送
+/// 返回true表示消息已进入发送队列, 之后KCP拒绝发送时收到发送失败事件(8), 会话不受影响
+///
+/// # Safety
+/// data 可以为空, 不为空时必须指向至少 length 字节的可读内存
+bool rust_net_kcp_send(KcpContext *kcp_context,
+                       const uint8_t *data,
+                       uintptr_t length);
+
+/// 取出一个事件, 没有事件时 message_type 为0
+/// 使用完成之后 调用 rust_net_kcp_free_message 释放内存
+SocketMessageData rust_net_kcp_get_message(KcpContext *kcp_context);
+
+KcpStats rust_net_kcp_get_stats(KcpContext *kcp_context);
+
+void rust_net_kcp_close(KcpContext *kcp_context);
+
+/// # Safety
+/// kcp_context 必须是 rust_net_kcp_connect 返回的指针, 释放后不能再使用
+void rust_net_kcp_free(KcpContext *kcp_context);
+
+void rust_net_kcp_free_message(SocketMessageData message);
+
+/// 监听 addr, 例如 "0.0.0.0:9000", 端口为0时由系统分配, config 可以为空
+/// {
+///     "nodelay": true,
+///     "keepalive_ms": 30000,
+///     "framing": {"header_size": 4, "byte_order": "big"}
+/// }
+/// 绑定或配置失败时返回空指针
+///
+/// # Safety
+/// addr 必须是以\0结尾的UTF-8字符串
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+TcpListenerContext *rust_net_tcp_listen(TokioContext *context,
+                                        const char *addr,
+                                        const char *config);
+
+/// 取出一个新接受的连接, 没有时返回空指针
+/// 返回的连接与 rust_net_tcp_connect 的返回值用法一致, 第一个事件为连接成功, data为客户端地址
+/// 使用完成之后 调用 rust_net_tcp_free 释放
+TcpContext *rust_net_tcp_listener_accept(TcpListenerContext *listener);
+
+/// 实际监听的地址
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_tcp_listener_local_addr(TcpListenerContext *listener);
+
+/// 停止监听, 未取走的连接会被断开, 已取走的连接不受影响
+///
+/// # Safety
+/// listener 必须是 rust_net_tcp_listen 返回的指针, 释放后不能再使用
+void rust_net_tcp_listener_free(TcpListenerContext *listener);
+
+/// 绑定本地地址, addr 例如 "0.0.0.0:9000", 端口为0时由系统分配, config 可以为空
+/// {
+///     "broadcast": false,
+///     "reuse_address": false,
+///     "recv_buffer_size": 0,
+///     "send_buffer_size": 0,
+///     "multicast_groups": ["239.255.0.1"],
+///     "multicast_interface": "192.168.1.10",
+///     "multicast_interface_index": 0,
+///     "multicast_loop": true,
+///     "multicast_ttl": 1
+/// }
+/// 绑定或配置失败时返回空指针
+///
+/// # Safety
+/// addr 必须是以\0结尾的UTF-8字符串
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+UdpContext *rust_net_udp_bind(TokioContext *context,
+                              const char *addr,
+                              const char *config);
+
+/// 设置默认的目标地址, 之后可以使用 rust_net_udp_send 发送, 并且只接收该地址的数据报
+///
+/// # Safety
+/// addr 必须是以\0结尾的UTF-8字符串
+bool rust_net_udp_connect(UdpContext *udp_context,
+                          const char *addr);
+
+/// 发送到 rust_net_udp_connect 设置的地址
+/// 发送缓冲区已满或出错时返回false
+///
+/// # Safety
+/// data 可以为空, 不为空时必须指向至少 length 字节的可读内存
+bool rust_net_udp_send(UdpContext *udp_context, const uint8_t *data, uintptr_t length);
+
+/// 发送到指定地址, addr 例如 "192.168.1.255:9000"
+/// 发送缓冲区已满或出错时返回false
+///
+/// # Safety
+/// addr 必须是以\0结尾的UTF-8字符串
+/// data 可以为空, 不为空时必须指向至少 length 字节的可读内存
+bool rust_net_udp_send_to(UdpContext *udp_context,
+                          const char *addr,
+                          const uint8_t *data,
+                          uintptr_t length);
+
+/// 实际绑定的地址
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_udp_local_addr(UdpContext *udp_context);
+
+/// 取出一个数据报, 没有数据报时 message_type 为0
+/// 使用完成之后 调用 rust_net_udp_free_message 释放内存
+UdpMessageData rust_net_udp_get_message(UdpContext *udp_context);
+
+/// # Safety
+/// udp_context 必须是 rust_net_udp_bind 返回的指针, 释放后不能再使用
+void rust_net_udp_free(UdpContext *udp_context);
+
+void rust_net_udp_free_message(UdpMessageData message);
+
+/// # Safety
+/// url、cookies 必须是以\0结尾的UTF-8字符串
 WsContext *rust_net_ws_connect(TokioContext *context, const char *url, const char *cookies);
 
+/// 使用json配置连接, config 可以为空
+/// {
+///     "dns": {
+///         "hosts": {"ws.example.com": ["10.0.0.1"]},
+///         "cache_ttl_ms": 0,
+///         "ip_family": "auto"
+///     },
+///     "happy_eyeballs_delay_ms": 250
+/// }
+/// ip_family: auto、prefer_ipv4、prefer_ipv6、ipv4_only、ipv6_only
+/// resolver 为自定义DNS解析回调, 可以为空, 参考 rust_net_http_set_dns_resolver
+/// dns 配置与 resolver、user_data 都相同的连接共享解析缓存
+/// config 解析失败或 dns 配置无效时返回空指针
+///
+/// # Safety
+/// url、cookies 必须是以\0结尾的UTF-8字符串
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+WsContext *rust_net_ws_connect_with_config(TokioContext *context,
+                                           const char *url,
+                                           const char *cookies,
+                                           const char *config,
+                                           DnsResolveCallback resolver,
+                                           void *user_data);
+
+/// # Safety
+/// data 可以为空, 不为空时必须指向至少 length 字节的可读内存
 void rust_net_ws_send(WsContext *ws_context, const uint8_t *data, uintptr_t length);
 
 WsMessageData rust_net_ws_get_message(WsContext *ws_context);
 
 void rust_net_ws_close(WsContext *ws_context);
 
+/// # Safety
+/// ws_context 必须是 rust_net_ws_connect 或 rust_net_ws_connect_with_config 返回的指针, 释放后不能再使用
 void rust_net_ws_free(WsContext *ws_context);
 
 void rust_net_ws_free_message(WsMessageData resp);
 
+/// 启动WebSocket服务器, addr 例如 "0.0.0.0:9000", 端口为0时由系统分配
+/// 绑定失败时返回空指针
+///
+/// # Safety
+/// addr 必须是以\0结尾的UTF-8字符串
+WsServerContext *rust_net_ws_server_new(TokioContext *context, const char *addr);
+
+/// 停止监听并断开所有连接
+///
+/// # Safety
+/// server 必须是 rust_net_ws_server_new 返回的指针, 释放后不能再使用
+void rust_net_ws_server_free(WsServerContext *server);
+
+/// 实际监听的地址, 例如 "0.0.0.0:9000"
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+char *rust_net_ws_server_local_addr(WsServerContext *server);
+
+/// 取出一个事件, 没有事件时 event_type 为0
+/// 使用完成之后 调用 rust_net_ws_server_free_event 释放内存
+WsServerEvent rust_net_ws_server_get_event(WsServerContext *server);
+
+/// 向一个连接发送消息, text 为true时以文本消息发送(需要是UTF-8)
+/// 连接不存在时返回false
+///
+/// # Safety
+/// data 必须指向至少 length 字节的可读内存
+bool rust_net_ws_server_send(WsServerContext *server,
+                             uint64_t connection_id,
+                             const uint8_t *data,
+                             uintptr_t length,
+                             bool text);
+
+/// 向所有连接发送消息, 返回发送的连接数
+///
+/// # Safety
+/// data 必须指向至少 length 字节的可读内存
+uint32_t rust_net_ws_server_broadcast(WsServerContext *server,
+                                      const uint8_t *data,
+                                      uintptr_t length,
+                                      bool text);
+
+/// 断开一个连接, reason 可以为空, 连接不存在时返回false
+/// 断开后会收到该连接的断开事件
+///
+/// # Safety
+/// reason 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+bool rust_net_ws_server_kick(WsServerContext *server, uint64_t connection_id, const char *reason);
+
+/// 当前的连接数
+uint32_t rust_net_ws_server_connection_count(WsServerContext *server);
+
+void rust_net_ws_server_free_event(WsServerEvent event);
+
 } // extern "C"
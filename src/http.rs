@@ -1,21 +1,149 @@
+mod auth;
+mod config;
+mod credentials;
+pub(crate) mod dns;
+#[cfg(feature = "doh")]
+mod doh;
+mod har;
+#[cfg(feature = "http3")]
+mod http3;
+mod interceptor;
+mod mock;
+mod query;
+mod scheduler;
+mod signer;
+mod timing;
+pub(crate) mod tls;
+
+use crate::stats::{FailKind, NetCounters, NetStats};
+use crate::throttle::{throttle, Bandwidth};
 use crate::TokioContext;
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use reqwest::{Response, Version};
+use auth::{Auth, AuthConfig, AuthRefreshCallback};
+use config::ClientConfig;
+use credentials::{Credentials, CredentialsConfig};
+use dns::{DnsResolveCallback, Resolver};
+use har::{HarCapture, HarConfig, HarRequest};
+#[cfg(feature = "http3")]
+use http3::Http3Fallback;
+use interceptor::{Interceptors, RequestInterceptor, ResponseInterceptor};
+use mock::{MockConfig, MockTransport, RecordedRequest};
+use query::{append_query, ParamEncoding, Params};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use reqwest::{Method, Request, Response, StatusCode, Url, Version};
+use scheduler::Scheduler;
+use serde::Deserialize;
+use signer::{Signer, SignerConfig};
 use std::collections::HashMap;
-use std::ffi::{CStr, CString};
+use std::ffi::{c_void, CStr, CString};
+use std::future::Future;
+use std::net::SocketAddr;
 use std::os::raw::c_char;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::{Duration, Instant};
+pub use timing::RequestTimings;
+use timing::{ConnectionTracker, TimingRecorder};
 use tokio::sync::OnceCell;
 
 /// client context
 pub struct ClientContext {
     client: reqwest::Client,
-    items: slab::Slab<Arc<OnceCell<RespResult>>>,
+    items: slab::Slab<Arc<RequestItem>>,
     headers: HashMap<String, String>,
-    params: HashMap<String, String>,
+    params: Params,
+    param_encoding: ParamEncoding,
     last_clear_time: Instant,
     clear_expires_enabled: bool,
+    scheduler: Arc<Scheduler>,
+    resolver: Resolver,
+    bandwidth: Arc<Bandwidth>,
+    interceptors: Arc<Interceptors>,
+    signer: Option<Arc<Signer>>,
+    auth: Option<Arc<Auth>>,
+    credentials: Option<Arc<Credentials>>,
+    transport: Option<Arc<MockTransport>>,
+    har: Option<Arc<HarCapture>>,
+    #[cfg(feature = "http3")]
+    http3: Option<Arc<Http3Fallback>>,
+    stats: Arc<NetCounters>,
+    connections: Arc<ConnectionTracker>,
+}
+
+/// 单个请求执行期间共享的状态
+struct RequestContext {
+    key: u64,
+    recorder: Arc<TimingRecorder>,
+    // 判断请求是否复用了客户端已有的连接
+    connections: Arc<ConnectionTracker>,
+    // 同时计入客户端与TokioContext的统计
+    stats: [Arc<NetCounters>; 2],
+    // 请求、客户端、TokioContext三级限速
+    bandwidth: [Arc<Bandwidth>; 3],
+    interceptors: Arc<Interceptors>,
+    signer: Option<Arc<Signer>>,
+    auth: Option<Arc<Auth>>,
+    // 请求参数中的用户名密码优先于客户端的设置
+    credentials: Option<Arc<Credentials>>,
+    transport: Option<Arc<MockTransport>>,
+    // 录制模式下发送的请求, 完成时与响应一起写入记录文件
+    recorded_request: OnceLock<RecordedRequest>,
+    har: Option<Arc<HarCapture>>,
+    // 最后一次发送的请求, 认证重放时会被替换
+    har_request: Mutex<Option<HarRequest>>,
+}
+
+/// 请求结果, 以及请求级别的限速
+struct RequestItem {
+    result: OnceCell<RespResult>,
+    bandwidth: Arc<Bandwidth>,
+}
+
+impl RequestContext {
+    fn stats(&self, f: impl Fn(&NetCounters)) {
+        for stats in &self.stats {
+            f(stats);
+        }
+    }
+}
+
+impl Drop for RequestContext {
+    fn drop(&mut self) {
+        // 请求完成或被取消
+        self.stats(|stats| stats.request_finished());
+    }
+}
+
+/// 单个请求的可选参数, 以json形式传入
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct RequestOptions {
+    // 优先级 数值越大越先执行
+    priority: i32,
+    // 该请求的上传限速(字节/秒) 0表示不限速
+    upload_bytes_per_sec: u64,
+    // 该请求的下载限速(字节/秒) 0表示不限速
+    download_bytes_per_sec: u64,
+    // 该请求的Basic/Digest认证
+    credentials: Option<CredentialsConfig>,
+    // 该请求的query参数, 按顺序追加在客户端参数之后, 例如 [["id", "1"], ["id", "2"]]
+    params: Vec<(String, String)>,
+    // 该请求的query参数编码, 为空时使用客户端的设置
+    param_encoding: Option<ParamEncoding>,
+}
+
+impl RequestOptions {
+    unsafe fn from_ptr(options: *const c_char) -> Self {
+        if options.is_null() {
+            return Self::default();
+        }
+        let options = CStr::from_ptr(options).to_str().unwrap();
+        match serde_json::from_str::<RequestOptions>(options) {
+            Ok(options) => options,
+            Err(err) => {
+                log::warn!("request options json decode error: {}", err);
+                Self::default()
+            }
+        }
+    }
 }
 
 pub struct ResponseData {
@@ -24,6 +152,7 @@ pub struct ResponseData {
     version: Version,
     cookies: String,
     headers: String,
+    remote_addr: Option<SocketAddr>,
 }
 
 enum RespResultType {
@@ -34,6 +163,7 @@ enum RespResultType {
 pub struct RespResult {
     resp: RespResultType,
     create_time: Instant,
+    timings: RequestTimings,
 }
 
 #[repr(C)]
@@ -42,6 +172,7 @@ pub struct RequestResponse {
     len: usize,
     cap: usize,
     status: u32,
+    /// 实际协商出的协议版本 9/10/11/20/30, 没有结果时为0
     version: i32,
 }
 
@@ -54,21 +185,7 @@ impl RequestResponse {
             len: buffer.len(),
             cap: buffer.capacity(),
             status: data.status as u32,
-            version: {
-                if data.version == Version::HTTP_09 {
-                    9
-                } else if data.version == Version::HTTP_10 {
-                    10
-                } else if data.version == Version::HTTP_11 {
-                    11
-                } else if data.version == Version::HTTP_2 {
-                    20
-                } else if data.version == Version::HTTP_3 {
-                    30
-                } else {
-                    1
-                }
-            },
+            version: version_to_i32(data.version),
         };
         // 防止 Rust 在离开这个函数时自动清理 buffer
         std::mem::forget(buffer);
@@ -76,11 +193,34 @@ impl RequestResponse {
     }
 }
 
+/// 实际协商出的协议版本
+/// 9: HTTP/0.9
+/// 10: HTTP/1.0
+/// 11: HTTP/1.1
+/// 20: HTTP/2
+/// 30: HTTP/3
+/// 1: 未知
+fn version_to_i32(version: Version) -> i32 {
+    if version == Version::HTTP_09 {
+        9
+    } else if version == Version::HTTP_10 {
+        10
+    } else if version == Version::HTTP_11 {
+        11
+    } else if version == Version::HTTP_2 {
+        20
+    } else if version == Version::HTTP_3 {
+        30
+    } else {
+        1
+    }
+}
+
 fn hash_map_to_header_map(map: &HashMap<String, String>) -> HeaderMap {
     let mut headers = HeaderMap::new();
     for (key, value) in map {
         if let Ok(header_name) = HeaderName::from_bytes(key.as_bytes()) {
-            if let Ok(header_value) = HeaderValue::from_str(&value) {
+            if let Ok(header_value) = HeaderValue::from_str(value) {
                 headers.insert(header_name, header_value);
             }
         }
@@ -90,30 +230,70 @@ fn hash_map_to_header_map(map: &HashMap<String, String>) -> HeaderMap {
 
 #[no_mangle]
 pub extern "C" fn rust_net_http_client_new(brotli: bool, cookie_store: bool) -> *mut ClientContext {
-    match reqwest::Client::builder()
-        .use_rustls_tls()
-        .brotli(brotli)
-        .cookie_store(cookie_store)
-        .build()
-    {
-        Ok(client) => Box::into_raw(Box::new(ClientContext {
-            client,
-            items: Default::default(),
-            headers: HashMap::new(),
-            params: HashMap::new(),
-            last_clear_time: Instant::now(),
-            clear_expires_enabled: true,
-        })),
-        Err(_) => std::ptr::null_mut(),
+    ClientContext::create(ClientConfig {
+        brotli,
+        cookie_store,
+        ..Default::default()
+    })
+}
+
+/// 使用json配置创建客户端, 配置错误时返回空指针
+/// {
+///     "brotli": true,
+///     "cookie_store": true,
+///     "http1_only": false,
+///     "http2_only": false,
+///     "http2_keep_alive_interval_ms": 0,
+///     "http2_keep_alive_timeout_ms": 0,
+///     "http2_keep_alive_while_idle": false,
+///     "http2_initial_stream_window_size": 0,
+///     "http2_initial_connection_window_size": 0,
+///     "http2_adaptive_window": false,
+///     "http3": false,
+///     "http3_timeout_ms": 3000,
+///     "root_certificates": [],
+///     "param_encoding": "form",
+///     "dns": {
+///         "hosts": {"api.example.com": ["10.0.0.1"]},
+///         "cache_ttl_ms": 60000,
+///         "ip_family": "auto",
+///         "doh": {
+///             "url": "https://1.1.1.1/dns-query",
+///             "bootstrap_ips": [],
+///             "timeout_ms": 5000,
+///             "fallback_system": true,
+///             "root_certificates": []
+///         }
+///     }
+/// }
+/// HTTP客户端不支持 happy_eyeballs_delay_ms, 双栈时固定在首选地址族连接300ms未成功后尝试另一地址族
+///
+/// # Safety
+/// config 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_client_new_with_config(
+    config: *const c_char,
+) -> *mut ClientContext {
+    let config = CStr::from_ptr(config).to_str().unwrap();
+    match serde_json::from_str::<ClientConfig>(config) {
+        Ok(config) => ClientContext::create(config),
+        Err(err) => {
+            log::error!("client config json decode error: {}", err);
+            std::ptr::null_mut()
+        }
     }
 }
 
+/// # Safety
+/// handler 必须是 rust_net_http_client_new 或 rust_net_http_client_new_with_config 返回的指针, 释放后不能再使用
 #[no_mangle]
 pub unsafe extern "C" fn rust_net_http_client_free(handler: *mut ClientContext) {
     let handler = Box::from_raw(handler);
     drop(handler)
 }
 
+/// # Safety
+/// key、value 必须是以\0结尾的UTF-8字符串
 #[no_mangle]
 pub unsafe extern "C" fn rust_net_http_add_header(
     context: &mut ClientContext,
@@ -126,10 +306,14 @@ pub unsafe extern "C" fn rust_net_http_add_header(
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn rust_net_http_clear_header(context: &mut ClientContext) {
+pub extern "C" fn rust_net_http_clear_header(context: &mut ClientContext) {
     context.headers.clear();
 }
 
+/// 设置query参数, 替换所有同名参数
+///
+/// # Safety
+/// key、value 必须是以\0结尾的UTF-8字符串
 #[no_mangle]
 pub unsafe extern "C" fn rust_net_http_add_param(
     context: &mut ClientContext,
@@ -138,11 +322,65 @@ pub unsafe extern "C" fn rust_net_http_add_param(
 ) {
     let key = CStr::from_ptr(key).to_str().unwrap().to_string();
     let value = CStr::from_ptr(value).to_str().unwrap().to_string();
-    context.params.insert(key, value);
+    context.params.set(key, value);
 }
 
+/// 追加query参数, 同名参数可以出现多次, 例如 ?id=1&id=2
+/// 参数按添加的顺序拼接
+///
+/// # Safety
+/// key、value 必须是以\0结尾的UTF-8字符串
 #[no_mangle]
-pub unsafe extern "C" fn rust_net_http_set_clear_expires_enabled(
+pub unsafe extern "C" fn rust_net_http_append_param(
+    context: &mut ClientContext,
+    key: *const c_char,
+    value: *const c_char,
+) {
+    let key = CStr::from_ptr(key).to_str().unwrap().to_string();
+    let value = CStr::from_ptr(value).to_str().unwrap().to_string();
+    context.params.append(key, value);
+}
+
+/// 删除该名称的所有query参数
+///
+/// # Safety
+/// key 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_remove_param(
+    context: &mut ClientContext,
+    key: *const c_char,
+) {
+    let key = CStr::from_ptr(key).to_str().unwrap();
+    context.params.remove(key);
+}
+
+/// 设置query参数编码
+/// form: application/x-www-form-urlencoded, 空格编码为+ (默认)
+/// rfc3986: 只保留非保留字符, 空格编码为%20
+/// raw: 参数已由调用方编码, 原样拼接
+///
+/// # Safety
+/// encoding 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_set_param_encoding(
+    context: &mut ClientContext,
+    encoding: *const c_char,
+) -> bool {
+    let encoding = CStr::from_ptr(encoding).to_str().unwrap();
+    match ParamEncoding::parse(encoding) {
+        Some(encoding) => {
+            context.param_encoding = encoding;
+            true
+        }
+        None => {
+            log::error!("unknown param encoding: {}", encoding);
+            false
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn rust_net_http_set_clear_expires_enabled(
     context: &mut ClientContext,
     value: bool,
 ) {
@@ -150,10 +388,385 @@ pub unsafe extern "C" fn rust_net_http_set_clear_expires_enabled(
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn rust_net_http_clear_param(context: &mut ClientContext) {
+pub extern "C" fn rust_net_http_clear_param(context: &mut ClientContext) {
     context.params.clear();
 }
 
+/// 设置并发上限
+/// max_concurrency 全局最大并发数 0表示不限制
+/// max_per_host 单个host最大并发数 0表示不限制
+#[no_mangle]
+pub extern "C" fn rust_net_http_set_concurrency_limit(
+    client_context: &mut ClientContext,
+    max_concurrency: u32,
+    max_per_host: u32,
+) {
+    client_context
+        .scheduler
+        .set_limits(max_concurrency as usize, max_per_host as usize);
+}
+
+/// 设置该客户端的限速(字节/秒) 0表示不限速, 可以在请求进行中调整
+#[no_mangle]
+pub extern "C" fn rust_net_http_set_bandwidth_limit(
+    client_context: &mut ClientContext,
+    upload_bytes_per_sec: u64,
+    download_bytes_per_sec: u64,
+) {
+    client_context
+        .bandwidth
+        .set_limit(upload_bytes_per_sec, download_bytes_per_sec);
+}
+
+/// 调整单个请求的限速(字节/秒) 0表示不限速
+#[no_mangle]
+pub extern "C" fn rust_net_http_set_request_bandwidth_limit(
+    client_context: &mut ClientContext,
+    key: u64,
+    upload_bytes_per_sec: u64,
+    download_bytes_per_sec: u64,
+) {
+    if let Some(item) = client_context.items.get(key as usize) {
+        item.bandwidth
+            .set_limit(upload_bytes_per_sec, download_bytes_per_sec);
+    }
+}
+
+/// 添加请求拦截器, 按添加顺序执行, 可以修改请求的method、url、请求头及body
+/// 只对之后发起的请求生效
+#[no_mangle]
+pub extern "C" fn rust_net_http_add_request_interceptor(
+    client_context: &mut ClientContext,
+    callback: RequestInterceptor,
+    user_data: *mut c_void,
+) {
+    Arc::make_mut(&mut client_context.interceptors).add_request(callback, user_data);
+}
+
+/// 添加响应拦截器, 按添加顺序执行, 可以查看或修改响应的状态码、响应头及body
+/// 只对之后发起的请求生效
+#[no_mangle]
+pub extern "C" fn rust_net_http_add_response_interceptor(
+    client_context: &mut ClientContext,
+    callback: ResponseInterceptor,
+    user_data: *mut c_void,
+) {
+    Arc::make_mut(&mut client_context.interceptors).add_response(callback, user_data);
+}
+
+/// 移除所有拦截器
+#[no_mangle]
+pub extern "C" fn rust_net_http_clear_interceptors(client_context: &mut ClientContext) {
+    client_context.interceptors = Default::default();
+}
+
+/// 设置请求签名, config 为空时取消签名, 配置错误时返回false
+/// {
+///     "key_id": "key-1",
+///     "secret": "******",
+///     "encoding": "hex",
+///     "signature_header": "X-Signature",
+///     "key_id_header": "X-Key-Id",
+///     "timestamp_header": "X-Timestamp",
+///     "timestamp_unit": "seconds",
+///     "nonce_header": "X-Nonce",
+///     "signed_headers": ["X-Device-Id"]
+/// }
+/// 只对之后发起的请求生效, 签名规则参考 SignerConfig
+///
+/// # Safety
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_set_signer(
+    client_context: &mut ClientContext,
+    config: *const c_char,
+) -> bool {
+    if config.is_null() {
+        client_context.signer = None;
+        return true;
+    }
+    let config = CStr::from_ptr(config).to_str().unwrap();
+    let signer = serde_json::from_str::<SignerConfig>(config)
+        .map_err(|err| err.to_string())
+        .and_then(Signer::new);
+    match signer {
+        Ok(signer) => {
+            client_context.signer = Some(Arc::new(signer));
+            true
+        }
+        Err(err) => {
+            log::error!("signer config error: {}", err);
+            false
+        }
+    }
+}
+
+/// 设置Bearer token认证, config 为空时关闭认证, 参数为json:
+/// {
+///     "access_token": "xxx",
+///     "refresh_token": "yyy",
+///     "refresh_url": "https://example.com/oauth/token",
+///     "refresh_method": "POST",
+///     "refresh_body": "{\"refresh_token\": \"{refresh_token}\"}",
+///     "refresh_content_type": "application/json",
+///     "token_field": "data.access_token",
+///     "refresh_token_field": "data.refresh_token"
+/// }
+/// 请求携带 Authorization: Bearer <access_token>, 响应401时刷新token并重放请求
+/// 刷新期间发起的请求排队等待, 刷新失败时这些请求一起失败
+/// 流式body的请求无法重放, 直接返回401响应
+///
+/// # Safety
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_set_auth(
+    client_context: &mut ClientContext,
+    config: *const c_char,
+) -> bool {
+    if config.is_null() {
+        client_context.auth = None;
+        return true;
+    }
+    let config = CStr::from_ptr(config).to_str().unwrap();
+    match serde_json::from_str::<AuthConfig>(config) {
+        Ok(config) => {
+            client_context.auth = Some(Auth::new(client_context.client.clone(), config));
+            true
+        }
+        Err(err) => {
+            log::error!("auth config error: {}", err);
+            false
+        }
+    }
+}
+
+/// 设置刷新token的回调, 设置后不再请求 refresh_url
+/// 需要先调用 rust_net_http_set_auth, 未启用认证时返回false
+#[no_mangle]
+pub extern "C" fn rust_net_http_set_auth_refresh_callback(
+    client_context: &mut ClientContext,
+    callback: AuthRefreshCallback,
+    user_data: *mut c_void,
+) -> bool {
+    match &client_context.auth {
+        Some(auth) => {
+            auth.set_callback(callback, user_data);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 手动更新access token, 例如重新登录之后
+///
+/// # Safety
+/// token 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_set_auth_token(
+    client_context: &mut ClientContext,
+    token: *const c_char,
+) -> bool {
+    let token = CStr::from_ptr(token).to_str().unwrap();
+    match &client_context.auth {
+        Some(auth) => {
+            auth.set_token(token.to_string());
+            true
+        }
+        None => false,
+    }
+}
+
+/// 当前的access token, 刷新之后可以用来持久化
+/// 未启用认证时返回空指针
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_http_get_auth_token(client_context: &mut ClientContext) -> *mut c_char {
+    match &client_context.auth {
+        Some(auth) => match CString::new(auth.token()) {
+            Ok(cstr) => cstr.into_raw(),
+            Err(_) => std::ptr::null_mut(),
+        },
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// 设置Basic/Digest认证, config 为空时清除, 参数为json:
+/// {
+///     "username": "admin",
+///     "password": "xxx",
+///     "scheme": "auto"
+/// }
+/// scheme:
+/// auto: 先不带认证发送, 收到401时按质询使用Basic或Digest并重放请求
+/// basic: 直接携带Basic认证头
+/// digest: 只响应Digest质询(MD5、SHA-256, qop=auth)
+/// 单个请求也可以在请求参数的 credentials 中设置, 优先于客户端的设置
+/// 设置后不再使用 rust_net_http_set_auth 的Bearer认证
+///
+/// # Safety
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_set_credentials(
+    client_context: &mut ClientContext,
+    config: *const c_char,
+) -> bool {
+    if config.is_null() {
+        client_context.credentials = None;
+        return true;
+    }
+    let config = CStr::from_ptr(config).to_str().unwrap();
+    match serde_json::from_str::<CredentialsConfig>(config) {
+        Ok(config) => {
+            client_context.credentials = Some(Arc::new(Credentials::new(config)));
+            true
+        }
+        Err(err) => {
+            log::error!("credentials config error: {}", err);
+            false
+        }
+    }
+}
+
+/// 设置模拟传输, config 为空时恢复正常发送, 参数为json:
+/// {
+///     "mode": "replay",
+///     "path": "/tmp/interactions.jsonl",
+///     "match_method": true,
+///     "match_query": true,
+///     "ignore_params": ["timestamp"],
+///     "match_body": false
+/// }
+/// record: 正常发送请求, 将请求与响应写入 path (会清空原有内容)
+/// replay: 不发送请求, 从 path 中返回匹配的响应, 没有匹配时请求失败
+/// 文件每行一个json, 响应body为base64, 可以手动编辑
+/// 回放的结果同样通过 rust_net_http_get_request_state、rust_net_http_get_request_response 等获取
+///
+/// # Safety
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_set_mock_transport(
+    client_context: &mut ClientContext,
+    config: *const c_char,
+) -> bool {
+    if config.is_null() {
+        client_context.transport = None;
+        return true;
+    }
+    let config = CStr::from_ptr(config).to_str().unwrap();
+    let transport = serde_json::from_str::<MockConfig>(config)
+        .map_err(|err| err.to_string())
+        .and_then(MockTransport::new);
+    match transport {
+        Ok(transport) => {
+            client_context.transport = Some(Arc::new(transport));
+            true
+        }
+        Err(err) => {
+            log::error!("mock transport config error: {}", err);
+            false
+        }
+    }
+}
+
+/// 开启HAR抓包, config 为空时关闭并丢弃已记录的请求, 参数为json:
+/// {
+///     "include_bodies": false,
+///     "max_body_size": 1048576,
+///     "max_entries": 1000,
+///     "path": "",
+///     "save_interval_ms": 0,
+///     "redact_headers": ["X-Signature"]
+/// }
+/// 记录实际发送的请求与拦截器修改之前的响应, authorization、cookie 等请求头会被脱敏
+/// path 不为空时每个请求完成后自动写入(至少间隔 save_interval_ms), 否则通过 rust_net_http_save_har 写入
+///
+/// # Safety
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_set_har_capture(
+    client_context: &mut ClientContext,
+    config: *const c_char,
+) -> bool {
+    if config.is_null() {
+        client_context.har = None;
+        return true;
+    }
+    let config = CStr::from_ptr(config).to_str().unwrap();
+    match serde_json::from_str::<HarConfig>(config) {
+        Ok(config) => {
+            client_context.har = Some(Arc::new(HarCapture::new(config)));
+            true
+        }
+        Err(err) => {
+            log::error!("har config error: {}", err);
+            false
+        }
+    }
+}
+
+/// 将已记录的请求写入HAR 1.2文件, 未开启抓包或写入失败时返回false
+///
+/// # Safety
+/// path 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_save_har(
+    client_context: &mut ClientContext,
+    path: *const c_char,
+) -> bool {
+    let path = CStr::from_ptr(path).to_str().unwrap();
+    match &client_context.har {
+        Some(har) => match har.save(path) {
+            Ok(()) => true,
+            Err(err) => {
+                log::error!("save har to {} failed: {}", path, err);
+                false
+            }
+        },
+        None => false,
+    }
+}
+
+/// 已记录请求的HAR 1.2 json, 未开启抓包时返回空指针
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_http_get_har(client_context: &mut ClientContext) -> *mut c_char {
+    match &client_context.har {
+        Some(har) => match CString::new(har.to_json()) {
+            Ok(cstr) => cstr.into_raw(),
+            Err(_) => std::ptr::null_mut(),
+        },
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// 丢弃已记录的请求
+#[no_mangle]
+pub extern "C" fn rust_net_http_clear_har(client_context: &mut ClientContext) {
+    if let Some(har) = &client_context.har {
+        har.clear();
+    }
+}
+
+/// 设置自定义DNS解析回调, callback 为空时恢复系统解析
+/// 静态host映射优先于解析回调, 设置后会清空DNS缓存
+#[no_mangle]
+pub extern "C" fn rust_net_http_set_dns_resolver(
+    client_context: &mut ClientContext,
+    callback: DnsResolveCallback,
+    user_data: *mut c_void,
+) {
+    client_context.resolver.set_callback(callback, user_data);
+}
+
+/// 清空DNS缓存
+#[no_mangle]
+pub extern "C" fn rust_net_http_clear_dns_cache(client_context: &mut ClientContext) {
+    client_context.resolver.clear_cache();
+}
+
+/// # Safety
+/// url 必须是以\0结尾的UTF-8字符串
+/// data 必须指向至少 length 字节的可读内存
 #[no_mangle]
 pub unsafe extern "C" fn rust_net_http_post(
     tokio_context: &mut TokioContext,
@@ -162,63 +775,84 @@ pub unsafe extern "C" fn rust_net_http_post(
     data: *const u8,
     length: usize,
 ) -> u64 {
-    client_context.clear_expires_data();
+    rust_net_http_post_with_options(
+        tokio_context,
+        client_context,
+        url,
+        data,
+        length,
+        std::ptr::null(),
+    )
+}
+
+/// # Safety
+/// url 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_get(
+    tokio_context: &mut TokioContext,
+    client_context: &mut ClientContext,
+    url: *const c_char,
+) -> u64 {
+    rust_net_http_get_with_options(tokio_context, client_context, url, std::ptr::null())
+}
+
+/// options 为json字符串, 可以为空
+/// {
+///     "priority": 0,
+///     "upload_bytes_per_sec": 0,
+///     "download_bytes_per_sec": 0,
+///     "credentials": {"username": "admin", "password": "xxx", "scheme": "auto"},
+///     "params": [["id", "1"], ["id", "2"]],
+///     "param_encoding": "form"
+/// }
+///
+/// # Safety
+/// url、options 必须是以\0结尾的UTF-8字符串
+/// data 可以为空, 不为空时必须指向至少 length 字节的可读内存
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_post_with_options(
+    tokio_context: &mut TokioContext,
+    client_context: &mut ClientContext,
+    url: *const c_char,
+    data: *const u8,
+    length: usize,
+    options: *const c_char,
+) -> u64 {
     if data.is_null() {
+        client_context.clear_expires_data();
         return 0;
     }
 
-    let client_cloned = client_context.client.clone();
-
     let url = CStr::from_ptr(url).to_str().unwrap().to_string();
-    let data_slice = std::slice::from_raw_parts(data, length);
-
-    let item = Arc::new(OnceCell::new());
-    let key = client_context.items.insert(item.clone());
-    let headers = hash_map_to_header_map(&client_context.headers);
-    let params = client_context.params.clone();
-    let data = data_slice.to_vec();
-
-    tokio_context.runtime.spawn(async move {
-        let response_result = client_cloned
-            .post(url)
-            .body(data)
-            .query(&params)
-            .headers(headers)
-            .send()
-            .await;
-        handle_response(response_result, item).await;
-    });
+    let data = std::slice::from_raw_parts(data, length).to_vec();
+    let options = RequestOptions::from_ptr(options);
 
-    key as u64
+    client_context.send_request(tokio_context, Method::POST, url, Some(data), options)
 }
 
+/// options 为json字符串, 可以为空
+/// {
+///     "priority": 0,
+///     "upload_bytes_per_sec": 0,
+///     "download_bytes_per_sec": 0,
+///     "credentials": {"username": "admin", "password": "xxx", "scheme": "auto"},
+///     "params": [["id", "1"], ["id", "2"]],
+///     "param_encoding": "form"
+/// }
+///
+/// # Safety
+/// url、options 必须是以\0结尾的UTF-8字符串
 #[no_mangle]
-pub unsafe extern "C" fn rust_net_http_get(
+pub unsafe extern "C" fn rust_net_http_get_with_options(
     tokio_context: &mut TokioContext,
     client_context: &mut ClientContext,
     url: *const c_char,
+    options: *const c_char,
 ) -> u64 {
-    client_context.clear_expires_data();
-    let client_cloned = client_context.client.clone();
-
     let url = CStr::from_ptr(url).to_str().unwrap().to_string();
+    let options = RequestOptions::from_ptr(options);
 
-    let item = Arc::new(OnceCell::new());
-    let key = client_context.items.insert(item.clone());
-    let headers = hash_map_to_header_map(&client_context.headers);
-    let params = client_context.params.clone();
-
-    tokio_context.runtime.spawn(async move {
-        let response_result = client_cloned
-            .get(url)
-            .headers(headers)
-            .query(&params)
-            .send()
-            .await;
-        handle_response(response_result, item).await;
-    });
-
-    key as u64
+    client_context.send_request(tokio_context, Method::GET, url, None, options)
 }
 
 #[no_mangle]
@@ -239,7 +873,7 @@ pub extern "C" fn rust_net_http_get_request_state(
     key: u64,
 ) -> i32 {
     if let Some(item) = client_context.items.get(key as usize) {
-        if let Some(resp) = item.get() {
+        if let Some(resp) = item.result.get() {
             match resp.resp {
                 RespResultType::Data(_) => 1,
                 RespResultType::Error(_) => -1,
@@ -261,7 +895,7 @@ pub extern "C" fn rust_net_http_get_request_error(
     key: u64,
 ) -> *mut c_char {
     if let Some(item) = client_context.items.get(key as usize) {
-        if let Some(resp) = item.get() {
+        if let Some(resp) = item.result.get() {
             if let RespResultType::Error(error) = &resp.resp {
                 // 将 Rust 字符串转换为 C 风格的 `CString`
                 return match CString::new(error.as_str()) {
@@ -286,7 +920,7 @@ pub extern "C" fn rust_net_http_get_request_response(
     key: u64,
 ) -> RequestResponse {
     if let Some(item) = client_context.items.get(key as usize) {
-        if let Some(resp) = item.get() {
+        if let Some(resp) = item.result.get() {
             if let RespResultType::Data(data) = &resp.resp {
                 return RequestResponse::from(data);
             }
@@ -310,7 +944,7 @@ pub extern "C" fn rust_net_http_get_response_cookies(
     key: u64,
 ) -> *mut c_char {
     if let Some(item) = client_context.items.get(key as usize) {
-        if let Some(resp) = item.get() {
+        if let Some(resp) = item.result.get() {
             if let RespResultType::Data(data) = &resp.resp {
                 let json = data.cookies.clone();
                 return match CString::new(json) {
@@ -334,7 +968,7 @@ pub extern "C" fn rust_net_http_get_response_headers(
     key: u64,
 ) -> *mut c_char {
     if let Some(item) = client_context.items.get(key as usize) {
-        if let Some(resp) = item.get() {
+        if let Some(resp) = item.result.get() {
             if let RespResultType::Data(data) = &resp.resp {
                 let json = data.headers.clone();
                 return match CString::new(json) {
@@ -350,6 +984,54 @@ pub extern "C" fn rust_net_http_get_response_headers(
     std::ptr::null_mut()
 }
 
+/// 获取实际连接的远端地址, 例如 "1.2.3.4:443"、"[::1]:8080"
+/// 请求未完成或地址未知时返回空指针
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_http_get_remote_addr(
+    client_context: &mut ClientContext,
+    key: u64,
+) -> *mut c_char {
+    if let Some(item) = client_context.items.get(key as usize) {
+        if let Some(resp) = item.result.get() {
+            if let RespResultType::Data(ResponseData {
+                remote_addr: Some(addr),
+                ..
+            }) = &resp.resp
+            {
+                return match CString::new(addr.to_string()) {
+                    Ok(cstr) => cstr.into_raw(),
+                    Err(_) => std::ptr::null_mut(),
+                };
+            }
+        }
+    }
+    std::ptr::null_mut()
+}
+
+/// 获取请求各阶段耗时
+/// 请求未完成或不存在时各项为-1
+#[no_mangle]
+pub extern "C" fn rust_net_http_get_request_timings(
+    client_context: &mut ClientContext,
+    key: u64,
+) -> RequestTimings {
+    if let Some(item) = client_context.items.get(key as usize) {
+        if let Some(resp) = item.result.get() {
+            return resp.timings;
+        }
+    }
+    RequestTimings::default()
+}
+
+/// 获取该客户端的http统计, websocket相关项为0
+#[no_mangle]
+pub extern "C" fn rust_net_http_get_stats(client_context: &mut ClientContext) -> NetStats {
+    client_context.stats.snapshot()
+}
+
+/// # Safety
+/// s 可以为空, 不为空时必须是本库返回的字符串, 且只能释放一次
 #[no_mangle]
 pub unsafe extern "C" fn rust_net_http_free_string(s: *mut c_char) {
     if !s.is_null() {
@@ -361,7 +1043,7 @@ pub unsafe extern "C" fn rust_net_http_free_string(s: *mut c_char) {
 
 #[no_mangle]
 pub extern "C" fn rust_net_http_free_request_response(resp: RequestResponse) {
-    if resp.data.is_null() || resp.cap <= 0 {
+    if resp.data.is_null() || resp.cap == 0 {
         return;
     }
     unsafe {
@@ -372,6 +1054,181 @@ pub extern "C" fn rust_net_http_free_request_response(resp: RequestResponse) {
 }
 
 impl ClientContext {
+    fn create(config: ClientConfig) -> *mut ClientContext {
+        match ClientContext::new(config) {
+            Ok(context) => Box::into_raw(Box::new(context)),
+            Err(err) => {
+                log::error!("client build error: {}", err);
+                std::ptr::null_mut()
+            }
+        }
+    }
+
+    fn new(config: ClientConfig) -> Result<ClientContext, String> {
+        let resolver = Resolver::new(&config.dns)?;
+        let (client, _cookie_jar) = config.build_client(&resolver)?;
+
+        #[cfg(feature = "http3")]
+        let http3 = if config.http3 {
+            let http3_client = config.build_http3_client(&_cookie_jar, &resolver)?;
+            Some(Http3Fallback::new(http3_client, config.http3_timeout()))
+        } else {
+            None
+        };
+
+        Ok(ClientContext {
+            client,
+            items: Default::default(),
+            headers: HashMap::new(),
+            params: Params::default(),
+            param_encoding: config.param_encoding,
+            last_clear_time: Instant::now(),
+            clear_expires_enabled: true,
+            scheduler: Scheduler::new(),
+            resolver,
+            bandwidth: Default::default(),
+            interceptors: Default::default(),
+            signer: None,
+            auth: None,
+            credentials: None,
+            transport: None,
+            har: None,
+            #[cfg(feature = "http3")]
+            http3,
+            stats: Default::default(),
+            connections: Default::default(),
+        })
+    }
+
+    fn send_request(
+        &mut self,
+        tokio_context: &mut TokioContext,
+        method: Method,
+        url: String,
+        body: Option<Vec<u8>>,
+        mut options: RequestOptions,
+    ) -> u64 {
+        self.clear_expires_data();
+
+        let item = Arc::new(RequestItem {
+            result: OnceCell::new(),
+            bandwidth: Bandwidth::new(options.upload_bytes_per_sec, options.download_bytes_per_sec),
+        });
+        let key = self.items.insert(item.clone());
+
+        let create_time = Instant::now();
+        let host = request_host(&url);
+        let mut builder = self
+            .client
+            .request(method, url)
+            .headers(hash_map_to_header_map(&self.headers));
+        if let Some(body) = body {
+            builder = builder.body(body);
+        }
+        let mut request = builder.build();
+        if let Ok(request) = &mut request {
+            let encoding = options.param_encoding.unwrap_or(self.param_encoding);
+            let params = self.params.iter().chain(options.params.iter());
+            append_query(request.url_mut(), params, encoding);
+        }
+        if let Ok(request) = &request {
+            log::debug!("request {} {} {}", key, request.method(), request.url());
+            log::trace!("request {} headers: {:?}", key, request.headers());
+        }
+        let client = self.client.clone();
+        let scheduler = self.scheduler.clone();
+        #[cfg(feature = "http3")]
+        let http3 = self.http3.clone();
+
+        let context = RequestContext {
+            key: key as u64,
+            recorder: TimingRecorder::new(create_time),
+            connections: self.connections.clone(),
+            stats: [self.stats.clone(), tokio_context.stats.clone()],
+            bandwidth: [
+                item.bandwidth.clone(),
+                self.bandwidth.clone(),
+                tokio_context.bandwidth.clone(),
+            ],
+            interceptors: self.interceptors.clone(),
+            signer: self.signer.clone(),
+            auth: self.auth.clone(),
+            credentials: match options.credentials.take() {
+                Some(config) => Some(Arc::new(Credentials::new(config))),
+                None => self.credentials.clone(),
+            },
+            transport: self.transport.clone(),
+            recorded_request: OnceLock::new(),
+            har: self.har.clone(),
+            har_request: Mutex::new(None),
+        };
+        context.stats(|stats| stats.request_started());
+
+        tokio_context.runtime.spawn(async move {
+            let recorder = context.recorder.clone();
+            recorder
+                .scope(async move {
+                    // 排队等待并发名额, 许可在读取完body之后才释放
+                    let _permit = scheduler.acquire(host.clone(), options.priority).await;
+                    // 排队期间请求被取消
+                    if Arc::strong_count(&item) == 1 {
+                        return;
+                    }
+
+                    let request = match request {
+                        Ok(request) => request,
+                        Err(err) => return handle_response(Err(err), item, &context).await,
+                    };
+                    let request = match context.interceptors.intercept_request(context.key, request)
+                    {
+                        Some(request) => request,
+                        None => {
+                            log::debug!("request {} rejected by interceptor", context.key);
+                            let error = "request rejected by interceptor".to_string();
+                            return fail_request(&item, error, &context);
+                        }
+                    };
+                    if let Some(transport) = &context.transport {
+                        if transport.is_replay() {
+                            return replay_request(transport, request, &item, &context);
+                        }
+                        let _ = context.recorded_request.set(RecordedRequest::new(&request));
+                    }
+
+                    let send = |request| {
+                        send(
+                            &client,
+                            #[cfg(feature = "http3")]
+                            &http3,
+                            request,
+                            &host,
+                            &context,
+                        )
+                    };
+                    let response_result = match (&context.credentials, &context.auth) {
+                        (Some(credentials), _) => {
+                            send_with_credentials(credentials, request, &context, send).await
+                        }
+                        (None, Some(auth)) => {
+                            match send_with_auth(auth, request, &context, send).await {
+                                Ok(response_result) => response_result,
+                                Err(error) => return fail_request(&item, error, &context),
+                            }
+                        }
+                        (None, None) => {
+                            let mut request = request;
+                            prepare(&mut request, &context);
+                            send(request).await
+                        }
+                    };
+                    handle_response(response_result, item, &context).await;
+                })
+                .await;
+        });
+
+        key as u64
+    }
+
     fn set_clear_expires_enabled(&mut self, value: bool) {
         self.clear_expires_enabled = value;
         if value {
@@ -388,7 +1245,7 @@ impl ClientContext {
 
             // 清理长时间未取的消息
             self.items.retain(|_, item| {
-                if let Some(resp) = item.get() {
+                if let Some(resp) = item.result.get() {
                     resp.create_time.elapsed() < Duration::from_secs(20)
                 } else {
                     true
@@ -398,6 +1255,196 @@ impl ClientContext {
     }
 }
 
+/// 发送前的最后一步: 签名并开始计时
+/// 签名在拦截器与认证之后, 覆盖它们对请求的修改
+fn prepare(request: &mut Request, context: &RequestContext) {
+    if let Some(signer) = &context.signer {
+        signer.sign(request);
+    }
+    if let Some(har) = &context.har {
+        *context.har_request.lock().unwrap() = Some(har.capture_request(request));
+    }
+    context.recorder.mark_send_start();
+}
+
+/// 携带token发送, 收到401时刷新token并重放一次
+/// 刷新失败时返回错误
+async fn send_with_auth<F, Fut>(
+    auth: &Arc<Auth>,
+    mut request: Request,
+    context: &RequestContext,
+    send: F,
+) -> Result<Result<Response, reqwest::Error>, String>
+where
+    F: Fn(Request) -> Fut,
+    Fut: Future<Output = Result<Response, reqwest::Error>>,
+{
+    // body为流时无法重放
+    let replay = request.try_clone();
+    let generation = auth.authorize(&mut request).await?;
+    prepare(&mut request, context);
+    let response_result = send(request).await;
+
+    let mut replay = match (&response_result, replay) {
+        (Ok(response), Some(replay)) if response.status() == StatusCode::UNAUTHORIZED => replay,
+        _ => return Ok(response_result),
+    };
+    drop(response_result);
+    log::debug!("request {} unauthorized, refresh token", context.key);
+    auth.refresh(generation).await?;
+
+    auth.authorize(&mut replay).await?;
+    prepare(&mut replay, context);
+    Ok(send(replay).await)
+}
+
+/// Basic/Digest认证, 收到401质询时计算认证头并重放一次
+async fn send_with_credentials<F, Fut>(
+    credentials: &Credentials,
+    mut request: Request,
+    context: &RequestContext,
+    send: F,
+) -> Result<Response, reqwest::Error>
+where
+    F: Fn(Request) -> Fut,
+    Fut: Future<Output = Result<Response, reqwest::Error>>,
+{
+    credentials.authorize(&mut request);
+    let sent = request.headers().get(AUTHORIZATION).cloned();
+    // body为流时无法重放
+    let replay = request.try_clone();
+    prepare(&mut request, context);
+    let response_result = send(request).await;
+
+    let response = match &response_result {
+        Ok(response) if response.status() == StatusCode::UNAUTHORIZED => response,
+        _ => return response_result,
+    };
+    let mut replay = match replay {
+        Some(replay) => replay,
+        None => return response_result,
+    };
+    if !credentials.retry(&mut replay, response.headers(), sent.as_ref()) {
+        return response_result;
+    }
+    drop(response_result);
+    log::debug!(
+        "request {} unauthorized, retry with credentials",
+        context.key
+    );
+
+    prepare(&mut replay, context);
+    send(replay).await
+}
+
+async fn send(
+    client: &reqwest::Client,
+    #[cfg(feature = "http3")] http3: &Option<Arc<Http3Fallback>>,
+    request: Request,
+    host: &str,
+    context: &RequestContext,
+) -> Result<Response, reqwest::Error> {
+    #[cfg(feature = "http3")]
+    if let Some(http3) = http3 {
+        return http3.send(client, request, host, context).await;
+    }
+    let _ = host;
+    execute(client, request, context).await
+}
+
+/// 从记录文件返回响应, 不发送请求
+fn replay_request(
+    transport: &MockTransport,
+    mut request: Request,
+    item: &Arc<RequestItem>,
+    context: &RequestContext,
+) {
+    prepare(&mut request, context);
+    match transport.replay(&request) {
+        Some(data) => {
+            context.stats(|stats| {
+                stats.add_downloaded(data.data.len());
+                if (200..300).contains(&data.status) {
+                    stats.request_succeeded();
+                } else {
+                    stats.request_failed(FailKind::Status);
+                }
+            });
+            finish_request(item, RespResultType::Data(data), context);
+        }
+        None => {
+            let error = format!(
+                "no recorded response for {} {}",
+                request.method(),
+                request.url()
+            );
+            log::warn!("{}", error);
+            fail_request(item, error, context);
+        }
+    }
+}
+
+fn fail_request(item: &Arc<RequestItem>, error: String, context: &RequestContext) {
+    context.stats(|stats| stats.request_failed(FailKind::Other));
+    finish_request(item, RespResultType::Error(error), context);
+}
+
+/// 发送请求, 记录body发送完成与收到响应头的时间
+async fn execute(
+    client: &reqwest::Client,
+    mut request: Request,
+    context: &RequestContext,
+) -> Result<Response, reqwest::Error> {
+    let upload_size = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .map_or(0, |bytes| bytes.len());
+
+    timing::wrap_body(
+        &mut request,
+        context.recorder.clone(),
+        context.bandwidth.clone(),
+    );
+    let response_result = client.execute(request).await;
+    context.recorder.mark_first_byte();
+
+    if let Ok(response) = &response_result {
+        let reused = context.connections.is_reused(response);
+        context.recorder.mark_connection_reused(reused);
+        context.stats(|stats| stats.add_uploaded(upload_size));
+    }
+    response_result
+}
+
+/// 分块读取响应body并限速
+async fn read_body(
+    mut response: Response,
+    context: &RequestContext,
+) -> Result<Vec<u8>, reqwest::Error> {
+    let mut data = Vec::with_capacity(response.content_length().unwrap_or(0) as usize);
+    while let Some(chunk) = response.chunk().await? {
+        let limiters = context
+            .bandwidth
+            .iter()
+            .map(|bandwidth| &bandwidth.download);
+        throttle(limiters, chunk.len()).await;
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+/// 用于并发控制的host标识
+fn request_host(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(url) => match (url.host_str(), url.port_or_known_default()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            _ => String::new(),
+        },
+        Err(_) => String::new(),
+    }
+}
+
 fn headers_to_json(headers: &HeaderMap) -> String {
     let mut headers_map = HashMap::new();
     for (key, value) in headers.iter() {
@@ -415,9 +1462,55 @@ fn headers_to_json(headers: &HeaderMap) -> String {
     }
 }
 
+fn finish_request(item: &Arc<RequestItem>, resp: RespResultType, context: &RequestContext) {
+    context.recorder.mark_body_end();
+    // 录制拦截器修改之前的响应, 回放时会再经过拦截器
+    if let (RespResultType::Data(data), Some(transport), Some(request)) =
+        (&resp, &context.transport, context.recorded_request.get())
+    {
+        transport.record(request, data);
+    }
+    if let Some(har) = &context.har {
+        if let Some(request) = context.har_request.lock().unwrap().take() {
+            let timings = context.recorder.timings();
+            har.add(
+                context.key,
+                request,
+                &resp,
+                timings.to_har(),
+                timings.total(),
+            );
+        }
+    }
+    let resp = match resp {
+        RespResultType::Data(data) => {
+            RespResultType::Data(context.interceptors.intercept_response(context.key, data))
+        }
+        resp => resp,
+    };
+    let _ = item.result.set(RespResult {
+        resp,
+        create_time: Instant::now(),
+        timings: context.recorder.timings(),
+    });
+}
+
+fn fail_kind(error: &reqwest::Error) -> FailKind {
+    if error.is_timeout() {
+        FailKind::Timeout
+    } else if error.is_connect() {
+        FailKind::Connect
+    } else if error.is_body() || error.is_decode() {
+        FailKind::Body
+    } else {
+        FailKind::Other
+    }
+}
+
 async fn handle_response(
     response_result: Result<Response, reqwest::Error>,
-    item: Arc<OnceCell<RespResult>>,
+    item: Arc<RequestItem>,
+    context: &RequestContext,
 ) {
     // 请求被取消
     if Arc::strong_count(&item) == 1 {
@@ -437,57 +1530,57 @@ async fn handle_response(
             };
 
             let headers = headers_to_json(response.headers());
+            let remote_addr = response.remote_addr();
 
             if response.status().is_success() {
                 let status = response.status().as_u16();
                 let version = response.version();
-                match response.bytes().await {
+                match read_body(response, context).await {
                     Ok(bytes) => {
-                        let _ = item.set(RespResult {
-                            resp: RespResultType::Data(ResponseData {
-                                status,
-                                data: bytes.to_vec(),
-                                version,
-                                cookies,
-                                headers,
-                            }),
-                            create_time: Instant::now(),
+                        context.stats(|stats| {
+                            stats.add_downloaded(bytes.len());
+                            stats.request_succeeded();
                         });
+                        let data = ResponseData {
+                            status,
+                            data: bytes,
+                            version,
+                            cookies,
+                            headers,
+                            remote_addr,
+                        };
+                        finish_request(&item, RespResultType::Data(data), context);
                     }
                     Err(error) => {
-                        let _ = item.set(RespResult {
-                            resp: RespResultType::Error(error.to_string()),
-                            create_time: Instant::now(),
-                        });
+                        log::warn!("read body failed: {}", error);
+                        context.stats(|stats| stats.request_failed(fail_kind(&error)));
+                        finish_request(&item, RespResultType::Error(error.to_string()), context);
                     }
                 }
             } else {
                 let status = response.status().as_u16();
                 let version = response.version();
-                let response_data = match response.bytes().await {
-                    Ok(bytes) => bytes.to_vec(),
-                    Err(_) => {
-                        vec![]
-                    }
-                };
-
-                let _ = item.set(RespResult {
-                    resp: RespResultType::Data(ResponseData {
-                        status,
-                        data: response_data,
-                        version,
-                        cookies,
-                        headers,
-                    }),
-                    create_time: Instant::now(),
+                let response_data = read_body(response, context).await.unwrap_or_default();
+                context.stats(|stats| {
+                    stats.add_downloaded(response_data.len());
+                    stats.request_failed(FailKind::Status);
                 });
+
+                let data = ResponseData {
+                    status,
+                    data: response_data,
+                    version,
+                    cookies,
+                    headers,
+                    remote_addr,
+                };
+                finish_request(&item, RespResultType::Data(data), context);
             }
         }
         Err(error) => {
-            let _ = item.set(RespResult {
-                resp: RespResultType::Error(error.to_string()),
-                create_time: Instant::now(),
-            });
+            log::warn!("request failed: {}", error);
+            context.stats(|stats| stats.request_failed(fail_kind(&error)));
+            finish_request(&item, RespResultType::Error(error.to_string()), context);
         }
     }
 }
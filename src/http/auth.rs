@@ -0,0 +1,249 @@
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use reqwest::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{Method, Request};
+use serde::Deserialize;
+use std::ffi::c_void;
+use std::os::raw::c_char;
+use std::sync::{Arc, Mutex};
+
+/// 刷新token的回调, 将新的access token写入buffer, 返回写入的字节数, 返回负数表示刷新失败
+/// 回调在阻塞线程池中调用, 可以同步请求
+pub type AuthRefreshCallback =
+    Option<extern "C" fn(buffer: *mut c_char, buffer_len: usize, user_data: *mut c_void) -> i32>;
+
+const TOKEN_BUFFER_LEN: usize = 8192;
+
+/// 认证配置, 以json形式传入 rust_net_http_set_auth
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub access_token: String,
+    pub refresh_token: String,
+    // 刷新token的地址, 设置了刷新回调时不使用
+    pub refresh_url: String,
+    // 为空时使用POST
+    pub refresh_method: String,
+    // 刷新请求的body, 其中的 {refresh_token} 会被替换
+    pub refresh_body: String,
+    // 为空时使用 application/json
+    pub refresh_content_type: String,
+    // 响应json中access token的字段, 可以用.表示嵌套, 为空时使用 access_token
+    pub token_field: String,
+    // 响应json中refresh token的字段, 为空时使用 refresh_token, 响应中没有该字段时保留原值
+    pub refresh_token_field: String,
+}
+
+type RefreshFuture = Shared<BoxFuture<'static, Result<(), String>>>;
+
+struct AuthState {
+    access_token: String,
+    refresh_token: String,
+    // token每次更新加1, 用于判断401是否由旧token引起
+    generation: u64,
+    // 正在进行的刷新, 期间的请求等待刷新结果
+    refreshing: Option<RefreshFuture>,
+    callback: Option<RefreshCallback>,
+}
+
+#[derive(Clone, Copy)]
+struct RefreshCallback {
+    callback: extern "C" fn(*mut c_char, usize, *mut c_void) -> i32,
+    user_data: *mut c_void,
+}
+
+// user_data 由调用方保证可以在任意线程使用
+unsafe impl Send for RefreshCallback {}
+
+impl RefreshCallback {
+    fn call(&self, buffer: &mut [u8]) -> i32 {
+        (self.callback)(
+            buffer.as_mut_ptr() as *mut c_char,
+            buffer.len(),
+            self.user_data,
+        )
+    }
+}
+
+/// Bearer token认证
+/// 请求携带 Authorization: Bearer, 响应401时刷新token并重放请求
+/// 同一时间只有一个刷新, 刷新期间发起的请求排队等待, 刷新失败时一起失败
+pub struct Auth {
+    client: reqwest::Client,
+    config: AuthConfig,
+    state: Mutex<AuthState>,
+}
+
+impl Auth {
+    pub fn new(client: reqwest::Client, config: AuthConfig) -> Arc<Self> {
+        Arc::new(Auth {
+            client,
+            state: Mutex::new(AuthState {
+                access_token: config.access_token.clone(),
+                refresh_token: config.refresh_token.clone(),
+                generation: 0,
+                refreshing: None,
+                callback: None,
+            }),
+            config,
+        })
+    }
+
+    pub fn set_callback(&self, callback: AuthRefreshCallback, user_data: *mut c_void) {
+        self.state.lock().unwrap().callback = callback.map(|callback| RefreshCallback {
+            callback,
+            user_data,
+        });
+    }
+
+    pub fn set_token(&self, access_token: String) {
+        let mut state = self.state.lock().unwrap();
+        state.access_token = access_token;
+        state.generation += 1;
+    }
+
+    pub fn token(&self) -> String {
+        self.state.lock().unwrap().access_token.clone()
+    }
+
+    /// 添加认证头, 正在刷新时等待刷新完成, 返回所用token的版本
+    pub async fn authorize(&self, request: &mut Request) -> Result<u64, String> {
+        let (token, generation) = loop {
+            let refreshing = {
+                let state = self.state.lock().unwrap();
+                match &state.refreshing {
+                    Some(refreshing) => refreshing.clone(),
+                    None => break (state.access_token.clone(), state.generation),
+                }
+            };
+            refreshing.await?;
+        };
+
+        if !token.is_empty() {
+            let value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(|_| "invalid access token".to_string())?;
+            request.headers_mut().insert(AUTHORIZATION, value);
+        }
+        Ok(generation)
+    }
+
+    /// 使用 generation 版本的token收到401时调用
+    /// token已被其它请求刷新时直接返回, 正在刷新时等待同一个刷新
+    pub async fn refresh(self: &Arc<Self>, generation: u64) -> Result<(), String> {
+        let refreshing = {
+            let mut state = self.state.lock().unwrap();
+            if state.generation != generation {
+                return Ok(());
+            }
+            match &state.refreshing {
+                Some(refreshing) => refreshing.clone(),
+                None => {
+                    let auth = self.clone();
+                    let refreshing = async move { auth.run_refresh().await }.boxed().shared();
+                    state.refreshing = Some(refreshing.clone());
+                    refreshing
+                }
+            }
+        };
+        refreshing.await
+    }
+
+    async fn run_refresh(self: Arc<Self>) -> Result<(), String> {
+        let (callback, refresh_token) = {
+            let state = self.state.lock().unwrap();
+            (state.callback, state.refresh_token.clone())
+        };
+        log::info!("refreshing access token");
+        let result = match callback {
+            Some(callback) => refresh_by_callback(callback).await,
+            None => {
+                // 在独立的task中请求, 不计入触发刷新的请求的耗时
+                let auth = self.clone();
+                tokio::spawn(async move { auth.refresh_by_url(refresh_token).await })
+                    .await
+                    .unwrap_or_else(|err| Err(err.to_string()))
+            }
+        };
+
+        let mut state = self.state.lock().unwrap();
+        state.refreshing = None;
+        match result {
+            Ok((access_token, refresh_token)) => {
+                state.access_token = access_token;
+                if let Some(refresh_token) = refresh_token {
+                    state.refresh_token = refresh_token;
+                }
+                state.generation += 1;
+                Ok(())
+            }
+            Err(err) => {
+                log::warn!("refresh access token failed: {}", err);
+                Err(format!("refresh access token failed: {}", err))
+            }
+        }
+    }
+
+    async fn refresh_by_url(
+        &self,
+        refresh_token: String,
+    ) -> Result<(String, Option<String>), String> {
+        let config = &self.config;
+        if config.refresh_url.is_empty() {
+            return Err("no refresh url or refresh callback".into());
+        }
+        let method = match config.refresh_method.as_str() {
+            "" => Method::POST,
+            method => Method::from_bytes(method.as_bytes()).map_err(|err| err.to_string())?,
+        };
+        let content_type = match config.refresh_content_type.as_str() {
+            "" => "application/json",
+            content_type => content_type,
+        };
+        let body = config
+            .refresh_body
+            .replace("{refresh_token}", &refresh_token);
+
+        let response = self
+            .client
+            .request(method, &config.refresh_url)
+            .header(CONTENT_TYPE, content_type)
+            .body(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| err.to_string())?;
+        let body = response.bytes().await.map_err(|err| err.to_string())?;
+        let json =
+            serde_json::from_slice::<serde_json::Value>(&body).map_err(|err| err.to_string())?;
+
+        let access_token = json_field(&json, &config.token_field, "access_token")
+            .ok_or("no access token in refresh response")?;
+        let refresh_token = json_field(&json, &config.refresh_token_field, "refresh_token");
+        Ok((access_token, refresh_token))
+    }
+}
+
+async fn refresh_by_callback(
+    callback: RefreshCallback,
+) -> Result<(String, Option<String>), String> {
+    tokio::task::spawn_blocking(move || {
+        let mut buffer = vec![0u8; TOKEN_BUFFER_LEN];
+        let len = callback.call(&mut buffer);
+        if len < 0 {
+            return Err(format!("refresh callback returned {}", len));
+        }
+        buffer.truncate((len as usize).min(TOKEN_BUFFER_LEN));
+        let access_token = String::from_utf8(buffer).map_err(|err| err.to_string())?;
+        Ok((access_token, None))
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+fn json_field(json: &serde_json::Value, path: &str, default: &str) -> Option<String> {
+    let path = if path.is_empty() { default } else { path };
+    let mut value = json;
+    for key in path.split('.') {
+        value = value.get(key)?;
+    }
+    value.as_str().map(str::to_string)
+}
@@ -0,0 +1,147 @@
+use super::dns::{DnsConfig, Resolver};
+use super::query::ParamEncoding;
+use super::tls;
+use reqwest::cookie::Jar;
+use reqwest::ClientBuilder;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 客户端配置, 以json形式传入 rust_net_http_client_new_with_config
+/// 未填写的字段使用默认值
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct ClientConfig {
+    pub brotli: bool,
+    pub cookie_store: bool,
+    // 只使用HTTP/1.1
+    pub http1_only: bool,
+    // 只使用HTTP/2(prior knowledge), 不经过ALPN协商直接以HTTP/2通信
+    pub http2_only: bool,
+    // HTTP/2 ping保活间隔(毫秒) 0表示关闭
+    pub http2_keep_alive_interval_ms: u64,
+    // HTTP/2 ping超时时间(毫秒) 0表示不设置
+    pub http2_keep_alive_timeout_ms: u64,
+    // 连接空闲时是否也发送ping
+    pub http2_keep_alive_while_idle: bool,
+    // HTTP/2 stream级流控窗口大小 0表示默认值
+    pub http2_initial_stream_window_size: u32,
+    // HTTP/2 连接级流控窗口大小 0表示默认值
+    pub http2_initial_connection_window_size: u32,
+    // 自适应流控窗口, 开启后会忽略上面两个窗口大小
+    pub http2_adaptive_window: bool,
+    // 启用HTTP/3(需要开启http3 feature), 失败时回退到HTTP/2或HTTP/1.1
+    // POST等非幂等请求只发往HTTP/3请求成功过的host, 且只在QUIC连接失败时回退
+    pub http3: bool,
+    // 幂等请求的HTTP/3超时时间(毫秒), 超时后回退 0表示默认3000
+    pub http3_timeout_ms: u64,
+    // 额外信任的根证书(PEM), 用于自签名的本地服务器
+    pub root_certificates: Vec<String>,
+    // DNS配置: 静态host映射及缓存时间
+    pub dns: DnsConfig,
+    // query参数编码 form、rfc3986 或 raw
+    pub param_encoding: ParamEncoding,
+}
+
+impl ClientConfig {
+    /// 构建客户端, 开启cookie_store时返回共享的cookie jar
+    pub fn build_client(
+        &self,
+        resolver: &Resolver,
+    ) -> Result<(reqwest::Client, Option<Arc<Jar>>), String> {
+        if self.http1_only && self.http2_only {
+            return Err("http1_only and http2_only are mutually exclusive".into());
+        }
+        if self.http3 && (self.http1_only || self.http2_only) {
+            return Err("http3 cannot be combined with http1_only or http2_only".into());
+        }
+        #[cfg(not(feature = "http3"))]
+        if self.http3 {
+            return Err("rust_net is built without the http3 feature".into());
+        }
+
+        let cookie_jar = if self.cookie_store {
+            Some(Arc::new(Jar::default()))
+        } else {
+            None
+        };
+
+        let alpn_protocols: &[&str] = if self.http1_only {
+            &["http/1.1"]
+        } else if self.http2_only {
+            &["h2"]
+        } else {
+            &["h2", "http/1.1"]
+        };
+        let mut builder = self.builder(&cookie_jar, resolver, alpn_protocols)?;
+
+        if self.http1_only {
+            builder = builder.http1_only();
+        }
+        if self.http2_only {
+            builder = builder.http2_prior_knowledge();
+        }
+        if self.http2_keep_alive_interval_ms > 0 {
+            builder = builder
+                .http2_keep_alive_interval(Duration::from_millis(self.http2_keep_alive_interval_ms))
+                .http2_keep_alive_while_idle(self.http2_keep_alive_while_idle);
+            if self.http2_keep_alive_timeout_ms > 0 {
+                builder = builder.http2_keep_alive_timeout(Duration::from_millis(
+                    self.http2_keep_alive_timeout_ms,
+                ));
+            }
+        }
+        if self.http2_initial_stream_window_size > 0 {
+            builder =
+                builder.http2_initial_stream_window_size(self.http2_initial_stream_window_size);
+        }
+        if self.http2_initial_connection_window_size > 0 {
+            builder = builder
+                .http2_initial_connection_window_size(self.http2_initial_connection_window_size);
+        }
+        builder = builder.http2_adaptive_window(self.http2_adaptive_window);
+
+        let client = builder.build().map_err(|err| err.to_string())?;
+        Ok((client, cookie_jar))
+    }
+
+    /// 构建只使用HTTP/3的客户端, 与普通客户端共享cookie
+    #[cfg(feature = "http3")]
+    pub fn build_http3_client(
+        &self,
+        cookie_jar: &Option<Arc<Jar>>,
+        resolver: &Resolver,
+    ) -> Result<reqwest::Client, String> {
+        self.builder(cookie_jar, resolver, &["h3"])?
+            .http3_prior_knowledge()
+            .build()
+            .map_err(|err| err.to_string())
+    }
+
+    #[cfg(feature = "http3")]
+    pub fn http3_timeout(&self) -> Duration {
+        if self.http3_timeout_ms > 0 {
+            Duration::from_millis(self.http3_timeout_ms)
+        } else {
+            Duration::from_millis(3000)
+        }
+    }
+
+    fn builder(
+        &self,
+        cookie_jar: &Option<Arc<Jar>>,
+        resolver: &Resolver,
+        alpn_protocols: &[&str],
+    ) -> Result<ClientBuilder, String> {
+        let tls_config = tls::client_config(&self.root_certificates, alpn_protocols)?;
+        let mut builder = reqwest::Client::builder()
+            .use_preconfigured_tls(tls_config)
+            .dns_resolver(Arc::new(resolver.clone()))
+            .brotli(self.brotli);
+
+        if let Some(cookie_jar) = cookie_jar {
+            builder = builder.cookie_provider(cookie_jar.clone());
+        }
+        Ok(builder)
+    }
+}
@@ -0,0 +1,490 @@
+use md5::Md5;
+use rand::RngCore;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use reqwest::Request;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// 用户名密码认证配置, 以json形式传入 rust_net_http_set_credentials 或请求参数
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct CredentialsConfig {
+    pub username: String,
+    pub password: String,
+    pub scheme: AuthScheme,
+}
+
+/// 认证方式
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthScheme {
+    /// 先不带认证发送, 按401响应的质询选择Basic或Digest
+    #[default]
+    Auto,
+    /// 直接携带Basic认证头, 不等待质询
+    Basic,
+    /// 只响应Digest质询, 不会以明文发送密码
+    Digest,
+}
+
+/// Basic与Digest认证
+/// Digest收到质询之后会记住nonce, 后续请求直接计算响应, nonce过期时服务器返回stale=true再重新质询
+pub struct Credentials {
+    config: CredentialsConfig,
+    digest: Mutex<Option<DigestState>>,
+}
+
+struct DigestState {
+    challenge: DigestChallenge,
+    // 同一个nonce的使用次数
+    nonce_count: u32,
+}
+
+#[derive(Clone)]
+struct DigestChallenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: Algorithm,
+    // 服务器是否支持 qop=auth, 不支持时按RFC 2069计算
+    qop_auth: bool,
+    stale: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    Md5,
+    Md5Sess,
+    Sha256,
+    Sha256Sess,
+}
+
+impl Algorithm {
+    fn parse(value: &str) -> Option<Algorithm> {
+        match value.to_ascii_uppercase().as_str() {
+            "MD5" => Some(Algorithm::Md5),
+            "MD5-SESS" => Some(Algorithm::Md5Sess),
+            "SHA-256" => Some(Algorithm::Sha256),
+            "SHA-256-SESS" => Some(Algorithm::Sha256Sess),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::Md5 => "MD5",
+            Algorithm::Md5Sess => "MD5-sess",
+            Algorithm::Sha256 => "SHA-256",
+            Algorithm::Sha256Sess => "SHA-256-sess",
+        }
+    }
+
+    fn is_sha256(self) -> bool {
+        matches!(self, Algorithm::Sha256 | Algorithm::Sha256Sess)
+    }
+
+    fn is_sess(self) -> bool {
+        matches!(self, Algorithm::Md5Sess | Algorithm::Sha256Sess)
+    }
+
+    fn hash(self, data: &str) -> String {
+        if self.is_sha256() {
+            hex::encode(Sha256::digest(data.as_bytes()))
+        } else {
+            hex::encode(Md5::digest(data.as_bytes()))
+        }
+    }
+}
+
+impl Credentials {
+    pub fn new(config: CredentialsConfig) -> Credentials {
+        Credentials {
+            config,
+            digest: Mutex::new(None),
+        }
+    }
+
+    /// 发送前添加认证头: Basic方式直接添加, Digest已有质询时使用缓存的nonce
+    pub fn authorize(&self, request: &mut Request) {
+        let value = if self.config.scheme == AuthScheme::Basic {
+            Some(self.basic())
+        } else {
+            let mut digest = self.digest.lock().unwrap();
+            digest.as_mut().map(|state| {
+                state.nonce_count += 1;
+                self.digest_response(&state.challenge, state.nonce_count, &cnonce(), request)
+            })
+        };
+        if let Some(value) = value.and_then(|value| HeaderValue::from_str(&value).ok()) {
+            request.headers_mut().insert(AUTHORIZATION, value);
+        }
+    }
+
+    /// 处理401响应的质询, 为重放的请求添加认证头, 返回false表示不再重试
+    /// sent 为被拒绝的请求所携带的认证头, 已携带Digest时只有stale的质询才重试, 避免密码错误时反复重试
+    /// Basic方式已经在发送时携带了认证头, 无法响应其它质询, 不再重试
+    pub fn retry(
+        &self,
+        replay: &mut Request,
+        headers: &HeaderMap,
+        sent: Option<&HeaderValue>,
+    ) -> bool {
+        if self.config.scheme == AuthScheme::Basic {
+            return false;
+        }
+        let sent = sent
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let challenges = headers
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(parse_challenges)
+            .collect::<Vec<_>>();
+
+        let digest = challenges
+            .iter()
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("digest"))
+            .filter_map(|(_, params)| DigestChallenge::from_params(params))
+            // 优先SHA-256
+            .max_by_key(|challenge| challenge.algorithm.is_sha256());
+        if let Some(challenge) = digest {
+            if sent.starts_with("Digest") && !challenge.stale {
+                return false;
+            }
+            *self.digest.lock().unwrap() = Some(DigestState {
+                challenge,
+                nonce_count: 0,
+            });
+            self.authorize(replay);
+            return true;
+        }
+
+        let basic = challenges
+            .iter()
+            .any(|(scheme, _)| scheme.eq_ignore_ascii_case("basic"));
+        if basic && self.config.scheme == AuthScheme::Auto && !sent.starts_with("Basic") {
+            if let Ok(value) = HeaderValue::from_str(&self.basic()) {
+                replay.headers_mut().insert(AUTHORIZATION, value);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn basic(&self) -> String {
+        use base64::Engine;
+        let credentials = format!("{}:{}", self.config.username, self.config.password);
+        format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD.encode(credentials)
+        )
+    }
+
+    fn digest_response(
+        &self,
+        challenge: &DigestChallenge,
+        nonce_count: u32,
+        cnonce: &str,
+        request: &Request,
+    ) -> String {
+        let algorithm = challenge.algorithm;
+        let url = request.url();
+        let uri = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let nc = format!("{:08x}", nonce_count);
+
+        let username = &self.config.username;
+        let mut ha1 = algorithm.hash(&format!(
+            "{}:{}:{}",
+            username, challenge.realm, self.config.password
+        ));
+        if algorithm.is_sess() {
+            ha1 = algorithm.hash(&format!("{}:{}:{}", ha1, challenge.nonce, cnonce));
+        }
+        let ha2 = algorithm.hash(&format!("{}:{}", request.method().as_str(), uri));
+        let response = if challenge.qop_auth {
+            algorithm.hash(&format!(
+                "{}:{}:{}:{}:auth:{}",
+                ha1, challenge.nonce, nc, cnonce, ha2
+            ))
+        } else {
+            algorithm.hash(&format!("{}:{}:{}", ha1, challenge.nonce, ha2))
+        };
+
+        let mut value = format!(
+            "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm={}, response=\"{}\"",
+            quote(username),
+            quote(&challenge.realm),
+            quote(&challenge.nonce),
+            quote(&uri),
+            algorithm.name(),
+            response
+        );
+        if challenge.qop_auth {
+            value.push_str(&format!(", qop=auth, nc={}, cnonce=\"{}\"", nc, cnonce));
+        }
+        if let Some(opaque) = &challenge.opaque {
+            value.push_str(&format!(", opaque=\"{}\"", quote(opaque)));
+        }
+        value
+    }
+}
+
+impl DigestChallenge {
+    /// 不支持的算法或只支持 qop=auth-int 时返回None
+    fn from_params(params: &HashMap<String, String>) -> Option<DigestChallenge> {
+        let algorithm = match params.get("algorithm") {
+            Some(algorithm) => Algorithm::parse(algorithm)?,
+            None => Algorithm::Md5,
+        };
+        let qop_auth = match params.get("qop") {
+            Some(qop) => {
+                if !qop
+                    .split(',')
+                    .any(|qop| qop.trim().eq_ignore_ascii_case("auth"))
+                {
+                    return None;
+                }
+                true
+            }
+            None => false,
+        };
+        Some(DigestChallenge {
+            realm: params.get("realm").cloned().unwrap_or_default(),
+            nonce: params.get("nonce")?.clone(),
+            opaque: params.get("opaque").cloned(),
+            algorithm,
+            qop_auth,
+            stale: params
+                .get("stale")
+                .is_some_and(|stale| stale.eq_ignore_ascii_case("true")),
+        })
+    }
+}
+
+fn cnonce() -> String {
+    let mut bytes = [0u8; 8];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+fn quote(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// 解析 WWW-Authenticate, 一个头中可能有多个质询:
+/// Digest realm="a", nonce="b", Basic realm="c"
+fn parse_challenges(value: &str) -> Vec<(String, HashMap<String, String>)> {
+    let mut challenges: Vec<(String, HashMap<String, String>)> = Vec::new();
+    let mut chars = value.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| *c == ',' || c.is_whitespace()) {
+            chars.next();
+        }
+        let mut token = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == '=' || c == ',' || c.is_whitespace() {
+                break;
+            }
+            token.push(c);
+            chars.next();
+        }
+        if token.is_empty() {
+            break;
+        }
+        while chars.peek().is_some_and(|c| *c == ' ') {
+            chars.next();
+        }
+
+        if chars.peek() != Some(&'=') {
+            // 新的质询
+            challenges.push((token, HashMap::new()));
+            continue;
+        }
+        chars.next();
+        while chars.peek().is_some_and(|c| *c == ' ') {
+            chars.next();
+        }
+        let mut param = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => param.extend(chars.next()),
+                    c => param.push(c),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c == ',' || c.is_whitespace() {
+                    break;
+                }
+                param.push(c);
+                chars.next();
+            }
+        }
+        if let Some((_, params)) = challenges.last_mut() {
+            params.insert(token.to_ascii_lowercase(), param);
+        }
+    }
+    challenges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::{Method, Url};
+
+    // RFC 7616 3.9.1 的示例
+    const CHALLENGES: [&str; 2] = [
+        "Digest realm=\"http-auth@example.org\", qop=\"auth, auth-int\", algorithm=SHA-256, \
+         nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", \
+         opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"",
+        "Digest realm=\"http-auth@example.org\", qop=\"auth, auth-int\", algorithm=MD5, \
+         nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", \
+         opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"",
+    ];
+    const CNONCE: &str = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+
+    fn credentials(scheme: AuthScheme) -> Credentials {
+        Credentials::new(CredentialsConfig {
+            username: "Mufasa".to_string(),
+            password: "Circle of Life".to_string(),
+            scheme,
+        })
+    }
+
+    fn request() -> Request {
+        let url = Url::parse("http://www.example.org/dir/index.html").unwrap();
+        Request::new(Method::GET, url)
+    }
+
+    fn challenge(value: &str) -> DigestChallenge {
+        let (scheme, params) = parse_challenges(value).pop().unwrap();
+        assert_eq!(scheme, "Digest");
+        DigestChallenge::from_params(&params).unwrap()
+    }
+
+    fn headers(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(WWW_AUTHENTICATE, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn rfc7616_sha256() {
+        let challenge = challenge(CHALLENGES[0]);
+        assert!(challenge.algorithm == Algorithm::Sha256 && challenge.qop_auth);
+        let value =
+            credentials(AuthScheme::Digest).digest_response(&challenge, 1, CNONCE, &request());
+        assert_eq!(
+            value,
+            "Digest username=\"Mufasa\", realm=\"http-auth@example.org\", \
+             nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", uri=\"/dir/index.html\", \
+             algorithm=SHA-256, \
+             response=\"753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1\", \
+             qop=auth, nc=00000001, cnonce=\"f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ\", \
+             opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\""
+        );
+    }
+
+    #[test]
+    fn rfc7616_md5() {
+        let challenge = challenge(CHALLENGES[1]);
+        assert!(challenge.algorithm == Algorithm::Md5 && challenge.qop_auth);
+        let value =
+            credentials(AuthScheme::Digest).digest_response(&challenge, 1, CNONCE, &request());
+        assert!(value.contains("response=\"8ca523f5e9506fed4657c9700eebdbec\""));
+        assert!(value.contains("algorithm=MD5,"));
+    }
+
+    #[test]
+    fn parse_multiple_challenges() {
+        let challenges =
+            parse_challenges("Digest realm=\"a, b\", nonce=\"n\\\"1\", Basic realm=\"c\"");
+        assert_eq!(challenges.len(), 2);
+        assert_eq!(challenges[0].0, "Digest");
+        assert_eq!(challenges[0].1["realm"], "a, b");
+        assert_eq!(challenges[0].1["nonce"], "n\"1");
+        assert_eq!(challenges[1].0, "Basic");
+        assert_eq!(challenges[1].1["realm"], "c");
+
+        // 只支持 auth-int 或不支持的算法时忽略
+        assert!(DigestChallenge::from_params(
+            &parse_challenges("Digest realm=\"a\", nonce=\"n\", qop=\"auth-int\"")[0].1
+        )
+        .is_none());
+        assert!(DigestChallenge::from_params(
+            &parse_challenges("Digest realm=\"a\", nonce=\"n\", algorithm=SHA-512-256")[0].1
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn retry_prefers_sha256() {
+        let credentials = credentials(AuthScheme::Auto);
+        let mut replay = request();
+        assert!(credentials.retry(&mut replay, &headers(&[CHALLENGES[1], CHALLENGES[0]]), None));
+        let value = replay.headers()[AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(value.starts_with("Digest ") && value.contains("algorithm=SHA-256,"));
+        assert!(value.contains("nc=00000001"));
+
+        // 后续请求直接使用缓存的nonce
+        let mut next = request();
+        credentials.authorize(&mut next);
+        assert!(next.headers()[AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .contains("nc=00000002"));
+
+        // 已携带Digest仍被拒绝, 只有stale时才重试
+        let sent = HeaderValue::from_str(&value).unwrap();
+        let mut replay = request();
+        assert!(!credentials.retry(&mut replay, &headers(&[CHALLENGES[0]]), Some(&sent)));
+        let stale = format!("{}, stale=true", CHALLENGES[0]);
+        assert!(credentials.retry(&mut replay, &headers(&[&stale]), Some(&sent)));
+    }
+
+    #[test]
+    fn retry_basic() {
+        let credentials = credentials(AuthScheme::Auto);
+        let mut replay = request();
+        assert!(credentials.retry(&mut replay, &headers(&["Basic realm=\"a\""]), None));
+        let sent = replay.headers()[AUTHORIZATION].clone();
+        assert_eq!(sent, "Basic TXVmYXNhOkNpcmNsZSBvZiBMaWZl");
+        assert!(!credentials.retry(&mut replay, &headers(&["Basic realm=\"a\""]), Some(&sent)));
+    }
+
+    #[test]
+    fn retry_unsupported_scheme() {
+        // Basic方式无法响应Digest质询
+        let basic = credentials(AuthScheme::Basic);
+        let mut replay = request();
+        basic.authorize(&mut replay);
+        let sent = replay.headers()[AUTHORIZATION].clone();
+        assert!(!basic.retry(&mut replay, &headers(&[CHALLENGES[0]]), Some(&sent)));
+        let mut next = request();
+        basic.authorize(&mut next);
+        assert!(next.headers()[AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .starts_with("Basic "));
+
+        // Digest方式不会以明文发送密码
+        let digest = credentials(AuthScheme::Digest);
+        let mut replay = request();
+        assert!(!digest.retry(&mut replay, &headers(&["Basic realm=\"a\""]), None));
+        assert!(replay.headers().get(AUTHORIZATION).is_none());
+    }
+}
@@ -0,0 +1,420 @@
+#[cfg(feature = "doh")]
+use super::doh::DohResolver;
+use super::timing::TimingRecorder;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{c_void, CString};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::os::raw::c_char;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// 自定义DNS解析回调
+/// 将host解析出的IP以逗号分隔写入buffer(例如 "1.2.3.4,::1"), 返回写入的字节数
+/// 返回负数表示解析失败, 返回0表示交给系统解析
+/// 回调在阻塞线程池中调用, 可以同步查询
+pub type DnsResolveCallback = Option<
+    extern "C" fn(
+        host: *const c_char,
+        buffer: *mut c_char,
+        buffer_len: usize,
+        user_data: *mut c_void,
+    ) -> i32,
+>;
+
+const RESOLVE_BUFFER_LEN: usize = 1024;
+// 共享解析器的数量上限, 超出时淘汰最早创建的
+const MAX_SHARED_RESOLVERS: usize = 16;
+
+/// DNS配置, 作为客户端配置及WebSocket连接配置中的 dns 字段
+#[derive(Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct DnsConfig {
+    // 静态host映射, 例如 {"api.example.com": ["10.0.0.1"]}, 优先于其它解析方式
+    pub hosts: HashMap<String, Vec<String>>,
+    // 解析结果缓存时间(毫秒) 0表示不缓存, DoH的结果未设置时使用记录自身的TTL
+    pub cache_ttl_ms: u64,
+    // DNS over HTTPS(需要开启doh feature), 优先于系统解析
+    pub doh: Option<DohConfig>,
+    // 地址族选择, 两种地址都可用时交替排列, 优先的地址族在前
+    // HTTP请求由hyper在首选地址族连接300ms未成功后并行尝试另一地址族, 该间隔固定不可配置
+    // happy_eyeballs_delay_ms 只用于WebSocket及TCP连接的配置
+    pub ip_family: IpFamily,
+}
+
+/// 地址族选择
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IpFamily {
+    // 以解析结果中第一个地址的地址族优先
+    #[default]
+    Auto,
+    PreferIpv4,
+    PreferIpv6,
+    Ipv4Only,
+    Ipv6Only,
+}
+
+impl IpFamily {
+    /// 按地址族过滤并排序, 两种地址交替排列以便Happy Eyeballs尽快尝试另一地址族
+    fn sort(self, ips: &[IpAddr]) -> Vec<IpAddr> {
+        let (v4, v6): (Vec<IpAddr>, Vec<IpAddr>) = ips.iter().partition(|ip| ip.is_ipv4());
+        let (preferred, fallback) = match self {
+            IpFamily::Ipv4Only => return v4,
+            IpFamily::Ipv6Only => return v6,
+            IpFamily::PreferIpv4 => (v4, v6),
+            IpFamily::PreferIpv6 => (v6, v4),
+            IpFamily::Auto => match ips.first() {
+                Some(ip) if ip.is_ipv6() => (v6, v4),
+                _ => (v4, v6),
+            },
+        };
+
+        let mut sorted = Vec::with_capacity(ips.len());
+        let mut preferred = preferred.into_iter();
+        let mut fallback = fallback.into_iter();
+        loop {
+            match (preferred.next(), fallback.next()) {
+                (None, None) => return sorted,
+                (a, b) => sorted.extend(a.into_iter().chain(b)),
+            }
+        }
+    }
+}
+
+/// DoH配置
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct DohConfig {
+    // DoH地址, 例如 https://1.1.1.1/dns-query
+    pub url: String,
+    // DoH服务器的IP, 连接DoH服务器时不再解析其域名
+    pub bootstrap_ips: Vec<String>,
+    // 查询超时时间(毫秒) 0表示默认5000
+    pub timeout_ms: u64,
+    // DoH查询失败时是否使用系统解析
+    pub fallback_system: bool,
+    // 额外信任的根证书(PEM), 用于本地测试的DoH服务器
+    pub root_certificates: Vec<String>,
+}
+
+impl Default for DohConfig {
+    fn default() -> Self {
+        DohConfig {
+            url: String::new(),
+            bootstrap_ips: Vec::new(),
+            timeout_ms: 0,
+            fallback_system: true,
+            root_certificates: Vec::new(),
+        }
+    }
+}
+
+struct CustomResolver {
+    callback: extern "C" fn(*const c_char, *mut c_char, usize, *mut c_void) -> i32,
+    user_data: *mut c_void,
+}
+
+// user_data 由调用方保证可以在任意线程使用
+unsafe impl Send for CustomResolver {}
+unsafe impl Sync for CustomResolver {}
+
+struct ResolverState {
+    hosts: HashMap<String, Vec<IpAddr>>,
+    cache_ttl: Duration,
+    ip_family: IpFamily,
+    cache: Mutex<HashMap<String, (Instant, Vec<IpAddr>)>>,
+    custom: RwLock<Option<Arc<CustomResolver>>>,
+    #[cfg(feature = "doh")]
+    doh: Option<(DohResolver, bool)>,
+}
+
+/// DNS解析器
+/// 依次使用静态host映射、缓存、自定义解析回调、DoH、系统解析, 并记录解析耗时
+#[derive(Clone)]
+pub struct Resolver {
+    state: Arc<ResolverState>,
+}
+
+impl Resolver {
+    pub fn new(config: &DnsConfig) -> Result<Resolver, String> {
+        let mut hosts = HashMap::new();
+        for (host, ips) in &config.hosts {
+            let ips = ips
+                .iter()
+                .map(|ip| {
+                    ip.parse::<IpAddr>()
+                        .map_err(|_| format!("invalid ip address for {}: {}", host, ip))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            hosts.insert(host.to_ascii_lowercase(), ips);
+        }
+
+        #[cfg(feature = "doh")]
+        let doh = match &config.doh {
+            Some(doh) => Some((DohResolver::new(doh)?, doh.fallback_system)),
+            None => None,
+        };
+        #[cfg(not(feature = "doh"))]
+        if config.doh.is_some() {
+            return Err("rust_net is built without the doh feature".into());
+        }
+
+        Ok(Resolver {
+            state: Arc::new(ResolverState {
+                hosts,
+                cache_ttl: Duration::from_millis(config.cache_ttl_ms),
+                ip_family: config.ip_family,
+                cache: Mutex::new(HashMap::new()),
+                custom: RwLock::new(None),
+                #[cfg(feature = "doh")]
+                doh,
+            }),
+        })
+    }
+
+    /// 设置自定义解析回调, callback 为空时恢复系统解析
+    pub fn set_callback(&self, callback: DnsResolveCallback, user_data: *mut c_void) {
+        let custom = callback.map(|callback| {
+            Arc::new(CustomResolver {
+                callback,
+                user_data,
+            })
+        });
+        *self.state.custom.write().unwrap() = custom;
+        self.clear_cache();
+    }
+
+    pub fn clear_cache(&self) {
+        self.state.cache.lock().unwrap().clear();
+    }
+
+    /// 解析host, 返回按地址族排序后端口为0的地址
+    pub async fn lookup(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+        let host = host.to_ascii_lowercase();
+        let ips = match self.state.hosts.get(&host) {
+            Some(ips) => ips.clone(),
+            None => match self.cached(&host) {
+                Some(ips) => ips,
+                None => self.resolve_and_cache(&host).await?,
+            },
+        };
+
+        let ips = self.state.ip_family.sort(&ips);
+        if ips.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no address found for {}", host),
+            ));
+        }
+        Ok(ips.iter().map(|ip| SocketAddr::new(*ip, 0)).collect())
+    }
+
+    async fn resolve_and_cache(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        let state = &self.state;
+        let (ips, ttl) = self.resolve(host).await?;
+        let cache_ttl = if state.cache_ttl.is_zero() {
+            ttl
+        } else {
+            state.cache_ttl
+        };
+        if !cache_ttl.is_zero() && !ips.is_empty() {
+            let expires = Instant::now() + cache_ttl;
+            let mut cache = state.cache.lock().unwrap();
+            cache.retain(|_, (expires, _)| *expires > Instant::now());
+            cache.insert(host.to_string(), (expires, ips.clone()));
+        }
+        Ok(ips)
+    }
+
+    /// 不经过缓存的解析, 返回IP及记录自身的TTL(未知时为0)
+    async fn resolve(&self, host: &str) -> io::Result<(Vec<IpAddr>, Duration)> {
+        let custom = self.state.custom.read().unwrap().clone();
+        if let Some(custom) = custom {
+            let ips = custom_lookup(custom, host.to_string()).await?;
+            if !ips.is_empty() {
+                return Ok((ips, Duration::ZERO));
+            }
+        }
+
+        #[cfg(feature = "doh")]
+        if let Some((doh, fallback_system)) = &self.state.doh {
+            match doh.lookup(host).await {
+                Ok(result) => return Ok(result),
+                Err(err) if *fallback_system => {
+                    log::warn!("doh lookup {} failed, fallback to system: {}", host, err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        let ips = tokio::net::lookup_host((host, 0))
+            .await?
+            .map(|addr| addr.ip())
+            .collect();
+        Ok((ips, Duration::ZERO))
+    }
+
+    fn cached(&self, host: &str) -> Option<Vec<IpAddr>> {
+        let cache = self.state.cache.lock().unwrap();
+        match cache.get(host) {
+            Some((expires, ips)) if *expires > Instant::now() => Some(ips.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            TimingRecorder::mark_dns_start();
+            let result = resolver.lookup(name.as_str()).await;
+            TimingRecorder::mark_dns_end();
+
+            let addrs: Addrs = Box::new(result?.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// WebSocket及TCP连接共享的解析器
+/// DNS配置与解析回调都相同的连接使用同一个解析器, 以便共享解析缓存
+#[derive(Default)]
+pub struct SharedResolvers {
+    // (DNS配置, 解析回调, user_data, 解析器)
+    resolvers: Mutex<VecDeque<(DnsConfig, usize, usize, Resolver)>>,
+}
+
+impl SharedResolvers {
+    pub fn get(
+        &self,
+        config: &DnsConfig,
+        callback: DnsResolveCallback,
+        user_data: *mut c_void,
+    ) -> Result<Resolver, String> {
+        let callback_key = callback.map_or(0, |callback| callback as usize);
+        let user_data_key = user_data as usize;
+        let mut resolvers = self.resolvers.lock().unwrap();
+        let shared = resolvers
+            .iter()
+            .find(|(shared_config, callback, user_data, _)| {
+                shared_config == config && *callback == callback_key && *user_data == user_data_key
+            });
+        if let Some((_, _, _, resolver)) = shared {
+            return Ok(resolver.clone());
+        }
+
+        let resolver = Resolver::new(config)?;
+        resolver.set_callback(callback, user_data);
+        if resolvers.len() >= MAX_SHARED_RESOLVERS {
+            resolvers.pop_front();
+        }
+        resolvers.push_back((
+            config.clone(),
+            callback_key,
+            user_data_key,
+            resolver.clone(),
+        ));
+        Ok(resolver)
+    }
+}
+
+async fn custom_lookup(custom: Arc<CustomResolver>, host: String) -> io::Result<Vec<IpAddr>> {
+    let result = tokio::task::spawn_blocking(move || {
+        let host = CString::new(host).map_err(|_| "invalid host".to_string())?;
+        let mut buffer = vec![0u8; RESOLVE_BUFFER_LEN];
+        let len = (custom.callback)(
+            host.as_ptr(),
+            buffer.as_mut_ptr() as *mut c_char,
+            buffer.len(),
+            custom.user_data,
+        );
+        if len < 0 {
+            return Err(format!("custom resolver failed: {}", len));
+        }
+        buffer.truncate((len as usize).min(RESOLVE_BUFFER_LEN));
+        let text = String::from_utf8(buffer).map_err(|err| err.to_string())?;
+        text.split(',')
+            .map(str::trim)
+            .filter(|ip| !ip.is_empty())
+            .map(|ip| {
+                ip.parse::<IpAddr>()
+                    .map_err(|_| format!("custom resolver returned invalid ip: {}", ip))
+            })
+            .collect::<Result<Vec<_>, _>>()
+    })
+    .await
+    .map_err(io::Error::other)?;
+
+    result.map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static LOOKUPS: AtomicUsize = AtomicUsize::new(0);
+
+    extern "C" fn lookup(
+        _host: *const c_char,
+        buffer: *mut c_char,
+        buffer_len: usize,
+        _user_data: *mut c_void,
+    ) -> i32 {
+        LOOKUPS.fetch_add(1, Ordering::SeqCst);
+        let ip = b"10.0.0.1";
+        assert!(buffer_len >= ip.len());
+        unsafe { std::ptr::copy_nonoverlapping(ip.as_ptr(), buffer as *mut u8, ip.len()) };
+        ip.len() as i32
+    }
+
+    #[tokio::test]
+    async fn shared_resolver_cache() {
+        let shared = SharedResolvers::default();
+        let config = DnsConfig {
+            cache_ttl_ms: 60000,
+            ..Default::default()
+        };
+
+        // 每次连接取到的是同一个解析器, 缓存在连接之间生效
+        for _ in 0..3 {
+            let resolver = shared
+                .get(&config, Some(lookup), std::ptr::null_mut())
+                .unwrap();
+            let addrs = resolver.lookup("ws.example.com").await.unwrap();
+            assert_eq!(addrs, vec![SocketAddr::from(([10, 0, 0, 1], 0))]);
+        }
+        assert_eq!(LOOKUPS.load(Ordering::SeqCst), 1);
+
+        let first = shared
+            .get(&config, Some(lookup), std::ptr::null_mut())
+            .unwrap();
+        let same = shared
+            .get(&config, Some(lookup), std::ptr::null_mut())
+            .unwrap();
+        assert!(Arc::ptr_eq(&first.state, &same.state));
+
+        // 配置或解析回调不同时使用不同的解析器
+        let without_callback = shared.get(&config, None, std::ptr::null_mut()).unwrap();
+        assert!(!Arc::ptr_eq(&first.state, &without_callback.state));
+        let other_config = DnsConfig {
+            ip_family: IpFamily::Ipv4Only,
+            ..config.clone()
+        };
+        let other = shared
+            .get(&other_config, Some(lookup), std::ptr::null_mut())
+            .unwrap();
+        assert!(!Arc::ptr_eq(&first.state, &other.state));
+
+        let invalid = DnsConfig {
+            hosts: HashMap::from([("a".to_string(), vec!["x".to_string()])]),
+            ..Default::default()
+        };
+        assert!(shared.get(&invalid, None, std::ptr::null_mut()).is_err());
+    }
+}
@@ -0,0 +1,430 @@
+use super::dns::DohConfig;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+// 编码后域名的最大长度
+const MAX_NAME_LEN: usize = 255;
+
+/// DNS over HTTPS 解析器(RFC 8484), 使用 POST application/dns-message
+/// 内部使用独立的reqwest客户端, 不经过自定义的DNS解析, 避免递归
+/// WebSocket及TCP连接通过共享的解析器复用同一个客户端及其TLS连接
+pub struct DohResolver {
+    client: reqwest::Client,
+    url: reqwest::Url,
+}
+
+impl DohResolver {
+    pub fn new(config: &DohConfig) -> Result<DohResolver, String> {
+        let url = reqwest::Url::parse(&config.url).map_err(|err| err.to_string())?;
+        let timeout = if config.timeout_ms > 0 {
+            Duration::from_millis(config.timeout_ms)
+        } else {
+            Duration::from_millis(5000)
+        };
+        let mut builder = reqwest::Client::builder().timeout(timeout);
+
+        // 通过bootstrap IP连接DoH服务器, 无需先解析其域名
+        if !config.bootstrap_ips.is_empty() {
+            let host = url.host_str().ok_or("doh url has no host")?;
+            let port = url.port_or_known_default().unwrap_or(443);
+            let addrs = config
+                .bootstrap_ips
+                .iter()
+                .map(|ip| {
+                    ip.parse::<IpAddr>()
+                        .map(|ip| SocketAddr::new(ip, port))
+                        .map_err(|_| format!("invalid doh bootstrap ip: {}", ip))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            builder = builder.resolve_to_addrs(host, &addrs);
+        }
+        for pem in &config.root_certificates {
+            let cert =
+                reqwest::Certificate::from_pem(pem.as_bytes()).map_err(|err| err.to_string())?;
+            builder = builder.add_root_certificate(cert);
+        }
+
+        let client = builder.build().map_err(|err| err.to_string())?;
+        Ok(DohResolver { client, url })
+    }
+
+    /// 同时查询A与AAAA记录, 返回IP及最小的TTL
+    pub async fn lookup(&self, host: &str) -> io::Result<(Vec<IpAddr>, Duration)> {
+        let (v4, v6) = tokio::join!(self.query(host, TYPE_A), self.query(host, TYPE_AAAA));
+        let ((v4_ips, v4_ttl), (v6_ips, v6_ttl)) = match (v4, v6) {
+            (Err(err), Err(_)) => return Err(err),
+            (v4, v6) => (v4.unwrap_or_default(), v6.unwrap_or_default()),
+        };
+        // 无记录时TTL为0
+        let ttl = match (v4_ips.is_empty(), v6_ips.is_empty()) {
+            (false, false) => v4_ttl.min(v6_ttl),
+            (false, true) => v4_ttl,
+            _ => v6_ttl,
+        };
+        Ok(([v4_ips, v6_ips].concat(), Duration::from_secs(ttl as u64)))
+    }
+
+    async fn query(&self, host: &str, record_type: u16) -> io::Result<(Vec<IpAddr>, u32)> {
+        let message = encode_query(host, record_type)?;
+        let response = self
+            .client
+            .post(self.url.clone())
+            .header("content-type", "application/dns-message")
+            .header("accept", "application/dns-message")
+            .body(message)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(io::Error::other)?;
+        let body = response.bytes().await.map_err(io::Error::other)?;
+        decode_response(&body, record_type)
+    }
+}
+
+/// 编码查询报文, ID固定为0以便HTTP缓存
+fn encode_query(host: &str, record_type: u16) -> io::Result<Vec<u8>> {
+    let mut message = Vec::with_capacity(host.len() + 18);
+    // ID, 标志位(RD), QDCOUNT=1, ANCOUNT, NSCOUNT, ARCOUNT
+    message.extend_from_slice(&[0, 0, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(invalid_data("invalid host name"));
+        }
+        message.push(label.len() as u8);
+        message.extend_from_slice(label.as_bytes());
+    }
+    message.push(0);
+    if message.len() - 12 > MAX_NAME_LEN {
+        return Err(invalid_data("host name too long"));
+    }
+    message.extend_from_slice(&record_type.to_be_bytes());
+    message.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(message)
+}
+
+/// 解析应答报文, 只取与查询类型一致的记录, 忽略CNAME等其它记录
+fn decode_response(message: &[u8], record_type: u16) -> io::Result<(Vec<IpAddr>, u32)> {
+    if message.len() < 12 {
+        return Err(invalid_data("dns message too short"));
+    }
+    // QR位为0表示不是应答
+    if message[2] & 0x80 == 0 {
+        return Err(invalid_data("not a dns response"));
+    }
+    // TC位表示应答被截断, DoH不应出现
+    if message[2] & 0x02 != 0 {
+        return Err(invalid_data("dns response truncated"));
+    }
+    let rcode = message[3] & 0x0f;
+    // NXDOMAIN 视为无记录
+    if rcode == 3 {
+        return Ok((Vec::new(), 0));
+    }
+    if rcode != 0 {
+        return Err(invalid_data(&format!("dns response code {}", rcode)));
+    }
+    let question_count = read_u16(message, 4)?;
+    let answer_count = read_u16(message, 6)?;
+
+    let mut pos = 12;
+    for _ in 0..question_count {
+        pos = skip_name(message, pos)? + 4;
+    }
+
+    let mut ips = Vec::new();
+    let mut min_ttl = u32::MAX;
+    for _ in 0..answer_count {
+        pos = skip_name(message, pos)?;
+        let answer_type = read_u16(message, pos)?;
+        let ttl = read_u32(message, pos + 4)?;
+        let data_len = read_u16(message, pos + 8)? as usize;
+        let data = message
+            .get(pos + 10..pos + 10 + data_len)
+            .ok_or_else(|| invalid_data("dns record truncated"))?;
+        pos += 10 + data_len;
+
+        let ip = match (answer_type, data.len()) {
+            (TYPE_A, 4) if record_type == TYPE_A => {
+                IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3]))
+            }
+            (TYPE_AAAA, 16) if record_type == TYPE_AAAA => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(data);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            _ => continue,
+        };
+        ips.push(ip);
+        min_ttl = min_ttl.min(ttl);
+    }
+    if ips.is_empty() {
+        min_ttl = 0;
+    }
+    Ok((ips, min_ttl))
+}
+
+/// 跳过域名, 支持压缩指针
+/// 压缩指针之后域名即结束, 不需要跟随指针, 因此不会因指针成环而死循环
+fn skip_name(message: &[u8], mut pos: usize) -> io::Result<usize> {
+    loop {
+        let len = *message
+            .get(pos)
+            .ok_or_else(|| invalid_data("dns name truncated"))?;
+        match len {
+            0 => return Ok(pos + 1),
+            len if len & 0xc0 == 0xc0 => {
+                if pos + 2 > message.len() {
+                    return Err(invalid_data("dns name truncated"));
+                }
+                return Ok(pos + 2);
+            }
+            // 0x40、0x80 为保留的标签类型
+            len if len & 0xc0 != 0 => return Err(invalid_data("invalid dns label")),
+            len => pos += 1 + len as usize,
+        }
+    }
+}
+
+fn read_u16(message: &[u8], pos: usize) -> io::Result<u16> {
+    message
+        .get(pos..pos + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| invalid_data("dns message truncated"))
+}
+
+fn read_u32(message: &[u8], pos: usize) -> io::Result<u32> {
+    message
+        .get(pos..pos + 4)
+        .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| invalid_data("dns message truncated"))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::dns::{DnsConfig, SharedResolvers};
+    use super::*;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Server};
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const TYPE_CNAME: u16 = 5;
+
+    /// 构造应答报文, 问题为 www.example.com, answers 为 (名字, 类型, TTL, 数据)
+    fn response(rcode: u8, record_type: u16, answers: &[(&[u8], u16, u32, &[u8])]) -> Vec<u8> {
+        let mut message = encode_query("www.example.com", record_type).unwrap();
+        message[2] = 0x81;
+        message[3] = 0x80 | rcode;
+        message[7] = answers.len() as u8;
+        for (name, answer_type, ttl, data) in answers {
+            message.extend_from_slice(name);
+            message.extend_from_slice(&answer_type.to_be_bytes());
+            message.extend_from_slice(&CLASS_IN.to_be_bytes());
+            message.extend_from_slice(&ttl.to_be_bytes());
+            message.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            message.extend_from_slice(data);
+        }
+        message
+    }
+
+    // 指向问题中 www.example.com 的压缩指针
+    const QUESTION_NAME: &[u8] = &[0xc0, 12];
+
+    #[test]
+    fn encode() {
+        let message = encode_query("example.com.", TYPE_AAAA).unwrap();
+        assert_eq!(
+            message,
+            b"\0\0\x01\0\0\x01\0\0\0\0\0\0\x07example\x03com\0\0\x1c\0\x01"
+        );
+        assert!(encode_query("www..example.com", TYPE_A).is_err());
+        assert!(encode_query(&"a".repeat(64), TYPE_A).is_err());
+        assert!(encode_query(&vec!["a".repeat(63); 4].join("."), TYPE_A).is_err());
+        assert!(encode_query(&vec!["a".repeat(62); 4].join("."), TYPE_A).is_ok());
+    }
+
+    #[test]
+    fn decode_a() {
+        let message = response(
+            0,
+            TYPE_A,
+            &[
+                (QUESTION_NAME, TYPE_A, 300, &[1, 2, 3, 4]),
+                (QUESTION_NAME, TYPE_A, 60, &[5, 6, 7, 8]),
+            ],
+        );
+        let (ips, ttl) = decode_response(&message, TYPE_A).unwrap();
+        assert_eq!(
+            ips,
+            vec![IpAddr::from([1, 2, 3, 4]), IpAddr::from([5, 6, 7, 8])]
+        );
+        assert_eq!(ttl, 60);
+    }
+
+    #[test]
+    fn decode_aaaa() {
+        let ip = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        let message = response(
+            0,
+            TYPE_AAAA,
+            &[(QUESTION_NAME, TYPE_AAAA, 120, &ip.octets())],
+        );
+        let (ips, ttl) = decode_response(&message, TYPE_AAAA).unwrap();
+        assert_eq!(ips, vec![IpAddr::V6(ip)]);
+        assert_eq!(ttl, 120);
+        // 查询类型不一致的记录被忽略
+        assert_eq!(decode_response(&message, TYPE_A).unwrap(), (vec![], 0));
+    }
+
+    #[test]
+    fn decode_cname_chain() {
+        // www.example.com CNAME a.example.com, a.example.com CNAME b.example.com, b.example.com A
+        // 问题中 example.com 位于偏移16
+        let a_name = b"\x01a\xc0\x10";
+        let b_name = b"\x01b\xc0\x10";
+        let mut message = response(0, TYPE_A, &[(QUESTION_NAME, TYPE_CNAME, 30, a_name)]);
+        // 第一条记录的数据(a.example.com)位于偏移45
+        let a_pointer: &[u8] = &[0xc0, 45];
+        let mut rest = response(
+            0,
+            TYPE_A,
+            &[
+                (a_pointer, TYPE_CNAME, 40, b_name),
+                (b"\x01b\x07example\x03com\0", TYPE_A, 50, &[9, 9, 9, 9]),
+            ],
+        );
+        message.extend(rest.split_off(33));
+        message[7] = 3;
+
+        let (ips, ttl) = decode_response(&message, TYPE_A).unwrap();
+        assert_eq!(ips, vec![IpAddr::from([9, 9, 9, 9])]);
+        // 只取地址记录的TTL
+        assert_eq!(ttl, 50);
+    }
+
+    #[test]
+    fn decode_nxdomain() {
+        let message = response(3, TYPE_A, &[]);
+        assert_eq!(decode_response(&message, TYPE_A).unwrap(), (vec![], 0));
+        let message = response(2, TYPE_A, &[]);
+        assert!(decode_response(&message, TYPE_A).is_err());
+    }
+
+    #[test]
+    fn decode_malformed() {
+        let message = response(0, TYPE_A, &[(QUESTION_NAME, TYPE_A, 300, &[1, 2, 3, 4])]);
+        // 任意截断都返回错误
+        for len in 0..message.len() {
+            assert!(decode_response(&message[..len], TYPE_A).is_err(), "{}", len);
+        }
+
+        // 不是应答
+        let mut query = message.clone();
+        query[2] = 0x01;
+        assert!(decode_response(&query, TYPE_A).is_err());
+
+        // TC位
+        let mut truncated = message.clone();
+        truncated[2] |= 0x02;
+        assert!(decode_response(&truncated, TYPE_A).is_err());
+
+        // 记录数多于实际
+        let mut count = message.clone();
+        count[7] = 0xff;
+        assert!(decode_response(&count, TYPE_A).is_err());
+
+        // 数据长度超出报文
+        let mut data_len = message.clone();
+        let len = data_len.len();
+        data_len[len - 5] = 0xff;
+        assert!(decode_response(&data_len, TYPE_A).is_err());
+
+        // 保留的标签类型
+        let mut label = message.clone();
+        label[33] = 0x40;
+        assert!(decode_response(&label, TYPE_A).is_err());
+
+        // 自指向的压缩指针不会死循环
+        let looped = response(0, TYPE_A, &[(&[0xc0, 33], TYPE_A, 300, &[1, 2, 3, 4])]);
+        assert!(decode_response(&looped, TYPE_A).is_ok());
+    }
+
+    #[tokio::test]
+    async fn lookup_local_server() {
+        let service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|request: hyper::Request<Body>| async move {
+                let query = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                let record_type = read_u16(&query, query.len() - 4).unwrap();
+                let message = match record_type {
+                    TYPE_A => response(0, TYPE_A, &[(QUESTION_NAME, TYPE_A, 300, &[1, 2, 3, 4])]),
+                    _ => response(
+                        0,
+                        TYPE_AAAA,
+                        &[(QUESTION_NAME, TYPE_AAAA, 100, &Ipv6Addr::LOCALHOST.octets())],
+                    ),
+                };
+                Ok::<_, Infallible>(hyper::Response::new(Body::from(message)))
+            }))
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(service);
+        let url = format!("http://{}/dns-query", server.local_addr());
+        tokio::spawn(server);
+
+        let resolver = DohResolver::new(&DohConfig {
+            url,
+            ..Default::default()
+        })
+        .unwrap();
+        let (ips, ttl) = resolver.lookup("www.example.com").await.unwrap();
+        assert_eq!(
+            ips,
+            vec![IpAddr::from([1, 2, 3, 4]), IpAddr::V6(Ipv6Addr::LOCALHOST)]
+        );
+        assert_eq!(ttl, Duration::from_secs(100));
+    }
+
+    #[tokio::test]
+    async fn shared_doh_client() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let server_connections = connections.clone();
+        let service = make_service_fn(move |_| {
+            server_connections.fetch_add(1, Ordering::SeqCst);
+            async {
+                Ok::<_, Infallible>(service_fn(|_: hyper::Request<Body>| async {
+                    let message =
+                        response(0, TYPE_A, &[(QUESTION_NAME, TYPE_A, 300, &[1, 2, 3, 4])]);
+                    Ok::<_, Infallible>(hyper::Response::new(Body::from(message)))
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(service);
+        let config = DnsConfig {
+            doh: Some(DohConfig {
+                url: format!("http://{}/dns-query", server.local_addr()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        tokio::spawn(server);
+
+        // 两次连接取到同一个解析器, 第二次查询复用第一次建立的DoH连接
+        let resolvers = SharedResolvers::default();
+        let resolver = resolvers.get(&config, None, std::ptr::null_mut()).unwrap();
+        resolver.lookup("a.example.com").await.unwrap();
+        let established = connections.load(Ordering::SeqCst);
+        assert!(established > 0);
+
+        let resolver = resolvers.get(&config, None, std::ptr::null_mut()).unwrap();
+        resolver.lookup("b.example.com").await.unwrap();
+        assert_eq!(connections.load(Ordering::SeqCst), established);
+    }
+}
@@ -0,0 +1,498 @@
+use super::{version_to_i32, RespResultType};
+use crate::logger::is_sensitive_header;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Request;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// HAR抓包配置, 以json形式传入 rust_net_http_set_har_capture
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct HarConfig {
+    // 是否记录请求与响应的body
+    pub include_bodies: bool,
+    // 单个body记录的最大字节数, 超出的部分截断 0表示不限制
+    pub max_body_size: usize,
+    // 最多保留的请求数, 超出时丢弃最早的 0表示不限制
+    pub max_entries: usize,
+    // 不为空时每个请求完成后自动写入该文件
+    pub path: String,
+    // 自动写入的最小间隔(毫秒), 间隔内完成的请求在间隔结束时一起写入
+    pub save_interval_ms: u64,
+    // 除 authorization、cookie 等之外需要脱敏的请求头与响应头
+    pub redact_headers: Vec<String>,
+}
+
+impl Default for HarConfig {
+    fn default() -> Self {
+        HarConfig {
+            include_bodies: false,
+            max_body_size: 1024 * 1024,
+            max_entries: 1000,
+            path: String::new(),
+            save_interval_ms: 0,
+            redact_headers: Vec::new(),
+        }
+    }
+}
+
+/// 发送时记下的请求, 完成时与响应一起生成HAR entry
+pub struct HarRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    content_type: String,
+    body: Option<Vec<u8>>,
+    body_size: usize,
+}
+
+/// 按HAR 1.2格式记录客户端的请求
+pub struct HarCapture {
+    config: HarConfig,
+    entries: Mutex<VecDeque<Value>>,
+    last_save: Mutex<Option<Instant>>,
+    // 已安排但还未开始的自动写入, 期间完成的请求合并到这次写入
+    save_pending: AtomicBool,
+    // 保证同一时间只有一个写入
+    save_lock: Mutex<()>,
+}
+
+impl HarCapture {
+    pub fn new(config: HarConfig) -> HarCapture {
+        HarCapture {
+            config,
+            entries: Mutex::new(VecDeque::new()),
+            last_save: Mutex::new(None),
+            save_pending: AtomicBool::new(false),
+            save_lock: Mutex::new(()),
+        }
+    }
+
+    /// 记录即将发送的请求, 需要在body被替换为流之前调用
+    pub fn capture_request(&self, request: &Request) -> HarRequest {
+        let body = request.body().and_then(|body| body.as_bytes());
+        HarRequest {
+            method: request.method().as_str().to_string(),
+            url: request.url().to_string(),
+            headers: request
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    let value = String::from_utf8_lossy(value.as_bytes());
+                    (
+                        name.as_str().to_string(),
+                        self.redact(name.as_str(), &value),
+                    )
+                })
+                .collect(),
+            content_type: request
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string(),
+            body: body
+                .filter(|_| self.config.include_bodies)
+                .map(|body| self.truncate(body).to_vec()),
+            body_size: body.map(|body| body.len()).unwrap_or_default(),
+        }
+    }
+
+    pub fn add(
+        self: &Arc<Self>,
+        key: u64,
+        request: HarRequest,
+        resp: &RespResultType,
+        timings: Value,
+        total: f64,
+    ) {
+        let total = total.max(0.0);
+        let started = SystemTime::now()
+            .checked_sub(Duration::from_secs_f64(total / 1000.0))
+            .unwrap_or_else(SystemTime::now);
+
+        let (response, http_version) = match resp {
+            RespResultType::Data(data) => {
+                let version = http_version(version_to_i32(data.version));
+                let headers = json_map(&data.headers);
+                let mime_type = headers
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+                    .map(|(_, value)| value.clone())
+                    .unwrap_or_default();
+                let mut content = json!({
+                    "size": data.data.len(),
+                    "mimeType": mime_type,
+                });
+                if self.config.include_bodies {
+                    body_text(&mut content, self.truncate(&data.data));
+                }
+                let response = json!({
+                    "status": data.status,
+                    "statusText": reqwest::StatusCode::from_u16(data.status)
+                        .ok()
+                        .and_then(|status| status.canonical_reason())
+                        .unwrap_or_default(),
+                    "httpVersion": version,
+                    // 来自 Set-Cookie, 与响应头使用同样的脱敏规则
+                    "cookies": name_values(json_map(&data.cookies).into_iter().map(|(name, value)| {
+                        let value = self.redact("set-cookie", &value);
+                        (name, value)
+                    })),
+                    "headers": name_values(headers.into_iter().map(|(name, value)| {
+                        let value = self.redact(&name, &value);
+                        (name, value)
+                    })),
+                    "content": content,
+                    "redirectURL": "",
+                    "headersSize": -1,
+                    "bodySize": data.data.len(),
+                });
+                (response, version)
+            }
+            // 请求失败时没有响应, 状态为0
+            RespResultType::Error(error) => {
+                let response = json!({
+                    "status": 0,
+                    "statusText": "",
+                    "httpVersion": "",
+                    "cookies": [],
+                    "headers": [],
+                    "content": {"size": 0, "mimeType": ""},
+                    "redirectURL": "",
+                    "headersSize": -1,
+                    "bodySize": -1,
+                    "_error": error,
+                });
+                (response, "HTTP/1.1")
+            }
+        };
+
+        let query = reqwest::Url::parse(&request.url)
+            .map(|url| {
+                url.query_pairs()
+                    .map(|(name, value)| (name.into_owned(), value.into_owned()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let mut har_request = json!({
+            "method": request.method,
+            "url": request.url,
+            "httpVersion": http_version,
+            "cookies": [],
+            "headers": name_values(request.headers.into_iter()),
+            "queryString": name_values(query.into_iter()),
+            "headersSize": -1,
+            "bodySize": request.body_size,
+        });
+        if let Some(body) = &request.body {
+            let mut post_data = json!({"mimeType": request.content_type});
+            body_text(&mut post_data, body);
+            har_request["postData"] = post_data;
+        }
+
+        let entry = json!({
+            "startedDateTime": iso8601(started),
+            "time": total,
+            "request": har_request,
+            "response": response,
+            "cache": {},
+            "timings": timings,
+            "_key": key,
+        });
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_back(entry);
+        if self.config.max_entries > 0 && entries.len() > self.config.max_entries {
+            entries.pop_front();
+        }
+        drop(entries);
+        self.auto_save();
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// HAR 1.2 json
+    pub fn to_json(&self) -> String {
+        let entries = self.entries.lock().unwrap();
+        let har = json!({
+            "log": {
+                "version": "1.2",
+                "creator": {"name": "rust_net", "version": env!("CARGO_PKG_VERSION")},
+                "pages": [],
+                "entries": *entries,
+            }
+        });
+        har.to_string()
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+
+    /// 距离上次写入不足 save_interval_ms 时延迟到间隔结束再写入, 最后完成的请求也会写入文件
+    /// 在阻塞线程池中写入文件, 不占用tokio工作线程
+    fn auto_save(self: &Arc<Self>) {
+        if self.config.path.is_empty() {
+            return;
+        }
+        if self.save_pending.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let interval = Duration::from_millis(self.config.save_interval_ms);
+        let delay = match *self.last_save.lock().unwrap() {
+            Some(last_save) => interval.saturating_sub(last_save.elapsed()),
+            None => Duration::ZERO,
+        };
+        let har = self.clone();
+        tokio::spawn(async move {
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            let _ = tokio::task::spawn_blocking(move || {
+                let _guard = har.save_lock.lock().unwrap();
+                *har.last_save.lock().unwrap() = Some(Instant::now());
+                har.save_pending.store(false, Ordering::Release);
+                if let Err(err) = har.save(&har.config.path) {
+                    log::warn!("save har to {} failed: {}", har.config.path, err);
+                }
+            })
+            .await;
+        });
+    }
+
+    fn redact(&self, name: &str, value: &str) -> String {
+        let redacted = is_sensitive_header(name)
+            || self
+                .config
+                .redact_headers
+                .iter()
+                .any(|header| name.eq_ignore_ascii_case(header));
+        if redacted {
+            "***".into()
+        } else {
+            value.into()
+        }
+    }
+
+    fn truncate<'a>(&self, body: &'a [u8]) -> &'a [u8] {
+        match self.config.max_body_size {
+            0 => body,
+            max => &body[..body.len().min(max)],
+        }
+    }
+}
+
+/// 文本body直接记录, 二进制body以base64记录
+fn body_text(content: &mut Value, body: &[u8]) {
+    match std::str::from_utf8(body) {
+        Ok(text) => content["text"] = json!(text),
+        Err(_) => {
+            use base64::Engine;
+            content["text"] = json!(base64::engine::general_purpose::STANDARD.encode(body));
+            content["encoding"] = json!("base64");
+        }
+    }
+}
+
+fn json_map(json: &str) -> Vec<(String, String)> {
+    let mut map = serde_json::from_str::<HashMap<String, String>>(json)
+        .unwrap_or_default()
+        .into_iter()
+        .collect::<Vec<_>>();
+    map.sort();
+    map
+}
+
+fn name_values(values: impl Iterator<Item = (String, String)>) -> Value {
+    values
+        .map(|(name, value)| json!({"name": name, "value": value}))
+        .collect()
+}
+
+fn http_version(version: i32) -> &'static str {
+    match version {
+        9 => "HTTP/0.9",
+        10 => "HTTP/1.0",
+        20 => "HTTP/2",
+        30 => "HTTP/3",
+        _ => "HTTP/1.1",
+    }
+}
+
+/// UTC时间, 例如 2024-01-02T03:04:05.678Z
+fn iso8601(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+
+    // 由天数计算年月日
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::ResponseData;
+    use reqwest::{Method, Url, Version};
+
+    fn capture(path: &str, save_interval_ms: u64) -> Arc<HarCapture> {
+        Arc::new(HarCapture::new(HarConfig {
+            path: path.to_string(),
+            save_interval_ms,
+            redact_headers: vec!["X-Signature".to_string()],
+            ..Default::default()
+        }))
+    }
+
+    fn add(har: &Arc<HarCapture>, key: u64) {
+        let mut request = Request::new(
+            Method::GET,
+            Url::parse("https://example.com/a?b=1").unwrap(),
+        );
+        request
+            .headers_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        request
+            .headers_mut()
+            .insert("x-signature", "abc".parse().unwrap());
+        let request = har.capture_request(&request);
+        let data = ResponseData {
+            status: 200,
+            data: b"ok".to_vec(),
+            version: Version::HTTP_11,
+            cookies: r#"{"session":"secret"}"#.to_string(),
+            headers: r#"{"set-cookie":"session=secret","content-type":"text/plain"}"#.to_string(),
+            remote_addr: None,
+        };
+        har.add(key, request, &RespResultType::Data(data), json!({}), 1.0);
+    }
+
+    fn values(entry: &Value, path: &str) -> Vec<(String, String)> {
+        entry
+            .pointer(path)
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|value| {
+                (
+                    value["name"].as_str().unwrap().to_string(),
+                    value["value"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn redact() {
+        let har = capture("", 0);
+        add(&har, 1);
+        let json: Value = serde_json::from_str(&har.to_json()).unwrap();
+        let entry = &json["log"]["entries"][0];
+        assert_eq!(
+            values(entry, "/request/headers"),
+            vec![
+                ("authorization".to_string(), "***".to_string()),
+                ("x-signature".to_string(), "***".to_string()),
+            ]
+        );
+        assert_eq!(
+            values(entry, "/response/headers"),
+            vec![
+                ("content-type".to_string(), "text/plain".to_string()),
+                ("set-cookie".to_string(), "***".to_string()),
+            ]
+        );
+        assert_eq!(
+            values(entry, "/response/cookies"),
+            vec![("session".to_string(), "***".to_string())]
+        );
+        assert!(!har.to_json().contains("secret"));
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("rust_net_{}_{}.json", name, std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    /// 写入在阻塞线程池中进行, 等待文件中的请求数达到 expected, 返回最后读到的请求数
+    async fn wait_saved(har: &HarCapture, path: &str, expected: usize) -> usize {
+        let mut entries = 0;
+        for _ in 0..100 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let _guard = har.save_lock.lock().unwrap();
+            let Ok(json) = std::fs::read_to_string(path) else {
+                continue;
+            };
+            let json: Value = serde_json::from_str(&json).unwrap();
+            entries = json["log"]["entries"].as_array().unwrap().len();
+            if entries == expected && !har.save_pending.load(Ordering::Acquire) {
+                break;
+            }
+        }
+        entries
+    }
+
+    fn saved_entries(path: &str) -> usize {
+        let json: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        json["log"]["entries"].as_array().unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn auto_save() {
+        let path = temp_path("har_auto_save");
+        let har = capture(&path, 0);
+        for key in 0..10 {
+            add(&har, key);
+        }
+        let entries = wait_saved(&har, &path, 10).await;
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(entries, 10);
+    }
+
+    #[tokio::test]
+    async fn auto_save_trailing() {
+        let path = temp_path("har_auto_save_trailing");
+        let har = capture(&path, 300);
+        add(&har, 0);
+        assert_eq!(wait_saved(&har, &path, 1).await, 1);
+
+        // 间隔内完成的两个请求不会立即写入, 但在间隔结束后写入
+        add(&har, 1);
+        add(&har, 2);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(saved_entries(&path), 1);
+
+        let entries = wait_saved(&har, &path, 3).await;
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(entries, 3);
+    }
+}
@@ -0,0 +1,360 @@
+use super::RequestContext;
+use reqwest::{Method, Request, Response, Version};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// HTTP/3失败后 该host在这段时间内直接走HTTP/2或HTTP/1.1
+const BROKEN_EXPIRES: Duration = Duration::from_secs(300);
+
+/// HTTP/3 请求及失败回退
+/// 幂等请求先尝试以HTTP/3发送, 失败或超时后回退到HTTP/2或HTTP/1.1(由ALPN协商)
+/// 非幂等请求可能已被服务器处理, 只发往已确认支持HTTP/3的host, 且只在QUIC连接建立失败时回退
+pub struct Http3Fallback {
+    // 只使用HTTP/3的客户端
+    client: reqwest::Client,
+    timeout: Duration,
+    broken_hosts: Mutex<HashMap<String, Instant>>,
+    // HTTP/3请求成功过的host
+    h3_hosts: Mutex<HashSet<String>>,
+}
+
+impl Http3Fallback {
+    pub fn new(client: reqwest::Client, timeout: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            client,
+            timeout,
+            broken_hosts: Mutex::new(HashMap::new()),
+            h3_hosts: Mutex::new(HashSet::new()),
+        })
+    }
+
+    fn is_broken(&self, host: &str) -> bool {
+        let mut broken_hosts = self.broken_hosts.lock().unwrap();
+        match broken_hosts.get(host) {
+            Some(time) if time.elapsed() < BROKEN_EXPIRES => true,
+            Some(_) => {
+                broken_hosts.remove(host);
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn mark_broken(&self, host: &str) {
+        self.h3_hosts.lock().unwrap().remove(host);
+        self.broken_hosts
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), Instant::now());
+    }
+
+    fn is_h3_host(&self, host: &str) -> bool {
+        self.h3_hosts.lock().unwrap().contains(host)
+    }
+
+    fn mark_h3_host(&self, host: &str) {
+        self.h3_hosts.lock().unwrap().insert(host.to_string());
+    }
+
+    pub async fn send(
+        &self,
+        client: &reqwest::Client,
+        request: Request,
+        host: &str,
+        context: &RequestContext,
+    ) -> Result<Response, reqwest::Error> {
+        // QUIC只用于https
+        if self.is_broken(host) || request.url().scheme() != "https" {
+            return super::execute(client, request, context).await;
+        }
+        let idempotent = is_idempotent(request.method());
+        if !idempotent && !self.is_h3_host(host) {
+            return super::execute(client, request, context).await;
+        }
+        // body为流时无法重放, 只能走默认协议
+        let mut h3_request = match request.try_clone() {
+            Some(h3_request) => h3_request,
+            None => return super::execute(client, request, context).await,
+        };
+
+        *h3_request.version_mut() = Version::HTTP_3;
+        let h3_response = super::execute(&self.client, h3_request, context);
+        if !idempotent {
+            // 请求可能已经到达服务器, 超时或其它错误直接返回, 不能重发
+            return match h3_response.await {
+                Err(err) if is_connect_error(&err) => {
+                    log::warn!("http3 connect failed, fallback: {}", err);
+                    self.mark_broken(host);
+                    super::execute(client, request, context).await
+                }
+                response_result => response_result,
+            };
+        }
+        match tokio::time::timeout(self.timeout, h3_response).await {
+            Ok(Ok(response)) => {
+                self.mark_h3_host(host);
+                return Ok(response);
+            }
+            Ok(Err(err)) => {
+                log::warn!("http3 request failed, fallback: {}", err);
+            }
+            Err(_) => {
+                log::warn!("http3 request timeout, fallback");
+            }
+        }
+        self.mark_broken(host);
+        super::execute(client, request, context).await
+    }
+}
+
+/// 重复发送不会改变服务器状态的请求
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
+/// QUIC连接建立失败, 请求还没有发出
+fn is_connect_error(err: &reqwest::Error) -> bool {
+    match std::error::Error::source(err) {
+        Some(source) => source.is::<quinn::ConnectError>() || source.is::<quinn::ConnectionError>(),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tls;
+    use bytes::{Buf, Bytes};
+    use hyper::server::conn::Http;
+    use hyper::service::service_fn;
+    use hyper::Body;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsAcceptor;
+
+    const CERTIFICATE: &str = include_str!("testdata/localhost.crt");
+    const PRIVATE_KEY: &str = include_str!("testdata/localhost.key");
+
+    /// 服务端收到的 GET、POST 请求数
+    #[derive(Default)]
+    struct Received {
+        get: AtomicUsize,
+        post: AtomicUsize,
+    }
+
+    impl Received {
+        fn count(&self, method: &Method) {
+            match *method {
+                Method::POST => self.post.fetch_add(1, Ordering::SeqCst),
+                _ => self.get.fetch_add(1, Ordering::SeqCst),
+            };
+        }
+
+        fn get(&self) -> usize {
+            self.get.load(Ordering::SeqCst)
+        }
+
+        fn post(&self) -> usize {
+            self.post.load(Ordering::SeqCst)
+        }
+    }
+
+    fn server_config(alpn_protocol: &[u8]) -> rustls::ServerConfig {
+        let certs = rustls_pemfile::certs(&mut CERTIFICATE.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect();
+        let key = rustls_pemfile::pkcs8_private_keys(&mut PRIVATE_KEY.as_bytes())
+            .unwrap()
+            .remove(0);
+        let mut config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, rustls::PrivateKey(key))
+            .unwrap();
+        config.alpn_protocols = vec![alpn_protocol.to_vec()];
+        config
+    }
+
+    /// HTTPS(HTTP/1.1)服务端, 响应 "h1"
+    async fn serve_h1(listener: TcpListener) -> Arc<Received> {
+        let received = Arc::new(Received::default());
+        let acceptor = TlsAcceptor::from(Arc::new(server_config(b"http/1.1")));
+        let server_received = received.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                let received = server_received.clone();
+                tokio::spawn(async move {
+                    let stream = acceptor.accept(stream).await.unwrap();
+                    let service = service_fn(move |request: hyper::Request<Body>| {
+                        received.count(request.method());
+                        async { Ok::<_, Infallible>(hyper::Response::new(Body::from("h1"))) }
+                    });
+                    let _ = Http::new().serve_connection(stream, service).await;
+                });
+            }
+        });
+        received
+    }
+
+    /// HTTP/3服务端, GET 响应 "h3", POST 收到后一直不响应
+    fn serve_h3(addr: SocketAddr) -> Arc<Received> {
+        let received = Arc::new(Received::default());
+        let config = quinn::ServerConfig::with_crypto(Arc::new(server_config(b"h3")));
+        let endpoint = quinn::Endpoint::server(config, addr).unwrap();
+        let server_received = received.clone();
+        tokio::spawn(async move {
+            while let Some(connecting) = endpoint.accept().await {
+                let received = server_received.clone();
+                tokio::spawn(async move {
+                    let connection = h3_quinn::Connection::new(connecting.await.unwrap());
+                    let mut connection = h3::server::Connection::<_, Bytes>::new(connection)
+                        .await
+                        .unwrap();
+                    while let Ok(Some((request, mut stream))) = connection.accept().await {
+                        received.count(request.method());
+                        tokio::spawn(async move {
+                            while let Ok(Some(mut data)) = stream.recv_data().await {
+                                data.advance(data.remaining());
+                            }
+                            if request.method() == Method::POST {
+                                std::future::pending::<()>().await;
+                            }
+                            let response = hyper::Response::new(());
+                            stream.send_response(response).await.unwrap();
+                            stream.send_data(Bytes::from("h3")).await.unwrap();
+                            stream.finish().await.unwrap();
+                        });
+                    }
+                });
+            }
+        });
+        received
+    }
+
+    fn fallback(timeout: Duration) -> (reqwest::Client, Arc<Http3Fallback>) {
+        let root_certificates = [CERTIFICATE.to_string()];
+        let client = reqwest::Client::builder()
+            .use_preconfigured_tls(tls::client_config(&root_certificates, &["http/1.1"]).unwrap())
+            .build()
+            .unwrap();
+        let http3_client = reqwest::Client::builder()
+            .use_preconfigured_tls(tls::client_config(&root_certificates, &["h3"]).unwrap())
+            .http3_prior_knowledge()
+            .timeout(Duration::from_secs(1))
+            .build()
+            .unwrap();
+        (client, Http3Fallback::new(http3_client, timeout))
+    }
+
+    fn context() -> RequestContext {
+        RequestContext {
+            key: 0,
+            recorder: super::super::TimingRecorder::new(Instant::now()),
+            connections: Default::default(),
+            stats: Default::default(),
+            bandwidth: Default::default(),
+            interceptors: Default::default(),
+            signer: None,
+            auth: None,
+            credentials: None,
+            transport: None,
+            recorded_request: Default::default(),
+            har: None,
+            har_request: Default::default(),
+        }
+    }
+
+    async fn send(
+        client: &reqwest::Client,
+        fallback: &Http3Fallback,
+        method: Method,
+        url: &str,
+    ) -> Result<(Version, String), reqwest::Error> {
+        let request = client.request(method, url).body("body").build()?;
+        let response = fallback
+            .send(client, request, "127.0.0.1", &context())
+            .await?;
+        Ok((response.version(), response.text().await?))
+    }
+
+    #[tokio::test]
+    async fn fallback_without_quic() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("https://{}/", listener.local_addr().unwrap());
+        let h1 = serve_h1(listener).await;
+        let (client, fallback) = fallback(Duration::from_millis(500));
+
+        // 非幂等请求不尝试HTTP/3
+        let response = send(&client, &fallback, Method::POST, &url).await.unwrap();
+        assert_eq!(response, (Version::HTTP_11, "h1".to_string()));
+        assert!(!fallback.is_broken("127.0.0.1"));
+
+        // 幂等请求HTTP/3超时后回退
+        let response = send(&client, &fallback, Method::GET, &url).await.unwrap();
+        assert_eq!(response, (Version::HTTP_11, "h1".to_string()));
+        assert!(fallback.is_broken("127.0.0.1"));
+        assert_eq!((h1.get(), h1.post()), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn post_not_resent() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let url = format!("https://{}/", addr);
+        let h1 = serve_h1(listener).await;
+        let h3 = serve_h3(addr);
+        let (client, fallback) = fallback(Duration::from_secs(3));
+
+        // 确认支持HTTP/3之前 非幂等请求走HTTP/1.1
+        let response = send(&client, &fallback, Method::POST, &url).await.unwrap();
+        assert_eq!(response, (Version::HTTP_11, "h1".to_string()));
+
+        let response = send(&client, &fallback, Method::GET, &url).await.unwrap();
+        assert_eq!(response, (Version::HTTP_3, "h3".to_string()));
+        assert!(fallback.is_h3_host("127.0.0.1"));
+
+        // HTTP/3的POST已经送达但没有响应, 超时后不能重发
+        let err = send(&client, &fallback, Method::POST, &url)
+            .await
+            .unwrap_err();
+        assert!(err.is_timeout());
+        assert_eq!((h1.get(), h1.post()), (0, 1));
+        assert_eq!((h3.get(), h3.post()), (1, 1));
+        assert!(!fallback.is_broken("127.0.0.1"));
+    }
+
+    #[test]
+    fn broken_host_expires() {
+        let fallback = Http3Fallback::new(reqwest::Client::new(), Duration::from_secs(3));
+        assert!(!fallback.is_broken("a.example.com"));
+
+        fallback.mark_broken("a.example.com");
+        assert!(fallback.is_broken("a.example.com"));
+        assert!(!fallback.is_broken("b.example.com"));
+
+        // 标记时间回拨到过期之前
+        let expired = Instant::now()
+            .checked_sub(BROKEN_EXPIRES + Duration::from_secs(1))
+            .unwrap();
+        fallback
+            .broken_hosts
+            .lock()
+            .unwrap()
+            .insert("a.example.com".to_string(), expired);
+        assert!(!fallback.is_broken("a.example.com"));
+        assert!(fallback.broken_hosts.lock().unwrap().is_empty());
+
+        // 过期后再次失败会重新标记
+        fallback.mark_broken("a.example.com");
+        assert!(fallback.is_broken("a.example.com"));
+    }
+}
@@ -0,0 +1,340 @@
+use super::{headers_to_json, ResponseData};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Body, Method, Request, Url};
+use std::collections::HashMap;
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+
+/// 请求拦截回调, 在请求即将发送时(排队之后)于工作线程中调用
+/// 返回false时请求失败, 不再发送
+pub type RequestInterceptor =
+    Option<extern "C" fn(request: &mut InterceptedRequest, user_data: *mut c_void) -> bool>;
+
+/// 响应拦截回调, 在读取完body之后、写入请求结果之前于工作线程中调用
+pub type ResponseInterceptor =
+    Option<extern "C" fn(response: &mut InterceptedResponse, user_data: *mut c_void)>;
+
+/// 拦截回调中的请求, 只在回调期间有效
+pub struct InterceptedRequest {
+    key: u64,
+    request: Request,
+}
+
+/// 拦截回调中的响应, 只在回调期间有效
+pub struct InterceptedResponse {
+    key: u64,
+    data: ResponseData,
+}
+
+#[derive(Clone, Copy)]
+struct Callback<F> {
+    callback: F,
+    user_data: *mut c_void,
+}
+
+/// 客户端上注册的拦截器, 按注册顺序执行
+#[derive(Clone, Default)]
+pub struct Interceptors {
+    request: Vec<Callback<extern "C" fn(&mut InterceptedRequest, *mut c_void) -> bool>>,
+    response: Vec<Callback<extern "C" fn(&mut InterceptedResponse, *mut c_void)>>,
+}
+
+// user_data 由调用方保证可以在任意线程使用
+unsafe impl Send for Interceptors {}
+unsafe impl Sync for Interceptors {}
+
+impl Interceptors {
+    pub fn add_request(&mut self, callback: RequestInterceptor, user_data: *mut c_void) {
+        if let Some(callback) = callback {
+            self.request.push(Callback {
+                callback,
+                user_data,
+            });
+        }
+    }
+
+    pub fn add_response(&mut self, callback: ResponseInterceptor, user_data: *mut c_void) {
+        if let Some(callback) = callback {
+            self.response.push(Callback {
+                callback,
+                user_data,
+            });
+        }
+    }
+
+    /// 依次执行请求拦截器, 被拒绝时返回None
+    pub fn intercept_request(&self, key: u64, request: Request) -> Option<Request> {
+        if self.request.is_empty() {
+            return Some(request);
+        }
+        let mut intercepted = InterceptedRequest { key, request };
+        for callback in &self.request {
+            if !(callback.callback)(&mut intercepted, callback.user_data) {
+                return None;
+            }
+        }
+        Some(intercepted.request)
+    }
+
+    pub fn intercept_response(&self, key: u64, data: ResponseData) -> ResponseData {
+        if self.response.is_empty() {
+            return data;
+        }
+        let mut intercepted = InterceptedResponse { key, data };
+        for callback in &self.response {
+            (callback.callback)(&mut intercepted, callback.user_data);
+        }
+        intercepted.data
+    }
+}
+
+fn into_c_string(value: &str) -> *mut c_char {
+    match CString::new(value) {
+        Ok(cstr) => cstr.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// 请求的key, 与 rust_net_http_get 等返回的key一致
+#[no_mangle]
+pub extern "C" fn rust_net_http_intercepted_request_key(request: &mut InterceptedRequest) -> u64 {
+    request.key
+}
+
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_http_intercepted_request_get_method(
+    request: &mut InterceptedRequest,
+) -> *mut c_char {
+    into_c_string(request.request.method().as_str())
+}
+
+/// # Safety
+/// method 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_intercepted_request_set_method(
+    request: &mut InterceptedRequest,
+    method: *const c_char,
+) -> bool {
+    let method = CStr::from_ptr(method).to_str().unwrap();
+    match Method::from_bytes(method.as_bytes()) {
+        Ok(method) => {
+            *request.request.method_mut() = method;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// 完整的url, 包含query参数
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_http_intercepted_request_get_url(
+    request: &mut InterceptedRequest,
+) -> *mut c_char {
+    into_c_string(request.request.url().as_str())
+}
+
+/// # Safety
+/// url 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_intercepted_request_set_url(
+    request: &mut InterceptedRequest,
+    url: *const c_char,
+) -> bool {
+    let url = CStr::from_ptr(url).to_str().unwrap();
+    match Url::parse(url) {
+        Ok(url) => {
+            *request.request.url_mut() = url;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// 请求头json
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_http_intercepted_request_get_headers(
+    request: &mut InterceptedRequest,
+) -> *mut c_char {
+    into_c_string(&headers_to_json(request.request.headers()))
+}
+
+/// 设置请求头, 覆盖同名的请求头
+///
+/// # Safety
+/// key、value 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_intercepted_request_set_header(
+    request: &mut InterceptedRequest,
+    key: *const c_char,
+    value: *const c_char,
+) -> bool {
+    let key = CStr::from_ptr(key).to_str().unwrap();
+    let value = CStr::from_ptr(value).to_str().unwrap();
+    match (
+        HeaderName::from_bytes(key.as_bytes()),
+        HeaderValue::from_str(value),
+    ) {
+        (Ok(key), Ok(value)) => {
+            request.request.headers_mut().insert(key, value);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// # Safety
+/// key 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_intercepted_request_remove_header(
+    request: &mut InterceptedRequest,
+    key: *const c_char,
+) {
+    let key = CStr::from_ptr(key).to_str().unwrap();
+    request.request.headers_mut().remove(key);
+}
+
+/// 请求body, 返回的指针只在回调期间有效, 无需释放
+#[no_mangle]
+pub extern "C" fn rust_net_http_intercepted_request_get_body(
+    request: &mut InterceptedRequest,
+    len: &mut usize,
+) -> *const u8 {
+    match request.request.body().and_then(|body| body.as_bytes()) {
+        Some(bytes) => {
+            *len = bytes.len();
+            bytes.as_ptr()
+        }
+        None => {
+            *len = 0;
+            std::ptr::null()
+        }
+    }
+}
+
+/// # Safety
+/// data 可以为空, 不为空时必须指向至少 len 字节的可读内存
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_intercepted_request_set_body(
+    request: &mut InterceptedRequest,
+    data: *const u8,
+    len: usize,
+) {
+    let body = if data.is_null() {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(data, len).to_vec()
+    };
+    *request.request.body_mut() = Some(Body::from(body));
+}
+
+/// 请求的key, 与 rust_net_http_get 等返回的key一致
+#[no_mangle]
+pub extern "C" fn rust_net_http_intercepted_response_key(
+    response: &mut InterceptedResponse,
+) -> u64 {
+    response.key
+}
+
+#[no_mangle]
+pub extern "C" fn rust_net_http_intercepted_response_get_status(
+    response: &mut InterceptedResponse,
+) -> u16 {
+    response.data.status
+}
+
+#[no_mangle]
+pub extern "C" fn rust_net_http_intercepted_response_set_status(
+    response: &mut InterceptedResponse,
+    status: u16,
+) {
+    response.data.status = status;
+}
+
+/// 响应头json
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_http_intercepted_response_get_headers(
+    response: &mut InterceptedResponse,
+) -> *mut c_char {
+    into_c_string(&response.data.headers)
+}
+
+/// 设置响应头, 覆盖同名的响应头
+///
+/// # Safety
+/// key、value 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_intercepted_response_set_header(
+    response: &mut InterceptedResponse,
+    key: *const c_char,
+    value: *const c_char,
+) -> bool {
+    let key = CStr::from_ptr(key).to_str().unwrap();
+    let value = CStr::from_ptr(value).to_str().unwrap();
+    let (key, value) = match (
+        HeaderName::from_bytes(key.as_bytes()),
+        HeaderValue::from_str(value),
+    ) {
+        (Ok(key), Ok(value)) => (key, value),
+        _ => return false,
+    };
+    let mut headers = response_headers(&response.data);
+    headers.insert(key, value);
+    response.data.headers = headers_to_json(&headers);
+    true
+}
+
+/// # Safety
+/// key 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_intercepted_response_remove_header(
+    response: &mut InterceptedResponse,
+    key: *const c_char,
+) {
+    let key = CStr::from_ptr(key).to_str().unwrap();
+    let mut headers = response_headers(&response.data);
+    headers.remove(key);
+    response.data.headers = headers_to_json(&headers);
+}
+
+/// 响应body, 返回的指针只在回调期间有效, 无需释放
+#[no_mangle]
+pub extern "C" fn rust_net_http_intercepted_response_get_body(
+    response: &mut InterceptedResponse,
+    len: &mut usize,
+) -> *const u8 {
+    *len = response.data.data.len();
+    response.data.data.as_ptr()
+}
+
+/// # Safety
+/// data 可以为空, 不为空时必须指向至少 len 字节的可读内存
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_intercepted_response_set_body(
+    response: &mut InterceptedResponse,
+    data: *const u8,
+    len: usize,
+) {
+    response.data.data = if data.is_null() {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(data, len).to_vec()
+    };
+}
+
+fn response_headers(data: &ResponseData) -> HeaderMap {
+    let map = serde_json::from_str::<HashMap<String, String>>(&data.headers).unwrap_or_default();
+    let mut headers = HeaderMap::new();
+    for (key, value) in map {
+        if let (Ok(key), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            headers.insert(key, value);
+        }
+    }
+    headers
+}
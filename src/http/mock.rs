@@ -0,0 +1,234 @@
+use super::{version_to_i32, ResponseData};
+use reqwest::{Request, Url, Version};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::Mutex;
+
+/// 模拟传输配置, 以json形式传入 rust_net_http_set_mock_transport
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct MockConfig {
+    pub mode: MockMode,
+    // 记录文件, 每行一个请求与响应的json
+    pub path: String,
+    // 匹配method
+    pub match_method: bool,
+    // 匹配query参数, 关闭时只匹配scheme、host、port与path
+    pub match_query: bool,
+    // 匹配时忽略的query参数, 例如时间戳、nonce
+    pub ignore_params: Vec<String>,
+    // 匹配请求body的SHA256
+    pub match_body: bool,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        MockConfig {
+            mode: MockMode::Replay,
+            path: String::new(),
+            match_method: true,
+            match_query: true,
+            ignore_params: Vec::new(),
+            match_body: false,
+        }
+    }
+}
+
+#[derive(Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MockMode {
+    /// 正常发送请求, 并将请求与响应写入记录文件, 原有内容会被清空
+    Record,
+    /// 不发送请求, 从记录文件中返回匹配的响应
+    Replay,
+}
+
+/// 记录文件中的一行
+#[derive(Serialize, Deserialize)]
+struct Interaction {
+    method: String,
+    url: String,
+    body_sha256: String,
+    status: u16,
+    // 协议版本 同 rust_net_http_get_request_response 返回的 version
+    version: i32,
+    headers: serde_json::Value,
+    cookies: serde_json::Value,
+    // base64
+    body: String,
+}
+
+/// 发送时记下的请求信息, 收到响应后一起写入记录文件
+pub struct RecordedRequest {
+    method: String,
+    url: String,
+    body_sha256: String,
+}
+
+impl RecordedRequest {
+    pub fn new(request: &Request) -> RecordedRequest {
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .unwrap_or_default();
+        RecordedRequest {
+            method: request.method().as_str().to_string(),
+            url: request.url().to_string(),
+            body_sha256: hex::encode(Sha256::digest(body)),
+        }
+    }
+}
+
+/// 模拟传输: 录制真实的请求响应, 或离线回放
+pub struct MockTransport {
+    config: MockConfig,
+    state: Mutex<MockState>,
+}
+
+enum MockState {
+    Record(File),
+    // 记录及是否已回放过
+    Replay(Vec<(Interaction, bool)>),
+}
+
+impl MockTransport {
+    /// record模式会清空记录文件
+    pub fn new(config: MockConfig) -> Result<MockTransport, String> {
+        let error = |err: std::io::Error| format!("{}: {}", config.path, err);
+        let state = match config.mode {
+            MockMode::Record => MockState::Record(File::create(&config.path).map_err(error)?),
+            MockMode::Replay => {
+                let file = File::open(&config.path).map_err(error)?;
+                let mut interactions = Vec::new();
+                for (index, line) in BufReader::new(file).lines().enumerate() {
+                    let line = line.map_err(error)?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let interaction = serde_json::from_str::<Interaction>(&line)
+                        .map_err(|err| format!("{}:{}: {}", config.path, index + 1, err))?;
+                    interactions.push((interaction, false));
+                }
+                MockState::Replay(interactions)
+            }
+        };
+        Ok(MockTransport {
+            config,
+            state: Mutex::new(state),
+        })
+    }
+
+    pub fn is_replay(&self) -> bool {
+        self.config.mode == MockMode::Replay
+    }
+
+    /// 同一请求录制了多次时按录制顺序返回, 全部返回过之后重复返回最后一次
+    pub fn replay(&self, request: &Request) -> Option<ResponseData> {
+        let request = RecordedRequest::new(request);
+        let mut state = self.state.lock().unwrap();
+        let interactions = match &mut *state {
+            MockState::Replay(interactions) => interactions,
+            MockState::Record(_) => return None,
+        };
+        let mut index = None;
+        for (i, (interaction, served)) in interactions.iter().enumerate() {
+            if self.matches(interaction, &request) {
+                index = Some(i);
+                if !served {
+                    break;
+                }
+            }
+        }
+        let (interaction, served) = &mut interactions[index?];
+        *served = true;
+        interaction.to_response()
+    }
+
+    pub fn record(&self, request: &RecordedRequest, data: &ResponseData) {
+        let mut state = self.state.lock().unwrap();
+        let file = match &mut *state {
+            MockState::Record(file) => file,
+            MockState::Replay(_) => return,
+        };
+        use base64::Engine;
+        let interaction = Interaction {
+            method: request.method.clone(),
+            url: request.url.clone(),
+            body_sha256: request.body_sha256.clone(),
+            status: data.status,
+            version: version_to_i32(data.version),
+            headers: json_object(&data.headers),
+            cookies: json_object(&data.cookies),
+            body: base64::engine::general_purpose::STANDARD.encode(&data.data),
+        };
+        let result = serde_json::to_string(&interaction)
+            .map_err(std::io::Error::other)
+            .and_then(|line| writeln!(file, "{}", line));
+        if let Err(err) = result {
+            log::warn!("record interaction failed: {}", err);
+        }
+    }
+
+    fn matches(&self, interaction: &Interaction, request: &RecordedRequest) -> bool {
+        if self.config.match_method && !interaction.method.eq_ignore_ascii_case(&request.method) {
+            return false;
+        }
+        if self.config.match_body && interaction.body_sha256 != request.body_sha256 {
+            return false;
+        }
+        match (
+            self.match_url(&interaction.url),
+            self.match_url(&request.url),
+        ) {
+            (Some(recorded), Some(requested)) => recorded == requested,
+            _ => false,
+        }
+    }
+
+    /// 参与匹配的url部分, query参数排序后比较
+    fn match_url(&self, url: &str) -> Option<(String, Vec<(String, String)>)> {
+        let mut url = Url::parse(url).ok()?;
+        let mut params = Vec::new();
+        if self.config.match_query {
+            params = url
+                .query_pairs()
+                .filter(|(key, _)| !self.config.ignore_params.iter().any(|name| name == key))
+                .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                .collect();
+            params.sort();
+        }
+        url.set_query(None);
+        url.set_fragment(None);
+        Some((url.to_string(), params))
+    }
+}
+
+impl Interaction {
+    fn to_response(&self) -> Option<ResponseData> {
+        use base64::Engine;
+        let body = base64::engine::general_purpose::STANDARD
+            .decode(&self.body)
+            .ok()?;
+        let version = match self.version {
+            9 => Version::HTTP_09,
+            10 => Version::HTTP_10,
+            20 => Version::HTTP_2,
+            30 => Version::HTTP_3,
+            _ => Version::HTTP_11,
+        };
+        Some(ResponseData {
+            status: self.status,
+            data: body,
+            version,
+            cookies: self.cookies.to_string(),
+            headers: self.headers.to_string(),
+            remote_addr: None,
+        })
+    }
+}
+
+fn json_object(json: &str) -> serde_json::Value {
+    serde_json::from_str(json).unwrap_or_else(|_| serde_json::Value::Object(Default::default()))
+}
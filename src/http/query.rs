@@ -0,0 +1,244 @@
+use reqwest::Url;
+use serde::Deserialize;
+
+/// query参数的编码方式
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamEncoding {
+    /// application/x-www-form-urlencoded, 空格编码为+
+    #[default]
+    Form,
+    /// RFC 3986, 只保留非保留字符, 空格编码为%20
+    Rfc3986,
+    /// 参数已由调用方编码, 原样拼接, 只转义url中不允许出现的字符
+    Raw,
+}
+
+impl ParamEncoding {
+    pub fn parse(value: &str) -> Option<ParamEncoding> {
+        serde_json::from_value(serde_json::Value::String(value.to_string())).ok()
+    }
+
+    fn encode(self, value: &str, query: &mut String) {
+        match self {
+            ParamEncoding::Form => {
+                for byte in value.bytes() {
+                    match byte {
+                        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'*' => {
+                            query.push(byte as char)
+                        }
+                        b' ' => query.push('+'),
+                        _ => query.push_str(&format!("%{:02X}", byte)),
+                    }
+                }
+            }
+            ParamEncoding::Rfc3986 => query.push_str(&encode_rfc3986(value)),
+            ParamEncoding::Raw => query.push_str(value),
+        }
+    }
+}
+
+/// 有序的query参数, 同名参数可以出现多次
+#[derive(Default, Clone)]
+pub struct Params {
+    params: Vec<(String, String)>,
+}
+
+impl Params {
+    /// 设置参数, 替换所有同名参数, 位置保持在第一个同名参数处
+    pub fn set(&mut self, key: String, value: String) {
+        match self.params.iter().position(|(name, _)| *name == key) {
+            Some(index) => {
+                self.params[index].1 = value;
+                let mut index = index + 1;
+                while index < self.params.len() {
+                    if self.params[index].0 == key {
+                        self.params.remove(index);
+                    } else {
+                        index += 1;
+                    }
+                }
+            }
+            None => self.params.push((key, value)),
+        }
+    }
+
+    /// 追加参数, 不影响已有的同名参数
+    pub fn append(&mut self, key: String, value: String) {
+        self.params.push((key, value));
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.params.retain(|(name, _)| name != key);
+    }
+
+    pub fn clear(&mut self) {
+        self.params.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(String, String)> {
+        self.params.iter()
+    }
+}
+
+/// 按顺序将参数追加到url已有的query之后
+pub fn append_query<'a>(
+    url: &mut Url,
+    params: impl IntoIterator<Item = &'a (String, String)>,
+    encoding: ParamEncoding,
+) {
+    let mut query = url.query().unwrap_or_default().to_string();
+    let len = query.len();
+    for (key, value) in params {
+        if !query.is_empty() {
+            query.push('&');
+        }
+        encoding.encode(key, &mut query);
+        query.push('=');
+        encoding.encode(value, &mut query);
+    }
+    if query.len() != len {
+        url.set_query(Some(&query));
+    }
+}
+
+/// RFC 3986 编码, 只保留非保留字符
+pub fn encode_rfc3986(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn query(url: &str, pairs: &[(&str, &str)], encoding: ParamEncoding) -> String {
+        let mut url = Url::parse(url).unwrap();
+        append_query(&mut url, &self::pairs(pairs), encoding);
+        url.to_string()
+    }
+
+    #[test]
+    fn params_order() {
+        let mut params = Params::default();
+        params.append("a".into(), "1".into());
+        params.append("b".into(), "2".into());
+        params.append("a".into(), "3".into());
+        params.append("c".into(), "4".into());
+
+        // set 替换所有同名参数, 保持在第一个同名参数的位置
+        params.set("a".into(), "5".into());
+        assert_eq!(
+            params.iter().cloned().collect::<Vec<_>>(),
+            pairs(&[("a", "5"), ("b", "2"), ("c", "4")])
+        );
+
+        params.set("d".into(), "6".into());
+        params.append("b".into(), "7".into());
+        assert_eq!(
+            params.iter().cloned().collect::<Vec<_>>(),
+            pairs(&[("a", "5"), ("b", "2"), ("c", "4"), ("d", "6"), ("b", "7")])
+        );
+
+        params.remove("b");
+        assert_eq!(
+            params.iter().cloned().collect::<Vec<_>>(),
+            pairs(&[("a", "5"), ("c", "4"), ("d", "6")])
+        );
+
+        params.clear();
+        assert_eq!(params.iter().count(), 0);
+    }
+
+    #[test]
+    fn form_encoding() {
+        assert_eq!(
+            query(
+                "https://example.com/search",
+                &[("q", "a b+c"), ("name", "中"), ("keep", "-_.*~!'()/?&=")],
+                ParamEncoding::Form,
+            ),
+            "https://example.com/search?q=a+b%2Bc&name=%E4%B8%AD&keep=-_.*%7E%21%27%28%29%2F%3F%26%3D"
+        );
+    }
+
+    #[test]
+    fn rfc3986_encoding() {
+        assert_eq!(
+            query(
+                "https://example.com/search",
+                &[("q", "a b+c"), ("name", "中"), ("keep", "-_.*~!'()/?&=")],
+                ParamEncoding::Rfc3986,
+            ),
+            "https://example.com/search?q=a%20b%2Bc&name=%E4%B8%AD&keep=-_.%2A~%21%27%28%29%2F%3F%26%3D"
+        );
+        assert_eq!(encode_rfc3986("AZaz09"), "AZaz09");
+    }
+
+    #[test]
+    fn raw_encoding() {
+        // 已编码的参数原样拼接, 只转义url中不允许出现的字符
+        assert_eq!(
+            query(
+                "https://example.com/search",
+                &[("q", "a%20b+c"), ("filter", "x=1&y=2"), ("s", "a b\"<>")],
+                ParamEncoding::Raw,
+            ),
+            "https://example.com/search?q=a%20b+c&filter=x=1&y=2&s=a%20b%22%3C%3E"
+        );
+    }
+
+    #[test]
+    fn merge_existing_query() {
+        // 追加在url已有的query之后, 已有的query保持不变
+        assert_eq!(
+            query(
+                "https://example.com/search?q=old%20value&id=1",
+                &[("id", "2"), ("q", "new value")],
+                ParamEncoding::Form,
+            ),
+            "https://example.com/search?q=old%20value&id=1&id=2&q=new+value"
+        );
+
+        // 没有参数时不改变url
+        assert_eq!(
+            query("https://example.com/search", &[], ParamEncoding::Form),
+            "https://example.com/search"
+        );
+        assert_eq!(
+            query("https://example.com/search?", &[], ParamEncoding::Rfc3986),
+            "https://example.com/search?"
+        );
+        assert_eq!(
+            query(
+                "https://example.com/",
+                &[("empty", "")],
+                ParamEncoding::Rfc3986
+            ),
+            "https://example.com/?empty="
+        );
+    }
+
+    #[test]
+    fn parse_encoding() {
+        assert!(ParamEncoding::parse("form") == Some(ParamEncoding::Form));
+        assert!(ParamEncoding::parse("rfc3986") == Some(ParamEncoding::Rfc3986));
+        assert!(ParamEncoding::parse("raw") == Some(ParamEncoding::Raw));
+        assert!(ParamEncoding::parse("Form").is_none());
+    }
+}
@@ -0,0 +1,175 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+/// 请求调度器
+/// 限制全局与单个host的并发数, 排队中的请求按优先级(数值越大越优先)出队
+pub struct Scheduler {
+    state: Mutex<SchedulerState>,
+}
+
+struct SchedulerState {
+    // 全局最大并发 0表示不限制
+    max_concurrency: usize,
+    // 单个host最大并发 0表示不限制
+    max_per_host: usize,
+    active: usize,
+    active_per_host: HashMap<String, usize>,
+    waiting: BinaryHeap<Waiter>,
+    // 同优先级按先来先服务
+    next_seq: u64,
+}
+
+struct Waiter {
+    priority: i32,
+    seq: u64,
+    host: String,
+    tx: oneshot::Sender<Permit>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority && self.seq == other.seq
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/// 并发许可, 释放时归还名额并唤醒排队中的请求
+pub struct Permit {
+    scheduler: Arc<Scheduler>,
+    host: String,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.scheduler.release(&self.host);
+    }
+}
+
+impl SchedulerState {
+    fn can_run(&self, host: &str) -> bool {
+        if self.max_concurrency > 0 && self.active >= self.max_concurrency {
+            return false;
+        }
+        if self.max_per_host > 0
+            && self.active_per_host.get(host).copied().unwrap_or(0) >= self.max_per_host
+        {
+            return false;
+        }
+        true
+    }
+
+    fn acquire(&mut self, host: &str) {
+        self.active += 1;
+        *self.active_per_host.entry(host.to_string()).or_insert(0) += 1;
+    }
+
+    fn release(&mut self, host: &str) {
+        self.active = self.active.saturating_sub(1);
+        if let Some(count) = self.active_per_host.get_mut(host) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                self.active_per_host.remove(host);
+            }
+        }
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(SchedulerState {
+                max_concurrency: 0,
+                max_per_host: 0,
+                active: 0,
+                active_per_host: HashMap::new(),
+                waiting: BinaryHeap::new(),
+                next_seq: 0,
+            }),
+        })
+    }
+
+    pub fn set_limits(self: &Arc<Self>, max_concurrency: usize, max_per_host: usize) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.max_concurrency = max_concurrency;
+            state.max_per_host = max_per_host;
+        }
+        // 上限调大后可能有排队的请求可以执行了
+        self.dispatch();
+    }
+
+    /// 等待直到获得并发许可
+    pub async fn acquire(self: &Arc<Self>, host: String, priority: i32) -> Permit {
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut state = self.state.lock().unwrap();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiting.push(Waiter {
+                priority,
+                seq,
+                host,
+                tx,
+            });
+        }
+        self.dispatch();
+
+        // 发送端只会在调度器派发时被消费, 调度器存活期间不会出现Err
+        rx.await.expect("scheduler dropped")
+    }
+
+    fn release(self: &Arc<Self>, host: &str) {
+        self.state.lock().unwrap().release(host);
+        self.dispatch();
+    }
+
+    /// 按优先级派发排队中的请求
+    /// host已满的请求继续排队, 不阻塞其它host的请求
+    fn dispatch(self: &Arc<Self>) {
+        // 已被取消的请求返还的许可需要在锁外释放
+        let mut cancelled = vec![];
+        {
+            let mut state = self.state.lock().unwrap();
+            let mut blocked = vec![];
+            while let Some(waiter) = state.waiting.pop() {
+                if state.max_concurrency > 0 && state.active >= state.max_concurrency {
+                    state.waiting.push(waiter);
+                    break;
+                }
+                if !state.can_run(&waiter.host) {
+                    blocked.push(waiter);
+                    continue;
+                }
+
+                state.acquire(&waiter.host);
+                let permit = Permit {
+                    scheduler: self.clone(),
+                    host: waiter.host,
+                };
+                if let Err(permit) = waiter.tx.send(permit) {
+                    cancelled.push(permit);
+                }
+            }
+            state.waiting.extend(blocked);
+        }
+        // 这里会重新进入dispatch, 每次释放都会尝试派发下一个请求
+        drop(cancelled);
+    }
+}
@@ -0,0 +1,292 @@
+use super::query::encode_rfc3986;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::Request;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 请求签名配置, 以json形式传入 rust_net_http_set_signer
+///
+/// 待签名字符串为以下各行以 \n 连接:
+/// 1. 大写的method
+/// 2. url中的path(按发送时的编码)
+/// 3. 排序后的query: 参数名与值按RFC 3986编码后以 name=value 表示, 按名称再按值排序, 以 & 连接
+/// 4. 时间戳
+/// 5. nonce, 未启用时为空行
+/// 6. signed_headers 中的请求头, 每个一行 小写名称:去除首尾空白的值, 按名称排序, 未设置的请求头值为空
+/// 7. body的SHA256(小写hex), 无body时为空字符串的SHA256
+///
+/// 签名为 HMAC-SHA256(secret, 待签名字符串)
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct SignerConfig {
+    pub key_id: String,
+    pub secret: String,
+    // 签名编码 hex 或 base64
+    pub encoding: String,
+    pub signature_header: String,
+    // 为空时不发送key id
+    pub key_id_header: String,
+    pub timestamp_header: String,
+    // 时间戳单位 seconds 或 milliseconds
+    pub timestamp_unit: String,
+    // 为空时不使用nonce
+    pub nonce_header: String,
+    // 参与签名的请求头, 例如客户端公共请求头中的设备信息
+    pub signed_headers: Vec<String>,
+}
+
+impl Default for SignerConfig {
+    fn default() -> Self {
+        SignerConfig {
+            key_id: String::new(),
+            secret: String::new(),
+            encoding: "hex".into(),
+            signature_header: "X-Signature".into(),
+            key_id_header: "X-Key-Id".into(),
+            timestamp_header: "X-Timestamp".into(),
+            timestamp_unit: "seconds".into(),
+            nonce_header: "X-Nonce".into(),
+            signed_headers: Vec::new(),
+        }
+    }
+}
+
+/// HMAC-SHA256请求签名
+pub struct Signer {
+    config: SignerConfig,
+    signature_header: HeaderName,
+    key_id_header: Option<HeaderName>,
+    timestamp_header: HeaderName,
+    nonce_header: Option<HeaderName>,
+}
+
+impl Signer {
+    pub fn new(mut config: SignerConfig) -> Result<Signer, String> {
+        if config.secret.is_empty() {
+            return Err("signer secret is empty".into());
+        }
+        if config.encoding != "hex" && config.encoding != "base64" {
+            return Err(format!("unknown signature encoding: {}", config.encoding));
+        }
+        if config.timestamp_unit != "seconds" && config.timestamp_unit != "milliseconds" {
+            return Err(format!("unknown timestamp unit: {}", config.timestamp_unit));
+        }
+        let header = |name: &str| {
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("invalid header name: {}", name))
+        };
+        let optional_header = |name: &str| match name.is_empty() {
+            true => Ok(None),
+            false => header(name).map(Some),
+        };
+
+        for name in config.signed_headers.iter_mut() {
+            *name = header(name)?.as_str().to_string();
+        }
+        config.signed_headers.sort();
+
+        Ok(Signer {
+            signature_header: header(&config.signature_header)?,
+            key_id_header: optional_header(&config.key_id_header)?,
+            timestamp_header: header(&config.timestamp_header)?,
+            nonce_header: optional_header(&config.nonce_header)?,
+            config,
+        })
+    }
+
+    /// 计算签名并写入请求头, 需要在请求发送之前的最后一步调用
+    pub fn sign(&self, request: &mut Request) {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let timestamp = if self.config.timestamp_unit == "milliseconds" {
+            since_epoch.as_millis().to_string()
+        } else {
+            since_epoch.as_secs().to_string()
+        };
+        let nonce = match self.nonce_header {
+            Some(_) => {
+                let mut bytes = [0u8; 16];
+                rand::thread_rng().fill_bytes(&mut bytes);
+                hex::encode(bytes)
+            }
+            None => String::new(),
+        };
+
+        let canonical = self.canonical_string(request, &timestamp, &nonce);
+        let signature = self.signature(&canonical);
+
+        let headers = request.headers_mut();
+        let mut insert = |name: &HeaderName, value: &str| {
+            if let Ok(value) = HeaderValue::from_str(value) {
+                headers.insert(name.clone(), value);
+            }
+        };
+        insert(&self.signature_header, &signature);
+        insert(&self.timestamp_header, &timestamp);
+        if let Some(name) = &self.key_id_header {
+            insert(name, &self.config.key_id);
+        }
+        if let Some(name) = &self.nonce_header {
+            insert(name, &nonce);
+        }
+    }
+
+    /// HMAC-SHA256(secret, 待签名字符串), 按配置编码为hex或base64
+    fn signature(&self, canonical: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.config.secret.as_bytes())
+            .expect("hmac accepts keys of any length");
+        mac.update(canonical.as_bytes());
+        let signature = mac.finalize().into_bytes();
+        if self.config.encoding == "base64" {
+            use base64::Engine;
+            base64::engine::general_purpose::STANDARD.encode(signature)
+        } else {
+            hex::encode(signature)
+        }
+    }
+
+    fn canonical_string(&self, request: &Request, timestamp: &str, nonce: &str) -> String {
+        let url = request.url();
+        let mut query: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(key, value)| (encode_rfc3986(&key), encode_rfc3986(&value)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let mut lines = vec![
+            request.method().as_str().to_ascii_uppercase(),
+            url.path().to_string(),
+            query,
+            timestamp.to_string(),
+            nonce.to_string(),
+        ];
+        for name in &self.config.signed_headers {
+            let value = request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            lines.push(format!("{}:{}", name, value.trim()));
+        }
+
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .unwrap_or_default();
+        lines.push(hex::encode(Sha256::digest(body)));
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CANONICAL: &str = "POST\n\
+        /v1/items%20list\n\
+        a=1&a=hello%20world&b=2&c=%E4%B8%AD&d=x%2Ay~&e=a%20b\n\
+        1700000000\n\
+        0123456789abcdef0123456789abcdef\n\
+        content-type:application/json\n\
+        x-device:ios 17\n\
+        x-missing:\n\
+        037c9214eef74cc3887f3a4f085b4e17d76280dafd273b0ee160c09c4ba1cfd4";
+
+    fn signer(encoding: &str) -> Signer {
+        Signer::new(SignerConfig {
+            key_id: "key-1".into(),
+            secret: "secret-key".into(),
+            encoding: encoding.into(),
+            signed_headers: vec!["X-Device".into(), "Content-Type".into(), "X-Missing".into()],
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn request() -> Request {
+        reqwest::Client::new()
+            .post("https://api.example.com/v1/items%20list?b=2&a=hello%20world&a=1&c=%E4%B8%AD&d=x*y~&e=a+b")
+            .header("content-type", "application/json")
+            .header("x-device", "  ios 17 ")
+            .body(r#"{"id":1}"#)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn canonical_string() {
+        let canonical = signer("hex").canonical_string(
+            &request(),
+            "1700000000",
+            "0123456789abcdef0123456789abcdef",
+        );
+        assert_eq!(canonical, CANONICAL);
+
+        // 无query、nonce及body
+        let request = reqwest::Client::new()
+            .get("https://api.example.com/")
+            .build()
+            .unwrap();
+        let signer = Signer::new(SignerConfig {
+            secret: "secret-key".into(),
+            nonce_header: String::new(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            signer.canonical_string(&request, "1700000000", ""),
+            "GET\n/\n\n1700000000\n\n\
+            e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn signature_encoding() {
+        assert_eq!(
+            signer("hex").signature(CANONICAL),
+            "5f0991362141ce8e175c022ff04118971b8def1c2d279c671e4531601cd327bf"
+        );
+        assert_eq!(
+            signer("base64").signature(CANONICAL),
+            "XwmRNiFBzo4XXAIv8EEYlxuN7xwtJ5xnHkUxYBzTJ78="
+        );
+    }
+
+    #[test]
+    fn sign_headers() {
+        let signer = signer("hex");
+        let mut request = request();
+        signer.sign(&mut request);
+
+        let header = |name: &str| request.headers()[name].to_str().unwrap().to_string();
+        let timestamp = header("x-timestamp");
+        let nonce = header("x-nonce");
+        assert_eq!(header("x-key-id"), "key-1");
+        assert_eq!(nonce.len(), 32);
+        let canonical = signer.canonical_string(&request, &timestamp, &nonce);
+        assert_eq!(header("x-signature"), signer.signature(&canonical));
+    }
+
+    #[test]
+    fn invalid_config() {
+        let config = |encoding: &str, timestamp_unit: &str| SignerConfig {
+            secret: "secret-key".into(),
+            encoding: encoding.into(),
+            timestamp_unit: timestamp_unit.into(),
+            ..Default::default()
+        };
+        assert!(Signer::new(config("hex", "milliseconds")).is_ok());
+        assert!(Signer::new(config("base32", "seconds")).is_err());
+        assert!(Signer::new(config("hex", "minutes")).is_err());
+        assert!(Signer::new(SignerConfig::default()).is_err());
+    }
+}
@@ -0,0 +1,367 @@
+use crate::throttle::{throttle, Bandwidth};
+use bytes::Bytes;
+use futures_util::stream;
+use hyper::client::connect::HttpInfo;
+use reqwest::header::{HeaderValue, CONTENT_LENGTH};
+use reqwest::{Body, Request, Response};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// 上传时每次交给底层连接的数据大小
+const UPLOAD_CHUNK_SIZE: usize = 16 * 1024;
+// 超过这个时间没有使用的连接已被连接池关闭, 与reqwest连接池的默认空闲超时一致
+const CONNECTION_IDLE: Duration = Duration::from_secs(90);
+
+tokio::task_local! {
+    // 当前请求的计时器, DNS解析与TLS握手在请求所在的task中执行, 通过task local关联到请求
+    static CURRENT: Arc<TimingRecorder>;
+}
+
+/// 请求各阶段耗时(毫秒), 无法获取的阶段为-1
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RequestTimings {
+    /// 排队等待并发名额
+    queued: f64,
+    /// DNS解析
+    dns: f64,
+    /// TCP连接, 仅https可以获取
+    tcp_connect: f64,
+    /// TLS握手
+    tls_handshake: f64,
+    /// 发送请求body, 仅有body的请求可以获取
+    /// 没有上传限速时body随请求头一次写出, 计为0
+    request_sent: f64,
+    /// 从开始发送到收到响应头(包含建立连接)
+    ttfb: f64,
+    /// 接收响应body
+    body_complete: f64,
+    /// 总耗时(包含排队)
+    total: f64,
+    /// 是否复用了已有连接, 无法获取连接信息(HTTP/3)时为false
+    connection_reused: bool,
+}
+
+impl Default for RequestTimings {
+    fn default() -> Self {
+        Self {
+            queued: -1.0,
+            dns: -1.0,
+            tcp_connect: -1.0,
+            tls_handshake: -1.0,
+            request_sent: -1.0,
+            ttfb: -1.0,
+            body_complete: -1.0,
+            total: -1.0,
+            connection_reused: false,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Marks {
+    send_start: Option<Instant>,
+    dns_start: Option<Instant>,
+    dns_end: Option<Instant>,
+    tls_start: Option<Instant>,
+    tls_end: Option<Instant>,
+    request_sent: Option<Instant>,
+    // body随请求头一次写出, 没有单独的发送时间
+    body_inline: bool,
+    first_byte: Option<Instant>,
+    body_end: Option<Instant>,
+    connection_reused: Option<bool>,
+}
+
+impl RequestTimings {
+    /// HAR 1.2 的 timings, 不适用的阶段为-1, send、wait、receive 不能为负数
+    /// HAR中的connect包含ssl, wait为收到响应头之前除去连接与发送的时间
+    pub fn to_har(self) -> serde_json::Value {
+        let connect = match (self.tcp_connect, self.tls_handshake) {
+            (tcp, tls) if tcp >= 0.0 && tls >= 0.0 => tcp + tls,
+            (tcp, _) => tcp,
+        };
+        let send = self.request_sent.max(0.0);
+        let wait = self.ttfb - self.dns.max(0.0) - connect.max(0.0) - send;
+        serde_json::json!({
+            "blocked": self.queued,
+            "dns": self.dns,
+            "connect": connect,
+            "ssl": self.tls_handshake,
+            "send": send,
+            "wait": wait.max(0.0),
+            "receive": self.body_complete.max(0.0),
+        })
+    }
+
+    pub fn total(&self) -> f64 {
+        self.total
+    }
+}
+
+/// 记录单个请求各阶段的时间点
+pub struct TimingRecorder {
+    create_time: Instant,
+    marks: Mutex<Marks>,
+}
+
+fn millis(from: Option<Instant>, to: Option<Instant>) -> f64 {
+    match (from, to) {
+        (Some(from), Some(to)) => to.saturating_duration_since(from).as_secs_f64() * 1000.0,
+        _ => -1.0,
+    }
+}
+
+impl TimingRecorder {
+    pub fn new(create_time: Instant) -> Arc<Self> {
+        Arc::new(Self {
+            create_time,
+            marks: Mutex::new(Marks::default()),
+        })
+    }
+
+    /// 在计时器的作用域内执行请求
+    pub async fn scope<F: std::future::Future>(self: Arc<Self>, f: F) -> F::Output {
+        CURRENT.scope(self, f).await
+    }
+
+    fn with_current(f: impl FnOnce(&mut Marks)) {
+        let _ = CURRENT.try_with(|recorder| f(&mut recorder.marks.lock().unwrap()));
+    }
+
+    pub fn mark_send_start(&self) {
+        self.marks.lock().unwrap().send_start = Some(Instant::now());
+    }
+
+    pub fn mark_first_byte(&self) {
+        self.marks.lock().unwrap().first_byte = Some(Instant::now());
+    }
+
+    pub fn mark_body_end(&self) {
+        self.marks.lock().unwrap().body_end = Some(Instant::now());
+    }
+
+    fn mark_request_sent(&self) {
+        self.marks.lock().unwrap().request_sent = Some(Instant::now());
+    }
+
+    fn mark_body_inline(&self) {
+        self.marks.lock().unwrap().body_inline = true;
+    }
+
+    pub fn mark_connection_reused(&self, reused: Option<bool>) {
+        self.marks.lock().unwrap().connection_reused = reused;
+    }
+
+    pub fn mark_dns_start() {
+        Self::with_current(|marks| {
+            marks.dns_start.get_or_insert_with(Instant::now);
+        });
+    }
+
+    pub fn mark_dns_end() {
+        Self::with_current(|marks| {
+            marks.dns_end.get_or_insert_with(Instant::now);
+        });
+    }
+
+    pub fn mark_tls_start() {
+        Self::with_current(|marks| {
+            marks.tls_start.get_or_insert_with(Instant::now);
+        });
+    }
+
+    pub fn mark_tls_end() {
+        Self::with_current(|marks| {
+            marks.tls_end.get_or_insert_with(Instant::now);
+        });
+    }
+
+    pub fn timings(&self) -> RequestTimings {
+        let marks = self.marks.lock().unwrap();
+        let create_time = Some(self.create_time);
+        // 连接建立完成的时间点, 复用连接时为开始发送的时间
+        let connected = marks.tls_end.or(marks.dns_end).or(marks.send_start);
+
+        RequestTimings {
+            queued: millis(create_time, marks.send_start),
+            dns: millis(marks.dns_start, marks.dns_end),
+            tcp_connect: millis(marks.dns_end.or(marks.send_start), marks.tls_start),
+            tls_handshake: millis(marks.tls_start, marks.tls_end),
+            request_sent: match marks.body_inline {
+                true if marks.first_byte.is_some() => 0.0,
+                _ => millis(connected, marks.request_sent),
+            },
+            ttfb: millis(marks.send_start, marks.first_byte),
+            body_complete: millis(marks.first_byte, marks.body_end),
+            total: millis(create_time, marks.body_end),
+            connection_reused: marks.connection_reused.unwrap_or_default(),
+        }
+    }
+}
+
+/// 客户端使用过的连接, 以本地地址与远端地址区分
+#[derive(Default)]
+pub struct ConnectionTracker {
+    // 连接 -> 最后一次使用的时间
+    connections: Mutex<HashMap<(SocketAddr, SocketAddr), Instant>>,
+}
+
+impl ConnectionTracker {
+    /// 响应所在的连接之前是否使用过, 没有连接信息时返回None
+    pub fn is_reused(&self, response: &Response) -> Option<bool> {
+        let info = response.extensions().get::<HttpInfo>()?;
+        let now = Instant::now();
+        let mut connections = self.connections.lock().unwrap();
+        connections.retain(|_, last_used| now.duration_since(*last_used) < CONNECTION_IDLE);
+        let reused = connections
+            .insert((info.local_addr(), info.remote_addr()), now)
+            .is_some();
+        Some(reused)
+    }
+}
+
+/// 有上传限速时将请求body替换为分块的流, 以便按块限速以及记录body发送完成的时间
+/// 流式body无法重放, 307/308重定向不会跟随, 所以没有限速时保留原body
+/// body由连接所在的task发送, 所以这里直接持有计时器
+pub fn wrap_body(
+    request: &mut Request,
+    recorder: Arc<TimingRecorder>,
+    bandwidth: [Arc<Bandwidth>; 3],
+) {
+    let bytes = match request.body().and_then(|body| body.as_bytes()) {
+        Some(bytes) if !bytes.is_empty() => Bytes::copy_from_slice(bytes),
+        _ => return,
+    };
+    if !bandwidth
+        .iter()
+        .any(|bandwidth| bandwidth.upload.is_limited())
+    {
+        recorder.mark_body_inline();
+        return;
+    }
+
+    // 流式body默认为chunked编码, 这里保留原始长度
+    request
+        .headers_mut()
+        .insert(CONTENT_LENGTH, HeaderValue::from(bytes.len()));
+
+    let chunks = stream::unfold(bytes, move |mut remaining| {
+        let recorder = recorder.clone();
+        let bandwidth = bandwidth.clone();
+        async move {
+            if remaining.is_empty() {
+                return None;
+            }
+            let chunk = remaining.split_to(remaining.len().min(UPLOAD_CHUNK_SIZE));
+            throttle(
+                bandwidth.iter().map(|bandwidth| &bandwidth.upload),
+                chunk.len(),
+            )
+            .await;
+            if remaining.is_empty() {
+                recorder.mark_request_sent();
+            }
+            Some((Ok::<_, std::io::Error>(chunk), remaining))
+        }
+    });
+    *request.body_mut() = Some(Body::wrap_stream(chunks));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Server, StatusCode};
+    use std::convert::Infallible;
+
+    fn request(body: &'static str) -> Request {
+        reqwest::Client::new()
+            .post("http://127.0.0.1/")
+            .body(body)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn wrap_body_only_when_limited() {
+        let recorder = TimingRecorder::new(Instant::now());
+        let bandwidth: [Arc<Bandwidth>; 3] = Default::default();
+
+        let mut unlimited = request("hello");
+        wrap_body(&mut unlimited, recorder.clone(), bandwidth.clone());
+        assert_eq!(unlimited.body().unwrap().as_bytes(), Some(&b"hello"[..]));
+        recorder.mark_send_start();
+        recorder.mark_first_byte();
+        assert_eq!(recorder.timings().request_sent, 0.0);
+
+        bandwidth[1].set_limit(1024, 0);
+        let mut limited = request("hello");
+        wrap_body(&mut limited, recorder, bandwidth);
+        assert!(limited.body().unwrap().as_bytes().is_none());
+        assert_eq!(limited.headers()[CONTENT_LENGTH], "5");
+    }
+
+    /// /redirect 以307重定向到 /echo, /echo 返回请求body
+    async fn serve() -> SocketAddr {
+        let service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(
+                |request: hyper::Request<hyper::Body>| async move {
+                    let response = match request.uri().path() {
+                        "/redirect" => hyper::Response::builder()
+                            .status(StatusCode::TEMPORARY_REDIRECT)
+                            .header("location", "/echo")
+                            .body(hyper::Body::empty()),
+                        _ => hyper::Response::builder().body(request.into_body()),
+                    };
+                    Ok::<_, Infallible>(response.unwrap())
+                },
+            ))
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn redirect_with_body() {
+        let addr = serve().await;
+        let client = reqwest::Client::new();
+        let mut request = client
+            .post(format!("http://{}/redirect", addr))
+            .body("hello")
+            .build()
+            .unwrap();
+        wrap_body(
+            &mut request,
+            TimingRecorder::new(Instant::now()),
+            Default::default(),
+        );
+        let response = client.execute(request).await.unwrap();
+        assert_eq!(response.url().path(), "/echo");
+        assert_eq!(response.text().await.unwrap(), "hello");
+    }
+
+    #[tokio::test]
+    async fn connection_reused() {
+        let addr = serve().await;
+        let url = format!("http://{}/echo", addr);
+        let client = reqwest::Client::new();
+        let connections = ConnectionTracker::default();
+
+        // IP地址的http请求没有DNS与TLS, 也能区分新连接
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(connections.is_reused(&response), Some(false));
+        response.bytes().await.unwrap();
+
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(connections.is_reused(&response), Some(true));
+        response.bytes().await.unwrap();
+
+        let response = reqwest::Client::new().get(&url).send().await.unwrap();
+        assert_eq!(connections.is_reused(&response), Some(false));
+    }
+}
@@ -0,0 +1,98 @@
+use super::timing::TimingRecorder;
+use rustls::client::{
+    ClientSessionMemoryCache, ClientSessionStore, Resumption, Tls12ClientSessionValue,
+    Tls13ClientSessionValue,
+};
+use rustls::{ClientConfig, KeyLog, NamedGroup, OwnedTrustAnchor, RootCertStore, ServerName};
+use std::sync::Arc;
+
+/// 构建rustls配置
+/// 与reqwest内置的rustls配置一致, 额外挂载了握手计时
+pub fn client_config(
+    root_certificates: &[String],
+    alpn_protocols: &[&str],
+) -> Result<ClientConfig, String> {
+    let mut root_store = RootCertStore::empty();
+    root_store.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|trust_anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            trust_anchor.subject,
+            trust_anchor.spki,
+            trust_anchor.name_constraints,
+        )
+    }));
+    for pem in root_certificates {
+        let certs = rustls_pemfile::certs(&mut pem.as_bytes()).map_err(|err| err.to_string())?;
+        if certs.is_empty() {
+            return Err("no certificate found in root_certificates".into());
+        }
+        for cert in certs {
+            root_store
+                .add(&rustls::Certificate(cert))
+                .map_err(|err| err.to_string())?;
+        }
+    }
+
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    config.alpn_protocols = alpn_protocols
+        .iter()
+        .map(|protocol| protocol.as_bytes().to_vec())
+        .collect();
+    config.resumption = Resumption::store(Arc::new(TimingSessionStore {
+        inner: ClientSessionMemoryCache::new(256),
+    }));
+    config.key_log = Arc::new(TimingKeyLog);
+    Ok(config)
+}
+
+/// 握手开始时rustls会先查找可恢复的会话, 以此作为TLS握手开始(TCP连接完成)的时间点
+struct TimingSessionStore {
+    inner: ClientSessionMemoryCache,
+}
+
+impl ClientSessionStore for TimingSessionStore {
+    fn set_kx_hint(&self, server_name: &ServerName, group: NamedGroup) {
+        self.inner.set_kx_hint(server_name, group)
+    }
+
+    fn kx_hint(&self, server_name: &ServerName) -> Option<NamedGroup> {
+        self.inner.kx_hint(server_name)
+    }
+
+    fn set_tls12_session(&self, server_name: &ServerName, value: Tls12ClientSessionValue) {
+        self.inner.set_tls12_session(server_name, value)
+    }
+
+    fn tls12_session(&self, server_name: &ServerName) -> Option<Tls12ClientSessionValue> {
+        self.inner.tls12_session(server_name)
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName) {
+        self.inner.remove_tls12_session(server_name)
+    }
+
+    fn insert_tls13_ticket(&self, server_name: &ServerName, value: Tls13ClientSessionValue) {
+        self.inner.insert_tls13_ticket(server_name, value)
+    }
+
+    fn take_tls13_ticket(&self, server_name: &ServerName) -> Option<Tls13ClientSessionValue> {
+        TimingRecorder::mark_tls_start();
+        self.inner.take_tls13_ticket(server_name)
+    }
+}
+
+/// 密钥协商完成即握手完成, 不会真正输出密钥
+struct TimingKeyLog;
+
+impl KeyLog for TimingKeyLog {
+    fn log(&self, _label: &str, _client_random: &[u8], _secret: &[u8]) {
+        TimingRecorder::mark_tls_end();
+    }
+
+    fn will_log(&self, label: &str) -> bool {
+        // TLS1.3 / TLS1.2
+        label == "CLIENT_TRAFFIC_SECRET_0" || label == "CLIENT_RANDOM"
+    }
+}
@@ -1,14 +1,30 @@
 pub mod http;
+mod logger;
+mod server;
+mod socket;
+mod stats;
+mod throttle;
 mod websocket;
+mod ws_server;
 
 extern crate alloc;
 extern crate core;
 
+use http::dns::SharedResolvers;
+use stats::{NetCounters, NetStats};
+use std::sync::Arc;
+use throttle::Bandwidth;
 use tokio::runtime::Runtime;
 
 /// tokio context
 pub struct TokioContext {
     runtime: Runtime,
+    // 所有http请求与websocket连接的统计
+    stats: Arc<NetCounters>,
+    // 所有http请求的全局限速
+    bandwidth: Arc<Bandwidth>,
+    // WebSocket及TCP连接共享的DNS解析器
+    resolvers: SharedResolvers,
 }
 
 #[no_mangle]
@@ -19,11 +35,37 @@ pub extern "C" fn rust_net_tokio_new(thread_count: u32) -> *mut TokioContext {
         .build()
         .expect("tokio runtime fail");
 
-    Box::into_raw(Box::new(TokioContext { runtime }))
+    Box::into_raw(Box::new(TokioContext {
+        runtime,
+        stats: Default::default(),
+        bandwidth: Default::default(),
+        resolvers: Default::default(),
+    }))
 }
 
+/// # Safety
+/// handler 必须是 rust_net_tokio_new 返回的指针, 释放后不能再使用
 #[no_mangle]
 pub unsafe extern "C" fn rust_net_tokio_free(handler: *mut TokioContext) {
     let handler = Box::from_raw(handler);
     drop(handler)
 }
+
+/// 获取该tokio context下所有http请求与websocket连接的统计
+#[no_mangle]
+pub extern "C" fn rust_net_tokio_get_stats(context: &mut TokioContext) -> NetStats {
+    context.stats.snapshot()
+}
+
+/// 设置该tokio context下所有http请求的全局限速(字节/秒) 0表示不限速
+/// 可以在请求进行中调整
+#[no_mangle]
+pub extern "C" fn rust_net_tokio_set_bandwidth_limit(
+    context: &mut TokioContext,
+    upload_bytes_per_sec: u64,
+    download_bytes_per_sec: u64,
+) {
+    context
+        .bandwidth
+        .set_limit(upload_bytes_per_sec, download_bytes_per_sec);
+}
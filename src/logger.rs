@@ -0,0 +1,243 @@
+use log::{LevelFilter, Log, Metadata, Record};
+use std::collections::BTreeMap;
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+use std::sync::{Once, RwLock};
+
+/// 日志回调
+/// level: 1 error, 2 warn, 3 info, 4 debug, 5 trace
+/// target: 模块名, 例如 rust_net::http、reqwest
+pub type LogCallback = Option<
+    extern "C" fn(
+        level: i32,
+        target: *const c_char,
+        message: *const c_char,
+        user_data: *mut c_void,
+    ),
+>;
+
+// 这些header的值不会出现在日志中
+const REDACTED_HEADERS: [&str; 4] = [
+    "proxy-authorization",
+    "authorization",
+    "set-cookie",
+    "cookie",
+];
+
+struct Sink {
+    callback: extern "C" fn(i32, *const c_char, *const c_char, *mut c_void),
+    user_data: *mut c_void,
+}
+
+// user_data 由调用方保证可以在任意线程使用
+unsafe impl Send for Sink {}
+unsafe impl Sync for Sink {}
+
+struct LoggerState {
+    sink: Option<Sink>,
+    level: LevelFilter,
+    // 按模块前缀设置的日志等级
+    module_levels: BTreeMap<String, LevelFilter>,
+}
+
+struct Logger {
+    state: RwLock<LoggerState>,
+}
+
+static LOGGER: Logger = Logger {
+    state: RwLock::new(LoggerState {
+        sink: None,
+        level: LevelFilter::Off,
+        module_levels: BTreeMap::new(),
+    }),
+};
+
+static INIT: Once = Once::new();
+
+fn level_filter(level: i32) -> LevelFilter {
+    match level {
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        5 => LevelFilter::Trace,
+        _ => LevelFilter::Off,
+    }
+}
+
+impl LoggerState {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        // BTreeMap按字典序遍历, 较短的前缀在前, 最后一个匹配的即为最长前缀
+        let mut matched = self.level;
+        for (module, level) in &self.module_levels {
+            if target == module
+                || (target.starts_with(module.as_str()) && target[module.len()..].starts_with("::"))
+            {
+                matched = *level;
+            }
+        }
+        matched
+    }
+
+    /// log crate的全局等级, 取所有设置中最详细的
+    fn max_level(&self) -> LevelFilter {
+        if self.sink.is_none() {
+            return LevelFilter::Off;
+        }
+        self.module_levels
+            .values()
+            .copied()
+            .fold(self.level, Ord::max)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let state = self.state.read().unwrap();
+        state.sink.is_some() && metadata.level() <= state.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        let state = self.state.read().unwrap();
+        let sink = match &state.sink {
+            Some(sink) => sink,
+            None => return,
+        };
+        if record.level() > state.level_for(record.target()) {
+            return;
+        }
+
+        let message = redact(&record.args().to_string());
+        let (target, message) = match (CString::new(record.target()), CString::new(message)) {
+            (Ok(target), Ok(message)) => (target, message),
+            _ => return,
+        };
+        (sink.callback)(
+            record.level() as i32,
+            target.as_ptr(),
+            message.as_ptr(),
+            sink.user_data,
+        );
+    }
+
+    fn flush(&self) {}
+}
+
+fn update<F: FnOnce(&mut LoggerState)>(f: F) {
+    INIT.call_once(|| {
+        let _ = log::set_logger(&LOGGER);
+    });
+    let mut state = LOGGER.state.write().unwrap();
+    f(&mut state);
+    log::set_max_level(state.max_level());
+}
+
+/// 是否为需要脱敏的请求头或响应头
+pub fn is_sensitive_header(name: &str) -> bool {
+    REDACTED_HEADERS
+        .iter()
+        .any(|header| name.eq_ignore_ascii_case(header))
+}
+
+/// 隐藏敏感header的值
+/// 兼容 `Authorization: xxx`、`cookie=xxx` 以及 `{"cookie": "xxx"}` 等形式
+pub fn redact(message: &str) -> String {
+    let lower = message.to_ascii_lowercase();
+    let lower = lower.as_bytes();
+    let bytes = message.as_bytes();
+    let mut result = String::with_capacity(message.len());
+    let mut copied = 0;
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let name = REDACTED_HEADERS
+            .iter()
+            .find(|name| lower[pos..].starts_with(name.as_bytes()));
+        let name = match name {
+            Some(name) if pos == 0 || !is_name_char(bytes[pos - 1]) => name,
+            _ => {
+                pos += 1;
+                continue;
+            }
+        };
+
+        let mut cursor = pos + name.len();
+        if bytes.get(cursor) == Some(&b'"') {
+            cursor += 1;
+        }
+        cursor = skip_spaces(bytes, cursor);
+        if !matches!(bytes.get(cursor), Some(b':') | Some(b'=')) {
+            pos += name.len();
+            continue;
+        }
+        cursor = skip_spaces(bytes, cursor + 1);
+
+        let quoted = bytes.get(cursor) == Some(&b'"');
+        if quoted {
+            cursor += 1;
+        }
+        let value_start = cursor;
+        while cursor < bytes.len() {
+            let c = bytes[cursor];
+            if (quoted && c == b'"') || c == b'\n' || c == b'\r' {
+                break;
+            }
+            cursor += 1;
+        }
+        if cursor > value_start {
+            result.push_str(&message[copied..value_start]);
+            result.push_str("***");
+            copied = cursor;
+        }
+        pos = cursor.max(pos + 1);
+    }
+    result.push_str(&message[copied..]);
+    result
+}
+
+fn is_name_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c == b'-'
+}
+
+fn skip_spaces(bytes: &[u8], mut cursor: usize) -> usize {
+    while bytes.get(cursor) == Some(&b' ') {
+        cursor += 1;
+    }
+    cursor
+}
+
+/// 设置日志回调, callback 为空时关闭日志
+/// level: 0 关闭, 1 error, 2 warn, 3 info, 4 debug, 5 trace
+/// 回调可能在任意线程中被调用, 回调中不能再调用日志设置相关的接口
+#[no_mangle]
+pub extern "C" fn rust_net_set_log_callback(
+    level: i32,
+    callback: LogCallback,
+    user_data: *mut c_void,
+) {
+    update(|state| {
+        state.level = level_filter(level);
+        state.sink = callback.map(|callback| Sink {
+            callback,
+            user_data,
+        });
+    });
+}
+
+/// 按模块设置日志等级, 覆盖 rust_net_set_log_callback 中的等级
+/// module 为模块前缀, 例如 rust_net::websocket、reqwest、hyper
+/// level 小于0时移除该模块的设置
+///
+/// # Safety
+/// module 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_set_log_module_level(module: *const c_char, level: i32) {
+    let module = CStr::from_ptr(module).to_str().unwrap().to_string();
+    update(|state| {
+        if level < 0 {
+            state.module_levels.remove(&module);
+        } else {
+            state.module_levels.insert(module, level_filter(level));
+        }
+    });
+}
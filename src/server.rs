@@ -0,0 +1,597 @@
+use crate::TokioContext;
+use hyper::body::HttpBody;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::ffi::{CStr, CString};
+use std::net::SocketAddr;
+use std::os::raw::c_char;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+// 请求body的上限, 超出时返回413
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+// 等待宿主响应的时间, 超时返回504
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 内嵌的HTTP服务器
+pub struct HttpServerContext {
+    local_addr: SocketAddr,
+    shared: Arc<Shared>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    // 等待宿主取走的请求
+    queue: Mutex<VecDeque<u64>>,
+    // 等待宿主响应的请求
+    requests: Mutex<HashMap<u64, PendingRequest>>,
+    next_id: AtomicU64,
+    // 静态目录 (url前缀, 本地目录)
+    static_dirs: RwLock<Vec<(String, PathBuf)>>,
+}
+
+/// 请求头与响应头的值, 同名的多个header以数组表示
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+enum HeaderValues {
+    One(String),
+    Many(Vec<String>),
+}
+
+struct PendingRequest {
+    method: String,
+    path: String,
+    query: String,
+    headers: String,
+    body: Vec<u8>,
+    remote_addr: SocketAddr,
+    responder: oneshot::Sender<Response<Body>>,
+}
+
+/// 启动HTTP服务器, addr 例如 "0.0.0.0:8080", 端口为0时由系统分配
+/// 绑定失败时返回空指针
+///
+/// # Safety
+/// addr 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_server_new(
+    context: &mut TokioContext,
+    addr: *const c_char,
+) -> *mut HttpServerContext {
+    let addr = CStr::from_ptr(addr).to_str().unwrap();
+    let _guard = context.runtime.enter();
+    let server = addr
+        .parse::<SocketAddr>()
+        .map_err(|err| err.to_string())
+        .and_then(|addr| Server::try_bind(&addr).map_err(|err| err.to_string()));
+    let server = match server {
+        Ok(server) => server,
+        Err(err) => {
+            log::error!("http server bind {} failed: {}", addr, err);
+            return std::ptr::null_mut();
+        }
+    };
+
+    let shared = Arc::new(Shared {
+        next_id: AtomicU64::new(1),
+        ..Default::default()
+    });
+    let service_shared = shared.clone();
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let shared = service_shared.clone();
+        let remote_addr = conn.remote_addr();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle(shared.clone(), remote_addr, request)
+            }))
+        }
+    });
+    let server = server.serve(make_service);
+    let local_addr = server.local_addr();
+
+    let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+    let server = server.with_graceful_shutdown(async {
+        let _ = shutdown_rx.await;
+    });
+    context.runtime.spawn(async move {
+        if let Err(err) = server.await {
+            log::warn!("http server error: {}", err);
+        }
+    });
+    log::info!("http server listening on {}", local_addr);
+
+    Box::into_raw(Box::new(HttpServerContext {
+        local_addr,
+        shared,
+        shutdown: Some(shutdown),
+    }))
+}
+
+/// 停止服务器, 未响应的请求返回503
+///
+/// # Safety
+/// server 必须是 rust_net_http_server_new 返回的指针, 释放后不能再使用
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_server_free(server: *mut HttpServerContext) {
+    let mut server = Box::from_raw(server);
+    if let Some(shutdown) = server.shutdown.take() {
+        let _ = shutdown.send(());
+    }
+    server.shared.requests.lock().unwrap().clear();
+    server.shared.queue.lock().unwrap().clear();
+    drop(server)
+}
+
+/// 实际监听的地址, 例如 "0.0.0.0:8080"
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_http_server_local_addr(server: &mut HttpServerContext) -> *mut c_char {
+    into_c_string(&server.local_addr.to_string())
+}
+
+/// 将url前缀映射到本地目录, 该前缀下的GET/HEAD请求直接返回文件, 不经过宿主
+/// 例如 prefix "/static" dir "./web", 请求目录时返回其中的 index.html
+/// 目录不存在时返回false
+///
+/// # Safety
+/// prefix、dir 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_server_serve_dir(
+    server: &mut HttpServerContext,
+    prefix: *const c_char,
+    dir: *const c_char,
+) -> bool {
+    let prefix = CStr::from_ptr(prefix).to_str().unwrap();
+    let dir = CStr::from_ptr(dir).to_str().unwrap();
+    let dir = PathBuf::from(dir);
+    if !dir.is_dir() {
+        log::error!("http server static dir not found: {}", dir.display());
+        return false;
+    }
+    let prefix = format!("/{}", prefix.trim_matches('/'));
+    let mut static_dirs = server.shared.static_dirs.write().unwrap();
+    static_dirs.retain(|(exists, _)| *exists != prefix);
+    static_dirs.push((prefix, dir));
+    // 前缀长的优先匹配
+    static_dirs.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+    true
+}
+
+/// 取出一个待处理的请求, 返回请求id, 没有请求时返回0
+/// 取出的请求需要调用 rust_net_http_server_respond 响应, 否则60秒后返回504
+#[no_mangle]
+pub extern "C" fn rust_net_http_server_poll_request(server: &mut HttpServerContext) -> u64 {
+    server
+        .shared
+        .queue
+        .lock()
+        .unwrap()
+        .pop_front()
+        .unwrap_or_default()
+}
+
+/// 请求的method
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_http_server_get_method(
+    server: &mut HttpServerContext,
+    id: u64,
+) -> *mut c_char {
+    server
+        .with_request(id, |request| into_c_string(&request.method))
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// 请求的path, 不包含query
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_http_server_get_path(
+    server: &mut HttpServerContext,
+    id: u64,
+) -> *mut c_char {
+    server
+        .with_request(id, |request| into_c_string(&request.path))
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// 请求的query, 不包含?, 没有query时为空字符串
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_http_server_get_query(
+    server: &mut HttpServerContext,
+    id: u64,
+) -> *mut c_char {
+    server
+        .with_request(id, |request| into_c_string(&request.query))
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// 请求头json, 例如 {"host":"example.com","cookie":["a=1","b=2"]}
+/// 名称为小写, 只出现一次的请求头值为字符串, 重复出现的请求头值为按顺序排列的数组
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_http_server_get_headers(
+    server: &mut HttpServerContext,
+    id: u64,
+) -> *mut c_char {
+    server
+        .with_request(id, |request| into_c_string(&request.headers))
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// 客户端地址, 例如 "192.168.1.2:53000"
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_http_server_get_remote_addr(
+    server: &mut HttpServerContext,
+    id: u64,
+) -> *mut c_char {
+    server
+        .with_request(id, |request| {
+            into_c_string(&request.remote_addr.to_string())
+        })
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// 请求body的副本, 请求不存在时 data 为空
+/// 使用完成之后 调用 rust_net_http_server_free_body 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_http_server_get_body(
+    server: &mut HttpServerContext,
+    id: u64,
+) -> HttpServerBody {
+    server
+        .with_request(id, |request| HttpServerBody::from(request.body.clone()))
+        .unwrap_or_else(HttpServerBody::default)
+}
+
+#[no_mangle]
+pub extern "C" fn rust_net_http_server_free_body(body: HttpServerBody) {
+    if body.data.is_null() || body.cap == 0 {
+        return;
+    }
+    unsafe {
+        let buffer = Vec::from_raw_parts(body.data as *mut u8, body.len, body.cap);
+        // Rust 会在这里清理内存
+        drop(buffer);
+    }
+}
+
+/// 响应请求, headers 为json字符串, 可以为空
+/// {"Content-Type": "text/html", "Set-Cookie": ["a=1; Path=/", "b=2; Path=/"]}
+/// 同名的多个header以数组传入
+/// 请求不存在(已响应或超时)或 status 不是有效的状态码时返回false
+///
+/// # Safety
+/// headers 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+/// body 可以为空, 不为空时必须指向至少 len 字节的可读内存
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_http_server_respond(
+    server: &mut HttpServerContext,
+    id: u64,
+    status: u16,
+    headers: *const c_char,
+    body: *const u8,
+    len: usize,
+) -> bool {
+    // 状态码无效时请求保留, 宿主可以重新响应
+    let status = match StatusCode::from_u16(status) {
+        Ok(status) => status,
+        Err(_) => {
+            log::error!("http server request {} invalid status: {}", id, status);
+            return false;
+        }
+    };
+    let request = match server.shared.requests.lock().unwrap().remove(&id) {
+        Some(request) => request,
+        None => return false,
+    };
+
+    let body = if body.is_null() {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(body, len).to_vec()
+    };
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    if !headers.is_null() {
+        let headers = CStr::from_ptr(headers).to_str().unwrap();
+        let headers = serde_json::from_str::<HashMap<String, HeaderValues>>(headers)
+            .unwrap_or_else(|err| {
+                log::warn!("http server response headers json decode error: {}", err);
+                HashMap::new()
+            });
+        for (key, values) in headers {
+            let values = match values {
+                HeaderValues::One(value) => vec![value],
+                HeaderValues::Many(values) => values,
+            };
+            for value in values {
+                if let (Ok(key), Ok(value)) = (
+                    HeaderName::from_bytes(key.as_bytes()),
+                    HeaderValue::from_str(&value),
+                ) {
+                    response.headers_mut().append(key, value);
+                }
+            }
+        }
+    }
+    request.responder.send(response).is_ok()
+}
+
+impl HttpServerContext {
+    fn with_request<T>(&self, id: u64, f: impl FnOnce(&PendingRequest) -> T) -> Option<T> {
+        self.shared.requests.lock().unwrap().get(&id).map(f)
+    }
+}
+
+/// 将请求头转换为与响应头相同的形式, 重复的请求头保留所有值
+fn header_values(headers: &HeaderMap) -> HashMap<&str, HeaderValues> {
+    headers
+        .keys()
+        .filter_map(|key| {
+            let mut values = headers
+                .get_all(key)
+                .iter()
+                .filter_map(|value| Some(value.to_str().ok()?.to_string()))
+                .collect::<Vec<_>>();
+            let values = match values.len() {
+                0 => return None,
+                1 => HeaderValues::One(values.remove(0)),
+                _ => HeaderValues::Many(values),
+            };
+            Some((key.as_str(), values))
+        })
+        .collect()
+}
+
+async fn handle(
+    shared: Arc<Shared>,
+    remote_addr: SocketAddr,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    if let Some(response) = serve_static(&shared, &request).await {
+        return Ok(response);
+    }
+
+    let (parts, mut body) = request.into_parts();
+    let content_length = parts
+        .headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or_default();
+    if content_length > MAX_BODY_SIZE {
+        return Ok(status_response(StatusCode::PAYLOAD_TOO_LARGE));
+    }
+    let mut data = Vec::with_capacity(content_length);
+    while let Some(chunk) = body.data().await {
+        match chunk {
+            Ok(chunk) if data.len() + chunk.len() <= MAX_BODY_SIZE => {
+                data.extend_from_slice(&chunk)
+            }
+            Ok(_) => return Ok(status_response(StatusCode::PAYLOAD_TOO_LARGE)),
+            Err(err) => {
+                log::debug!("http server read body from {} failed: {}", remote_addr, err);
+                return Ok(status_response(StatusCode::BAD_REQUEST));
+            }
+        }
+    }
+
+    let headers = header_values(&parts.headers);
+    let (responder, response) = oneshot::channel();
+    let id = shared.next_id.fetch_add(1, Ordering::Relaxed);
+    shared.requests.lock().unwrap().insert(
+        id,
+        PendingRequest {
+            method: parts.method.as_str().to_string(),
+            path: parts.uri.path().to_string(),
+            query: parts.uri.query().unwrap_or_default().to_string(),
+            headers: serde_json::to_string(&headers).unwrap_or_else(|_| "{}".into()),
+            body: data,
+            remote_addr,
+            responder,
+        },
+    );
+    shared.queue.lock().unwrap().push_back(id);
+    log::debug!("http server request {} {} {}", id, parts.method, parts.uri);
+
+    match tokio::time::timeout(RESPONSE_TIMEOUT, response).await {
+        Ok(Ok(response)) => Ok(response),
+        // 服务器已停止
+        Ok(Err(_)) => Ok(status_response(StatusCode::SERVICE_UNAVAILABLE)),
+        Err(_) => {
+            log::warn!("http server request {} not responded in time", id);
+            shared.requests.lock().unwrap().remove(&id);
+            shared.queue.lock().unwrap().retain(|queued| *queued != id);
+            Ok(status_response(StatusCode::GATEWAY_TIMEOUT))
+        }
+    }
+}
+
+/// 匹配静态目录时返回文件或404, 否则返回None交给宿主处理
+async fn serve_static(shared: &Shared, request: &Request<Body>) -> Option<Response<Body>> {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return None;
+    }
+    let path = request.uri().path();
+    let (rest, dir) = shared
+        .static_dirs
+        .read()
+        .unwrap()
+        .iter()
+        .find_map(|(prefix, dir)| {
+            let rest = match prefix.as_str() {
+                "/" => path,
+                prefix => path.strip_prefix(prefix)?,
+            };
+            (rest.is_empty() || rest.starts_with('/')).then(|| (rest.to_string(), dir.clone()))
+        })?;
+
+    let mut file = match resolve_path(&dir, &rest) {
+        Some(file) => file,
+        None => return Some(status_response(StatusCode::NOT_FOUND)),
+    };
+    if file.is_dir() {
+        file.push("index.html");
+    }
+    let data = match tokio::fs::read(&file).await {
+        Ok(data) => data,
+        Err(_) => return Some(status_response(StatusCode::NOT_FOUND)),
+    };
+
+    let len = data.len();
+    let body = if request.method() == Method::HEAD {
+        Body::empty()
+    } else {
+        Body::from(data)
+    };
+    let response = Response::builder()
+        .header(CONTENT_TYPE, content_type(&file))
+        .header(CONTENT_LENGTH, len)
+        .body(body)
+        .ok()?;
+    Some(response)
+}
+
+/// 将url路径映射到目录下的文件, 不允许访问目录之外的文件
+fn resolve_path(dir: &Path, path: &str) -> Option<PathBuf> {
+    let path = percent_decode(path)?;
+    let mut file = dir.to_path_buf();
+    for component in Path::new(path.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(name) => file.push(name),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(file)
+}
+
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    let decoded = String::from_utf8(decoded).ok()?;
+    // 不允许通过编码绕过路径检查
+    if decoded.contains('\\') || decoded.contains('\0') {
+        return None;
+    }
+    Some(decoded)
+}
+
+fn content_type(file: &Path) -> &'static str {
+    let extension = file
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" | "log" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "wasm" => "application/wasm",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::from(status.to_string()));
+    *response.status_mut() = status;
+    response
+}
+
+fn into_c_string(value: &str) -> *mut c_char {
+    match CString::new(value) {
+        Ok(cstr) => cstr.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// 请求body
+#[repr(C)]
+pub struct HttpServerBody {
+    data: *const u8,
+    len: usize,
+    cap: usize,
+}
+
+impl HttpServerBody {
+    fn default() -> Self {
+        Self {
+            data: std::ptr::null(),
+            len: 0,
+            cap: 0,
+        }
+    }
+
+    fn from(buffer: Vec<u8>) -> Self {
+        let ret = Self {
+            data: buffer.as_ptr(),
+            len: buffer.len(),
+            cap: buffer.capacity(),
+        };
+        // 防止 Rust 在离开这个函数时自动清理 buffer
+        std::mem::forget(buffer);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_request_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("example.com"));
+        headers.append("cookie", HeaderValue::from_static("a=1"));
+        headers.append("cookie", HeaderValue::from_static("b=2"));
+        headers.append("x-binary", HeaderValue::from_bytes(b"\xff").unwrap());
+
+        let values = header_values(&headers);
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["host"], HeaderValues::One("example.com".into()));
+        assert_eq!(
+            values["cookie"],
+            HeaderValues::Many(vec!["a=1".into(), "b=2".into()])
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string(&values).unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"host": "example.com", "cookie": ["a=1", "b=2"]})
+        );
+    }
+}
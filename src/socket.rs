@@ -0,0 +1,491 @@
+mod framing;
+mod kcp;
+mod listener;
+mod tls;
+mod udp;
+
+use crate::http::dns::{DnsConfig, Resolver};
+use crate::websocket::tcp_connect;
+use crate::TokioContext;
+use anyhow::{anyhow, Result};
+use bytes::{Bytes, BytesMut};
+use framing::FramingConfig;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString};
+use std::net::{IpAddr, SocketAddr};
+use std::os::raw::c_char;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tls::TlsConfig;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::select;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::OnceCell;
+use tokio_util::codec::{Decoder, Encoder, LengthDelimitedCodec};
+
+// 单次读取的最小缓冲区大小
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+enum SocketMessage {
+    // 连接成功, 附带实际连接的远端地址
+    ConnectSuccess(SocketAddr),
+    // 连接失败
+    ConnectFailed(String),
+    // 断开连接
+    Disconnect(String),
+    // 收到数据
+    Recv(Vec<u8>),
+    // 一条消息发送失败, 连接不受影响
+    SendFailed(String),
+}
+
+enum SocketWriterMessage {
+    Send(Vec<u8>),
+    Close,
+}
+
+type MessageQueue = Arc<Mutex<VecDeque<SocketMessage>>>;
+
+/// 连接使用的流, TCP或TLS
+trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Stream for T {}
+
+/// TCP连接
+pub struct TcpContext {
+    msg_queue: MessageQueue,
+    tx: Arc<OnceCell<UnboundedSender<SocketWriterMessage>>>,
+    // TLS握手协商出的ALPN协议
+    alpn_protocol: Arc<OnceLock<String>>,
+}
+
+impl TcpContext {
+    fn new() -> TcpContext {
+        TcpContext {
+            msg_queue: Default::default(),
+            tx: Arc::new(OnceCell::new()),
+            alpn_protocol: Default::default(),
+        }
+    }
+}
+
+/// 连接配置, 以json形式传入 rust_net_tcp_connect
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct TcpConfig {
+    // DNS配置: 静态host映射、缓存时间及地址族选择
+    dns: DnsConfig,
+    // Happy Eyeballs: 上一个地址连接未完成时, 间隔多久(毫秒)开始尝试下一个地址 0表示默认250
+    happy_eyeballs_delay_ms: u64,
+    // 连接超时(毫秒), 包括DNS解析 0表示不限制
+    connect_timeout_ms: u64,
+    // 是否开启 TCP_NODELAY
+    nodelay: bool,
+    // TCP keepalive 空闲多久(毫秒)开始探测 0表示关闭
+    keepalive_ms: u64,
+    // 不为空时在TCP连接上进行TLS握手
+    tls: Option<TlsConfig>,
+    // 不为空时按长度前缀分帧收发
+    framing: Option<FramingConfig>,
+}
+
+/// 连接 host:port, config 可以为空
+/// {
+///     "dns": {
+///         "hosts": {"game.example.com": ["10.0.0.1"]},
+///         "cache_ttl_ms": 0,
+///         "ip_family": "auto"
+///     },
+///     "happy_eyeballs_delay_ms": 250,
+///     "connect_timeout_ms": 5000,
+///     "nodelay": true,
+///     "keepalive_ms": 30000,
+///     "tls": {
+///         "server_name": "game.example.com",
+///         "root_certificates": [],
+///         "alpn_protocols": ["game/1"]
+///     },
+///     "framing": {
+///         "header_size": 4,
+///         "byte_order": "big",
+///         "length_includes_header": false,
+///         "max_frame_size": 8388608
+///     }
+/// }
+/// tls.server_name 为空时使用host, root_certificates 为PEM格式的自定义根证书, 与库内置的 Mozilla 根证书(webpki-roots)一起使用, 不读取操作系统的证书库
+/// 不需要TLS时不传 tls
+/// framing 开启后每个接收事件恰好是一条完整消息, rust_net_tcp_send 的每段数据作为一条消息发送
+/// byte_order: big、little
+/// 连接结果通过 rust_net_tcp_get_message 获取
+/// dns 配置相同的连接共享解析缓存
+/// config 解析失败或 dns、framing 配置无效时返回空指针
+///
+/// # Safety
+/// host 必须是以\0结尾的UTF-8字符串
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_tcp_connect(
+    context: &mut TokioContext,
+    host: *const c_char,
+    port: u16,
+    config: *const c_char,
+) -> *mut TcpContext {
+    let host = CStr::from_ptr(host).to_str().unwrap().to_string();
+    log::debug!("tcp connect {}:{}", host, port);
+
+    let config = if config.is_null() {
+        TcpConfig::default()
+    } else {
+        let config = CStr::from_ptr(config).to_str().unwrap();
+        match serde_json::from_str::<TcpConfig>(config) {
+            Ok(config) => config,
+            Err(err) => {
+                log::error!("tcp connect config error: {}", err);
+                return std::ptr::null_mut();
+            }
+        }
+    };
+    let codec = match config.framing.as_ref().map(FramingConfig::codec) {
+        Some(Ok(codec)) => Some(codec),
+        Some(Err(err)) => {
+            log::error!("tcp connect config error: {}", err);
+            return std::ptr::null_mut();
+        }
+        None => None,
+    };
+    let resolver = match context
+        .resolvers
+        .get(&config.dns, None, std::ptr::null_mut())
+    {
+        Ok(resolver) => resolver,
+        Err(err) => {
+            log::error!("tcp connect config error: {}", err);
+            return std::ptr::null_mut();
+        }
+    };
+
+    let tcp_context = TcpContext::new();
+
+    let tx_cloned = tcp_context.tx.clone();
+    let msg_queue = tcp_context.msg_queue.clone();
+    let alpn_protocol = tcp_context.alpn_protocol.clone();
+
+    context.runtime.spawn(async move {
+        let result = match config.connect_timeout_ms {
+            0 => connect(&host, port, &config, &resolver).await,
+            timeout => {
+                let timeout = Duration::from_millis(timeout);
+                tokio::time::timeout(timeout, connect(&host, port, &config, &resolver))
+                    .await
+                    .unwrap_or_else(|_| Err(anyhow!("connect timed out")))
+            }
+        };
+
+        // 调用了 rust_net_tcp_connect 之后 立即调用 rust_net_tcp_free 销毁了TcpContext
+        if Arc::strong_count(&tx_cloned) == 1 {
+            return;
+        }
+
+        match result {
+            Ok((stream, remote_addr, protocol)) => {
+                log::info!("tcp connected to {}", remote_addr);
+                if let Some(protocol) = protocol {
+                    let _ = alpn_protocol.set(protocol);
+                }
+                run(stream, remote_addr, codec, tx_cloned, msg_queue).await;
+            }
+            Err(err) => {
+                log::warn!("tcp connect failed: {}", err);
+                push(&msg_queue, SocketMessage::ConnectFailed(err.to_string()));
+            }
+        }
+    });
+
+    Box::into_raw(Box::new(tcp_context))
+}
+
+/// # Safety
+/// data 可以为空, 不为空时必须指向至少 length 字节的可读内存
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_tcp_send(
+    tcp_context: &mut TcpContext,
+    data: *const u8,
+    length: usize,
+) {
+    if data.is_null() {
+        return;
+    }
+    if let Some(tx) = tcp_context.tx.get() {
+        let data = std::slice::from_raw_parts(data, length).to_vec();
+        let _ = tx.send(SocketWriterMessage::Send(data));
+    }
+}
+
+/// 取出一个事件, 没有事件时 message_type 为0
+/// 使用完成之后 调用 rust_net_tcp_free_message 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_tcp_get_message(tcp_context: &mut TcpContext) -> SocketMessageData {
+    match tcp_context.msg_queue.lock().unwrap().pop_front() {
+        Some(message) => SocketMessageData::from(message),
+        None => SocketMessageData::default(),
+    }
+}
+
+/// TLS握手协商出的ALPN协议, 未使用TLS或未协商时返回空指针
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_tcp_get_alpn_protocol(tcp_context: &mut TcpContext) -> *mut c_char {
+    match tcp_context.alpn_protocol.get() {
+        Some(protocol) => match CString::new(protocol.as_str()) {
+            Ok(cstr) => cstr.into_raw(),
+            Err(_) => std::ptr::null_mut(),
+        },
+        None => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn rust_net_tcp_close(tcp_context: &mut TcpContext) {
+    if let Some(tx) = tcp_context.tx.get() {
+        let _ = tx.send(SocketWriterMessage::Close);
+    }
+}
+
+/// # Safety
+/// tcp_context 必须是 rust_net_tcp_connect 或 rust_net_tcp_listener_accept 返回的指针, 释放后不能再使用
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_tcp_free(tcp_context: *mut TcpContext) {
+    let tcp_context = Box::from_raw(tcp_context);
+
+    if let Some(tx) = tcp_context.tx.get() {
+        let _ = tx.send(SocketWriterMessage::Close);
+    }
+
+    drop(tcp_context)
+}
+
+#[no_mangle]
+pub extern "C" fn rust_net_tcp_free_message(message: SocketMessageData) {
+    if message.data.is_null() || message.cap == 0 {
+        return;
+    }
+    unsafe {
+        let buffer = Vec::from_raw_parts(message.data as *mut u8, message.len, message.cap);
+        // Rust 会在这里清理内存
+        drop(buffer);
+    }
+}
+
+/// 返回连接的流、远端地址及协商出的ALPN协议
+async fn connect(
+    host: &str,
+    port: u16,
+    config: &TcpConfig,
+    resolver: &Resolver,
+) -> Result<(Box<dyn Stream>, SocketAddr, Option<String>)> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs = match host.parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => resolver
+            .lookup(host)
+            .await?
+            .into_iter()
+            .map(|addr| SocketAddr::new(addr.ip(), port))
+            .collect(),
+    };
+    let happy_eyeballs_delay = match config.happy_eyeballs_delay_ms {
+        0 => Duration::from_millis(250),
+        delay => Duration::from_millis(delay),
+    };
+    let (stream, remote_addr) = tcp_connect(addrs, happy_eyeballs_delay).await?;
+
+    set_options(&stream, config.nodelay, config.keepalive_ms)?;
+
+    match &config.tls {
+        Some(tls) => {
+            let stream = tls::connect(stream, host, tls).await?;
+            let protocol = stream
+                .get_ref()
+                .1
+                .alpn_protocol()
+                .map(|protocol| String::from_utf8_lossy(protocol).into_owned());
+            Ok((Box::new(stream), remote_addr, protocol))
+        }
+        None => Ok((Box::new(stream), remote_addr, None)),
+    }
+}
+
+fn set_options(stream: &TcpStream, nodelay: bool, keepalive_ms: u64) -> std::io::Result<()> {
+    stream.set_nodelay(nodelay)?;
+    if keepalive_ms > 0 {
+        let keepalive = Duration::from_millis(keepalive_ms);
+        let keepalive = socket2::TcpKeepalive::new()
+            .with_time(keepalive)
+            .with_interval(keepalive);
+        socket2::SockRef::from(stream).set_tcp_keepalive(&keepalive)?;
+    }
+    Ok(())
+}
+
+/// 连接建立后收发数据直到断开
+async fn run<S>(
+    stream: S,
+    remote_addr: SocketAddr,
+    codec: Option<LengthDelimitedCodec>,
+    tx: Arc<OnceCell<UnboundedSender<SocketWriterMessage>>>,
+    msg_queue: MessageQueue,
+) where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (writer_tx, rx) = unbounded_channel();
+    if tx.set(writer_tx).is_err() {
+        push(
+            &msg_queue,
+            SocketMessage::ConnectFailed("init failed".to_string()),
+        );
+        return;
+    }
+    // 不持有发送端, TcpContext 释放后写任务随之结束
+    drop(tx);
+    push(&msg_queue, SocketMessage::ConnectSuccess(remote_addr));
+
+    let (reader, writer) = tokio::io::split(stream);
+    select! {
+        _ = poll_read(reader, codec.clone(), msg_queue.clone()) => {}
+        _ = poll_write(writer, codec, rx, msg_queue) => {}
+    }
+    log::info!("tcp disconnected from {}", remote_addr);
+}
+
+async fn poll_write(
+    mut writer: impl AsyncWrite + Unpin,
+    mut codec: Option<LengthDelimitedCodec>,
+    mut rx: UnboundedReceiver<SocketWriterMessage>,
+    msg_queue: MessageQueue,
+) {
+    while let Some(message) = rx.recv().await {
+        match message {
+            SocketWriterMessage::Send(data) => {
+                let data = match &mut codec {
+                    Some(codec) => {
+                        let mut frame = BytesMut::new();
+                        if let Err(err) = codec.encode(Bytes::from(data), &mut frame) {
+                            log::warn!("tcp frame encode failed: {}", err);
+                            push(&msg_queue, SocketMessage::SendFailed(err.to_string()));
+                            continue;
+                        }
+                        frame.to_vec()
+                    }
+                    None => data,
+                };
+                if let Err(err) = writer.write_all(&data).await {
+                    push(&msg_queue, SocketMessage::Disconnect(err.to_string()));
+                    break;
+                }
+            }
+            SocketWriterMessage::Close => {
+                let _ = writer.shutdown().await;
+                push(
+                    &msg_queue,
+                    SocketMessage::Disconnect("proactively disconnect".to_string()),
+                );
+                break;
+            }
+        }
+    }
+    rx.close();
+}
+
+async fn poll_read(
+    mut reader: impl AsyncRead + Unpin,
+    mut codec: Option<LengthDelimitedCodec>,
+    msg_queue: MessageQueue,
+) {
+    let mut buffer = BytesMut::new();
+    loop {
+        buffer.reserve(READ_BUFFER_SIZE);
+        match reader.read_buf(&mut buffer).await {
+            Ok(0) => {
+                push(&msg_queue, SocketMessage::Disconnect("close".into()));
+                break;
+            }
+            Ok(_) => {
+                let codec = match &mut codec {
+                    Some(codec) => codec,
+                    None => {
+                        push(&msg_queue, SocketMessage::Recv(buffer.split().to_vec()));
+                        continue;
+                    }
+                };
+                // 一次读取可能包含多条消息, 也可能不足一条
+                loop {
+                    match codec.decode(&mut buffer) {
+                        Ok(Some(frame)) => push(&msg_queue, SocketMessage::Recv(frame.to_vec())),
+                        Ok(None) => break,
+                        Err(err) => {
+                            push(&msg_queue, SocketMessage::Disconnect(err.to_string()));
+                            return;
+                        }
+                    }
+                }
+            }
+            Err(err) => {
+                push(&msg_queue, SocketMessage::Disconnect(err.to_string()));
+                break;
+            }
+        }
+    }
+}
+
+fn push(msg_queue: &MessageQueue, message: SocketMessage) {
+    msg_queue.lock().unwrap().push_back(message);
+}
+
+/// 连接事件
+/// message_type:
+/// 0: 没有事件
+/// 1: 连接成功, data为远端地址
+/// 2: 连接失败, data为原因
+/// 3: 断开连接, data为原因
+/// 7: 收到数据
+/// 8: 一条消息发送失败, data为原因, 例如开启 framing 时消息超过 max_frame_size
+/// 与 rust_net_ws_get_message 的 message_type 一致
+#[repr(C)]
+pub struct SocketMessageData {
+    message_type: i32,
+    data: *const u8,
+    len: usize,
+    cap: usize,
+}
+
+impl SocketMessageData {
+    fn default() -> Self {
+        Self {
+            message_type: 0,
+            data: std::ptr::null(),
+            len: 0,
+            cap: 0,
+        }
+    }
+
+    fn from(message: SocketMessage) -> Self {
+        let (message_type, buffer): (i32, Vec<u8>) = match message {
+            SocketMessage::ConnectSuccess(remote_addr) => (1, remote_addr.to_string().into()),
+            SocketMessage::ConnectFailed(reason) => (2, reason.into()),
+            SocketMessage::Disconnect(reason) => (3, reason.into()),
+            SocketMessage::Recv(data) => (7, data),
+            SocketMessage::SendFailed(reason) => (8, reason.into()),
+        };
+        let ret = Self {
+            message_type,
+            data: buffer.as_ptr(),
+            len: buffer.len(),
+            cap: buffer.capacity(),
+        };
+        // 防止 Rust 在离开这个函数时自动清理 buffer
+        std::mem::forget(buffer);
+        ret
+    }
+}
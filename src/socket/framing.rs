@@ -0,0 +1,60 @@
+use serde::Deserialize;
+use tokio_util::codec::LengthDelimitedCodec;
+
+/// 长度前缀分帧配置, 作为 rust_net_tcp_connect 配置中的 framing 字段
+/// 开启后每个接收事件恰好是一条完整消息, 发送的每段数据会自动加上长度头
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct FramingConfig {
+    // 长度头的字节数, 2或4
+    pub header_size: usize,
+    pub byte_order: ByteOrder,
+    // 长度是否包含长度头本身
+    pub length_includes_header: bool,
+    // 单条消息(不含长度头)的最大字节数, 收到超出的消息时断开连接
+    pub max_frame_size: usize,
+}
+
+impl Default for FramingConfig {
+    fn default() -> Self {
+        FramingConfig {
+            header_size: 4,
+            byte_order: ByteOrder::Big,
+            length_includes_header: false,
+            max_frame_size: 8 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ByteOrder {
+    Big,
+    Little,
+}
+
+impl FramingConfig {
+    pub fn codec(&self) -> Result<LengthDelimitedCodec, String> {
+        if self.header_size != 2 && self.header_size != 4 {
+            return Err(format!(
+                "framing header_size must be 2 or 4, got {}",
+                self.header_size
+            ));
+        }
+        if self.max_frame_size == 0 {
+            return Err("framing max_frame_size must be greater than 0".into());
+        }
+        let mut builder = LengthDelimitedCodec::builder();
+        builder
+            .length_field_length(self.header_size)
+            .max_frame_length(self.max_frame_size);
+        match self.byte_order {
+            ByteOrder::Big => builder.big_endian(),
+            ByteOrder::Little => builder.little_endian(),
+        };
+        if self.length_includes_header {
+            builder.length_adjustment(-(self.header_size as isize));
+        }
+        Ok(builder.new_codec())
+    }
+}
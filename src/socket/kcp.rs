@@ -0,0 +1,429 @@
+mod protocol;
+
+use super::udp::is_port_unreachable;
+use super::{push, rust_net_tcp_free_message, MessageQueue, SocketMessage, SocketMessageData};
+use crate::TokioContext;
+use anyhow::{anyhow, Result};
+use protocol::Kcp;
+use serde::Deserialize;
+use std::ffi::CStr;
+use std::net::SocketAddr;
+use std::os::raw::c_char;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::select;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+// UDP数据报的最大长度
+const MAX_DATAGRAM_SIZE: usize = 64 * 1024;
+
+enum KcpWriterMessage {
+    Send(Vec<u8>),
+    Close,
+}
+
+/// KCP会话
+pub struct KcpContext {
+    msg_queue: MessageQueue,
+    tx: UnboundedSender<KcpWriterMessage>,
+    stats: Arc<Mutex<KcpStats>>,
+    // 单条消息的最大长度, 由mtu决定
+    max_message_size: usize,
+}
+
+/// KCP配置, 以json形式传入 rust_net_kcp_connect
+#[derive(Deserialize)]
+#[serde(default)]
+struct KcpConfig {
+    // 本地绑定地址, 为空时按远端地址族绑定任意地址与端口
+    local_addr: String,
+    // 0关闭 1开启 2更激进的超时重传
+    nodelay: u32,
+    // 内部时钟间隔(毫秒) 10~5000
+    interval_ms: u32,
+    // 收到多少次跨越确认后快速重传 0表示关闭
+    resend: u32,
+    // 关闭拥塞控制
+    no_congestion_window: bool,
+    // 发送窗口(分片数)
+    send_window: u32,
+    // 接收窗口(分片数) 不小于128
+    recv_window: u32,
+    mtu: u32,
+    // 某个分片重传多少次后认为连接已断开
+    dead_link: u32,
+}
+
+impl Default for KcpConfig {
+    fn default() -> Self {
+        KcpConfig {
+            local_addr: String::new(),
+            nodelay: 0,
+            interval_ms: 100,
+            resend: 0,
+            no_congestion_window: false,
+            send_window: 32,
+            recv_window: 128,
+            mtu: 1400,
+            dead_link: 20,
+        }
+    }
+}
+
+/// KCP会话统计, 时间单位为毫秒
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct KcpStats {
+    // 平滑RTT
+    srtt: u32,
+    // RTT偏差
+    rttvar: u32,
+    // 当前的重传超时
+    rto: u32,
+    // 等待发送及等待确认的分片数
+    wait_send: u32,
+    // 累计超时重传次数
+    retransmits: u32,
+}
+
+/// 与 addr(例如 "10.0.0.1:4000") 建立KCP会话, conv 为会话ID, 两端必须一致, config 可以为空
+/// {
+///     "local_addr": "0.0.0.0:4001",
+///     "nodelay": 1,
+///     "interval_ms": 10,
+///     "resend": 2,
+///     "no_congestion_window": true,
+///     "send_window": 128,
+///     "recv_window": 128,
+///     "mtu": 1400,
+///     "dead_link": 20
+/// }
+/// 以上为常用的极速模式, 默认为普通模式 nodelay 0、interval_ms 100、resend 0
+/// 绑定成功后收到连接成功事件, 重传达到 dead_link 次后收到断开事件
+/// 事件与 rust_net_tcp_get_message 一致
+/// config 解析失败、mtu 小于50或超过UDP数据报的最大长度时返回空指针
+///
+/// # Safety
+/// addr 必须是以\0结尾的UTF-8字符串
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_kcp_connect(
+    context: &mut TokioContext,
+    addr: *const c_char,
+    conv: u32,
+    config: *const c_char,
+) -> *mut KcpContext {
+    let addr = CStr::from_ptr(addr).to_str().unwrap().to_string();
+    let config = if config.is_null() {
+        KcpConfig::default()
+    } else {
+        let config = CStr::from_ptr(config).to_str().unwrap();
+        match serde_json::from_str::<KcpConfig>(config) {
+            Ok(config) => config,
+            Err(err) => {
+                log::error!("kcp connect config error: {}", err);
+                return std::ptr::null_mut();
+            }
+        }
+    };
+    if config.mtu as usize > MAX_DATAGRAM_SIZE {
+        log::error!(
+            "kcp connect config error: mtu {} exceeds {}",
+            config.mtu,
+            MAX_DATAGRAM_SIZE
+        );
+        return std::ptr::null_mut();
+    }
+    let mut kcp = Kcp::new(conv);
+    if let Err(err) = kcp.set_mtu(config.mtu) {
+        log::error!("kcp connect config error: {}", err);
+        return std::ptr::null_mut();
+    }
+
+    let (tx, rx) = unbounded_channel();
+    let kcp_context = KcpContext {
+        msg_queue: Default::default(),
+        tx,
+        stats: Default::default(),
+        max_message_size: kcp.max_message_size(),
+    };
+
+    let msg_queue = kcp_context.msg_queue.clone();
+    let stats = kcp_context.stats.clone();
+    context.runtime.spawn(async move {
+        match bind(&addr, &config).await {
+            Ok((socket, remote_addr)) => {
+                log::info!("kcp {} connected to {}", conv, remote_addr);
+                kcp.set_nodelay(
+                    config.nodelay,
+                    config.interval_ms,
+                    config.resend,
+                    config.no_congestion_window,
+                );
+                kcp.set_wndsize(config.send_window, config.recv_window);
+                kcp.set_dead_link(config.dead_link);
+                push(&msg_queue, SocketMessage::ConnectSuccess(remote_addr));
+                run(kcp, socket, rx, &msg_queue, &stats).await;
+                log::info!("kcp {} disconnected from {}", conv, remote_addr);
+            }
+            Err(err) => {
+                log::warn!("kcp connect failed: {}", err);
+                push(&msg_queue, SocketMessage::ConnectFailed(err.to_string()));
+            }
+        }
+    });
+
+    Box::into_raw(Box::new(kcp_context))
+}
+
+/// 发送一条消息, 对端收到的也是一条完整消息
+/// 消息分片数必须小于128, 即默认mtu下不超过约170KB
+/// 消息过大或会话已断开时返回false, 消息不会发送
+/// 返回true表示消息已进入发送队列, 之后KCP拒绝发送时收到发送失败事件(8), 会话不受影响
+///
+/// # Safety
+/// data 可以为空, 不为空时必须指向至少 length 字节的可读内存
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_kcp_send(
+    kcp_context: &mut KcpContext,
+    data: *const u8,
+    length: usize,
+) -> bool {
+    if data.is_null() {
+        return false;
+    }
+    if length > kcp_context.max_message_size {
+        log::error!(
+            "kcp message too large: {}, max: {}",
+            length,
+            kcp_context.max_message_size
+        );
+        return false;
+    }
+    let data = std::slice::from_raw_parts(data, length).to_vec();
+    kcp_context.tx.send(KcpWriterMessage::Send(data)).is_ok()
+}
+
+/// 取出一个事件, 没有事件时 message_type 为0
+/// 使用完成之后 调用 rust_net_kcp_free_message 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_kcp_get_message(kcp_context: &mut KcpContext) -> SocketMessageData {
+    match kcp_context.msg_queue.lock().unwrap().pop_front() {
+        Some(message) => SocketMessageData::from(message),
+        None => SocketMessageData::default(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn rust_net_kcp_get_stats(kcp_context: &mut KcpContext) -> KcpStats {
+    *kcp_context.stats.lock().unwrap()
+}
+
+#[no_mangle]
+pub extern "C" fn rust_net_kcp_close(kcp_context: &mut KcpContext) {
+    let _ = kcp_context.tx.send(KcpWriterMessage::Close);
+}
+
+/// # Safety
+/// kcp_context 必须是 rust_net_kcp_connect 返回的指针, 释放后不能再使用
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_kcp_free(kcp_context: *mut KcpContext) {
+    let kcp_context = Box::from_raw(kcp_context);
+    let _ = kcp_context.tx.send(KcpWriterMessage::Close);
+    drop(kcp_context)
+}
+
+#[no_mangle]
+pub extern "C" fn rust_net_kcp_free_message(message: SocketMessageData) {
+    rust_net_tcp_free_message(message)
+}
+
+async fn bind(addr: &str, config: &KcpConfig) -> Result<(UdpSocket, SocketAddr)> {
+    let remote_addr = tokio::net::lookup_host(addr)
+        .await?
+        .next()
+        .ok_or_else(|| anyhow!("dns resolved no address"))?;
+    let local_addr = match config.local_addr.as_str() {
+        "" if remote_addr.is_ipv4() => "0.0.0.0:0",
+        "" => "[::]:0",
+        local_addr => local_addr,
+    };
+    let socket = UdpSocket::bind(local_addr).await?;
+    socket.connect(remote_addr).await?;
+    Ok((socket, remote_addr))
+}
+
+/// 驱动KCP时钟并收发数据直到断开
+async fn run(
+    mut kcp: Kcp,
+    socket: UdpSocket,
+    mut rx: UnboundedReceiver<KcpWriterMessage>,
+    msg_queue: &MessageQueue,
+    stats: &Mutex<KcpStats>,
+) {
+    let start = Instant::now();
+    let now = || start.elapsed().as_millis() as u32;
+    let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+    kcp.update(now());
+
+    loop {
+        let current = now();
+        let next = Duration::from_millis(kcp.check(current).wrapping_sub(current) as u64);
+        select! {
+            result = socket.recv(&mut buffer) => match result {
+                Ok(len) => {
+                    if let Err(err) = kcp.input(&buffer[..len]) {
+                        log::debug!("kcp input failed: {}", err);
+                    }
+                    while let Some(data) = kcp.recv() {
+                        push(msg_queue, SocketMessage::Recv(data));
+                    }
+                }
+                // 对端尚未启动时会收到ICMP端口不可达(Windows上为ConnectionReset), 交由重传处理
+                Err(err) if is_port_unreachable(&err) => {}
+                Err(err) => {
+                    push(msg_queue, SocketMessage::Disconnect(err.to_string()));
+                    break;
+                }
+            },
+            message = rx.recv() => match message {
+                Some(KcpWriterMessage::Send(data)) => {
+                    if let Err(err) = kcp.send(&data) {
+                        log::warn!("kcp send failed: {}", err);
+                        push(msg_queue, SocketMessage::SendFailed(err.to_string()));
+                    }
+                    kcp.flush();
+                }
+                Some(KcpWriterMessage::Close) | None => {
+                    push(
+                        msg_queue,
+                        SocketMessage::Disconnect("proactively disconnect".to_string()),
+                    );
+                    break;
+                }
+            },
+            _ = tokio::time::sleep(next) => kcp.update(now()),
+        }
+
+        for datagram in kcp.take_output() {
+            if let Err(err) = socket.send(&datagram).await {
+                log::debug!("kcp send datagram failed: {}", err);
+            }
+        }
+
+        *stats.lock().unwrap() = KcpStats {
+            srtt: kcp.srtt(),
+            rttvar: kcp.rttvar(),
+            rto: kcp.rto(),
+            wait_send: kcp.wait_snd() as u32,
+            retransmits: kcp.retransmits(),
+        };
+
+        if kcp.is_dead_link() {
+            push(
+                msg_queue,
+                SocketMessage::Disconnect("dead link".to_string()),
+            );
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rust_net_tokio_free, rust_net_tokio_new};
+    use std::ffi::CString;
+
+    fn free_port() -> u16 {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.local_addr().unwrap().port()
+    }
+
+    unsafe fn connect(
+        context: &mut TokioContext,
+        local_port: u16,
+        remote_port: u16,
+    ) -> &'static mut KcpContext {
+        let addr = CString::new(format!("127.0.0.1:{}", remote_port)).unwrap();
+        let config = CString::new(format!(
+            r#"{{"local_addr":"127.0.0.1:{}","nodelay":1,"interval_ms":10,"resend":2,"no_congestion_window":true}}"#,
+            local_port
+        ))
+        .unwrap();
+        &mut *rust_net_kcp_connect(context, addr.as_ptr(), 7, config.as_ptr())
+    }
+
+    /// 等待指定类型的事件, 跳过其他事件, 返回事件数据
+    fn wait_message(kcp_context: &mut KcpContext, message_type: i32) -> Vec<u8> {
+        for _ in 0..500 {
+            let message = rust_net_kcp_get_message(kcp_context);
+            if message.message_type == 0 {
+                std::thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            let received = message.message_type;
+            let data = match message.len {
+                0 => Vec::new(),
+                len => unsafe { std::slice::from_raw_parts(message.data, len).to_vec() },
+            };
+            rust_net_kcp_free_message(message);
+            if received == message_type {
+                return data;
+            }
+        }
+        panic!("kcp message {} not received", message_type);
+    }
+
+    #[test]
+    fn loopback() {
+        unsafe {
+            let context = &mut *rust_net_tokio_new(2);
+            let (port_a, port_b) = (free_port(), free_port());
+            let a = connect(context, port_a, port_b);
+            let b = connect(context, port_b, port_a);
+            wait_message(a, 1);
+            wait_message(b, 1);
+
+            // 多个分片的消息在对端还原为一条完整消息
+            let data = (0..100 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+            assert!(rust_net_kcp_send(a, data.as_ptr(), data.len()));
+            assert_eq!(wait_message(b, 7), data);
+            assert!(rust_net_kcp_send(b, b"pong".as_ptr(), 4));
+            assert_eq!(wait_message(a, 7), b"pong");
+
+            let oversized = vec![0u8; a.max_message_size + 1];
+            assert!(!rust_net_kcp_send(a, oversized.as_ptr(), oversized.len()));
+
+            rust_net_kcp_close(a);
+            wait_message(a, 3);
+            let mut sent = true;
+            for _ in 0..100 {
+                sent = rust_net_kcp_send(a, b"ping".as_ptr(), 4);
+                if !sent {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            assert!(!sent);
+
+            rust_net_kcp_free(a);
+            rust_net_kcp_free(b);
+            rust_net_tokio_free(context);
+        }
+    }
+
+    #[test]
+    fn invalid_config() {
+        unsafe {
+            let context = &mut *rust_net_tokio_new(1);
+            let addr = CString::new("127.0.0.1:4000").unwrap();
+            for config in [r#"{"mtu":10}"#, r#"{"mtu":70000}"#, "{"] {
+                let config = CString::new(config).unwrap();
+                assert!(rust_net_kcp_connect(context, addr.as_ptr(), 1, config.as_ptr()).is_null());
+            }
+            rust_net_tokio_free(context);
+        }
+    }
+}
@@ -0,0 +1,897 @@
+//! KCP协议, 移植自 ikcp.c (https://github.com/skywind3000/kcp)
+//! 与原实现的区别: 输出不通过回调, 而是暂存在 output 中由调用方取出发送; 只支持消息模式
+
+use std::collections::VecDeque;
+
+const RTO_NDL: u32 = 30;
+const RTO_MIN: u32 = 100;
+const RTO_DEF: u32 = 200;
+const RTO_MAX: u32 = 60000;
+const CMD_PUSH: u8 = 81;
+const CMD_ACK: u8 = 82;
+const CMD_WASK: u8 = 83;
+const CMD_WINS: u8 = 84;
+const ASK_SEND: u32 = 1;
+const ASK_TELL: u32 = 2;
+const WND_SND: u32 = 32;
+const WND_RCV: u32 = 128;
+const MTU_DEF: u32 = 1400;
+const INTERVAL: u32 = 100;
+const OVERHEAD: u32 = 24;
+const DEADLINK: u32 = 20;
+const THRESH_INIT: u32 = 2;
+const THRESH_MIN: u32 = 2;
+const PROBE_INIT: u32 = 7000;
+const PROBE_LIMIT: u32 = 120000;
+const FASTACK_LIMIT: u32 = 5;
+
+#[derive(Debug)]
+pub enum KcpError {
+    // 数据过短或长度字段与实际不符
+    InvalidPacket,
+    // 会话ID不一致
+    ConvMismatch(u32),
+    UnknownCommand(u8),
+    // 消息分片数超过接收窗口
+    MessageTooLarge(usize),
+    InvalidMtu(u32),
+}
+
+impl std::fmt::Display for KcpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KcpError::InvalidPacket => write!(f, "invalid kcp packet"),
+            KcpError::ConvMismatch(conv) => write!(f, "kcp conv mismatch: {}", conv),
+            KcpError::UnknownCommand(cmd) => write!(f, "unknown kcp command: {}", cmd),
+            KcpError::MessageTooLarge(len) => write!(f, "kcp message too large: {}", len),
+            KcpError::InvalidMtu(mtu) => write!(f, "invalid kcp mtu: {}", mtu),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Segment {
+    conv: u32,
+    cmd: u8,
+    frg: u8,
+    wnd: u16,
+    ts: u32,
+    sn: u32,
+    una: u32,
+    resendts: u32,
+    rto: u32,
+    fastack: u32,
+    xmit: u32,
+    data: Vec<u8>,
+}
+
+impl Segment {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.conv.to_le_bytes());
+        buffer.push(self.cmd);
+        buffer.push(self.frg);
+        buffer.extend_from_slice(&self.wnd.to_le_bytes());
+        buffer.extend_from_slice(&self.ts.to_le_bytes());
+        buffer.extend_from_slice(&self.sn.to_le_bytes());
+        buffer.extend_from_slice(&self.una.to_le_bytes());
+        buffer.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        buffer.extend_from_slice(&self.data);
+    }
+}
+
+/// 带回绕的时间差
+fn timediff(later: u32, earlier: u32) -> i32 {
+    later.wrapping_sub(earlier) as i32
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+pub struct Kcp {
+    conv: u32,
+    mtu: u32,
+    mss: u32,
+    dead: bool,
+    snd_una: u32,
+    snd_nxt: u32,
+    rcv_nxt: u32,
+    ssthresh: u32,
+    rx_rttval: u32,
+    rx_srtt: u32,
+    rx_rto: u32,
+    rx_minrto: u32,
+    snd_wnd: u32,
+    rcv_wnd: u32,
+    rmt_wnd: u32,
+    cwnd: u32,
+    probe: u32,
+    current: u32,
+    interval: u32,
+    ts_flush: u32,
+    xmit: u32,
+    nodelay: u32,
+    updated: bool,
+    ts_probe: u32,
+    probe_wait: u32,
+    dead_link: u32,
+    incr: u32,
+    snd_queue: VecDeque<Segment>,
+    rcv_queue: VecDeque<Segment>,
+    snd_buf: VecDeque<Segment>,
+    rcv_buf: VecDeque<Segment>,
+    acklist: Vec<(u32, u32)>,
+    fastresend: u32,
+    fastlimit: u32,
+    nocwnd: bool,
+    // 待发送的UDP数据报
+    output: Vec<Vec<u8>>,
+}
+
+impl Kcp {
+    pub fn new(conv: u32) -> Kcp {
+        Kcp {
+            conv,
+            mtu: MTU_DEF,
+            mss: MTU_DEF - OVERHEAD,
+            dead: false,
+            snd_una: 0,
+            snd_nxt: 0,
+            rcv_nxt: 0,
+            ssthresh: THRESH_INIT,
+            rx_rttval: 0,
+            rx_srtt: 0,
+            rx_rto: RTO_DEF,
+            rx_minrto: RTO_MIN,
+            snd_wnd: WND_SND,
+            rcv_wnd: WND_RCV,
+            rmt_wnd: WND_RCV,
+            cwnd: 0,
+            probe: 0,
+            current: 0,
+            interval: INTERVAL,
+            ts_flush: INTERVAL,
+            xmit: 0,
+            nodelay: 0,
+            updated: false,
+            ts_probe: 0,
+            probe_wait: 0,
+            dead_link: DEADLINK,
+            incr: 0,
+            snd_queue: VecDeque::new(),
+            rcv_queue: VecDeque::new(),
+            snd_buf: VecDeque::new(),
+            rcv_buf: VecDeque::new(),
+            acklist: Vec::new(),
+            fastresend: 0,
+            fastlimit: FASTACK_LIMIT,
+            nocwnd: false,
+            output: Vec::new(),
+        }
+    }
+
+    /// 取出一条完整消息
+    pub fn recv(&mut self) -> Option<Vec<u8>> {
+        let size = self.peek_size()?;
+        let recover = self.rcv_queue.len() as u32 >= self.rcv_wnd;
+
+        // 合并分片
+        let mut data = Vec::with_capacity(size);
+        while let Some(seg) = self.rcv_queue.pop_front() {
+            data.extend_from_slice(&seg.data);
+            if seg.frg == 0 {
+                break;
+            }
+        }
+
+        self.move_rcv_buf();
+
+        // 接收窗口从满变为未满, 主动告知对端
+        if (self.rcv_queue.len() as u32) < self.rcv_wnd && recover {
+            self.probe |= ASK_TELL;
+        }
+        Some(data)
+    }
+
+    fn peek_size(&self) -> Option<usize> {
+        let seg = self.rcv_queue.front()?;
+        if seg.frg == 0 {
+            return Some(seg.data.len());
+        }
+        if self.rcv_queue.len() < seg.frg as usize + 1 {
+            return None;
+        }
+        let mut size = 0;
+        for seg in &self.rcv_queue {
+            size += seg.data.len();
+            if seg.frg == 0 {
+                break;
+            }
+        }
+        Some(size)
+    }
+
+    /// 单条消息的最大长度, 分片数必须小于对端的最小接收窗口
+    pub fn max_message_size(&self) -> usize {
+        (WND_RCV - 1) as usize * self.mss as usize
+    }
+
+    /// 发送一条消息, 超过mss时分片
+    pub fn send(&mut self, data: &[u8]) -> Result<(), KcpError> {
+        if data.len() > self.max_message_size() {
+            return Err(KcpError::MessageTooLarge(data.len()));
+        }
+        let mss = self.mss as usize;
+        let count = data.len().div_ceil(mss).max(1);
+        let mut chunks = data.chunks(mss);
+        for i in 0..count {
+            self.snd_queue.push_back(Segment {
+                frg: (count - i - 1) as u8,
+                data: chunks.next().unwrap_or_default().to_vec(),
+                ..Default::default()
+            });
+        }
+        Ok(())
+    }
+
+    fn update_ack(&mut self, rtt: i32) {
+        let rtt = rtt as u32;
+        if self.rx_srtt == 0 {
+            self.rx_srtt = rtt;
+            self.rx_rttval = rtt / 2;
+        } else {
+            let delta = rtt.abs_diff(self.rx_srtt);
+            self.rx_rttval = (3 * self.rx_rttval + delta) / 4;
+            self.rx_srtt = ((7 * self.rx_srtt + rtt) / 8).max(1);
+        }
+        let rto = self.rx_srtt + self.interval.max(4 * self.rx_rttval);
+        self.rx_rto = rto.clamp(self.rx_minrto, RTO_MAX);
+    }
+
+    fn shrink_buf(&mut self) {
+        self.snd_una = match self.snd_buf.front() {
+            Some(seg) => seg.sn,
+            None => self.snd_nxt,
+        };
+    }
+
+    fn parse_ack(&mut self, sn: u32) {
+        if timediff(sn, self.snd_una) < 0 || timediff(sn, self.snd_nxt) >= 0 {
+            return;
+        }
+        for i in 0..self.snd_buf.len() {
+            let seg_sn = self.snd_buf[i].sn;
+            if sn == seg_sn {
+                self.snd_buf.remove(i);
+                break;
+            }
+            if timediff(sn, seg_sn) < 0 {
+                break;
+            }
+        }
+    }
+
+    fn parse_una(&mut self, una: u32) {
+        while let Some(seg) = self.snd_buf.front() {
+            if timediff(una, seg.sn) > 0 {
+                self.snd_buf.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn parse_fastack(&mut self, sn: u32) {
+        if timediff(sn, self.snd_una) < 0 || timediff(sn, self.snd_nxt) >= 0 {
+            return;
+        }
+        for seg in self.snd_buf.iter_mut() {
+            if timediff(sn, seg.sn) < 0 {
+                break;
+            } else if sn != seg.sn {
+                seg.fastack += 1;
+            }
+        }
+    }
+
+    fn parse_data(&mut self, seg: Segment) {
+        let sn = seg.sn;
+        if timediff(sn, self.rcv_nxt.wrapping_add(self.rcv_wnd)) >= 0
+            || timediff(sn, self.rcv_nxt) < 0
+        {
+            return;
+        }
+
+        let mut repeat = false;
+        let mut index = 0;
+        for (i, buffered) in self.rcv_buf.iter().enumerate().rev() {
+            if buffered.sn == sn {
+                repeat = true;
+                break;
+            }
+            if timediff(sn, buffered.sn) > 0 {
+                index = i + 1;
+                break;
+            }
+        }
+        if !repeat {
+            self.rcv_buf.insert(index, seg);
+        }
+
+        self.move_rcv_buf();
+    }
+
+    /// 将连续的数据从 rcv_buf 移到 rcv_queue
+    fn move_rcv_buf(&mut self) {
+        while let Some(seg) = self.rcv_buf.front() {
+            if seg.sn == self.rcv_nxt && (self.rcv_queue.len() as u32) < self.rcv_wnd {
+                let seg = self.rcv_buf.pop_front().unwrap();
+                self.rcv_queue.push_back(seg);
+                self.rcv_nxt = self.rcv_nxt.wrapping_add(1);
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// 输入收到的UDP数据报
+    pub fn input(&mut self, mut data: &[u8]) -> Result<(), KcpError> {
+        if data.len() < OVERHEAD as usize {
+            return Err(KcpError::InvalidPacket);
+        }
+        let prev_una = self.snd_una;
+        let mut maxack = None;
+
+        while data.len() >= OVERHEAD as usize {
+            let conv = read_u32(data, 0);
+            if conv != self.conv {
+                return Err(KcpError::ConvMismatch(conv));
+            }
+            let cmd = data[4];
+            let frg = data[5];
+            let wnd = u16::from_le_bytes([data[6], data[7]]);
+            let ts = read_u32(data, 8);
+            let sn = read_u32(data, 12);
+            let una = read_u32(data, 16);
+            let len = read_u32(data, 20) as usize;
+            data = &data[OVERHEAD as usize..];
+            if data.len() < len {
+                return Err(KcpError::InvalidPacket);
+            }
+            if !matches!(cmd, CMD_PUSH | CMD_ACK | CMD_WASK | CMD_WINS) {
+                return Err(KcpError::UnknownCommand(cmd));
+            }
+
+            self.rmt_wnd = wnd as u32;
+            self.parse_una(una);
+            self.shrink_buf();
+
+            match cmd {
+                CMD_ACK => {
+                    let rtt = timediff(self.current, ts);
+                    if rtt >= 0 {
+                        self.update_ack(rtt);
+                    }
+                    self.parse_ack(sn);
+                    self.shrink_buf();
+                    match maxack {
+                        Some(max) if timediff(sn, max) <= 0 => {}
+                        _ => maxack = Some(sn),
+                    }
+                }
+                // 超出接收窗口的数据直接丢弃, 不确认
+                CMD_PUSH if timediff(sn, self.rcv_nxt.wrapping_add(self.rcv_wnd)) < 0 => {
+                    self.acklist.push((sn, ts));
+                    if timediff(sn, self.rcv_nxt) >= 0 {
+                        self.parse_data(Segment {
+                            conv,
+                            cmd,
+                            frg,
+                            wnd,
+                            ts,
+                            sn,
+                            una,
+                            data: data[..len].to_vec(),
+                            ..Default::default()
+                        });
+                    }
+                }
+                CMD_WASK => {
+                    // 对端询问窗口大小
+                    self.probe |= ASK_TELL;
+                }
+                _ => {}
+            }
+            data = &data[len..];
+        }
+
+        if let Some(maxack) = maxack {
+            self.parse_fastack(maxack);
+        }
+
+        // 收到新的确认, 拥塞窗口增长
+        if timediff(self.snd_una, prev_una) > 0 && self.cwnd < self.rmt_wnd {
+            let mss = self.mss;
+            if self.cwnd < self.ssthresh {
+                self.cwnd += 1;
+                self.incr += mss;
+            } else {
+                if self.incr < mss {
+                    self.incr = mss;
+                }
+                self.incr += (mss * mss) / self.incr + (mss / 16);
+                if (self.cwnd + 1) * mss <= self.incr {
+                    self.cwnd = (self.incr + mss - 1) / mss.max(1);
+                }
+            }
+            if self.cwnd > self.rmt_wnd {
+                self.cwnd = self.rmt_wnd;
+                self.incr = self.rmt_wnd * mss;
+            }
+        }
+        Ok(())
+    }
+
+    fn wnd_unused(&self) -> u16 {
+        self.rcv_wnd.saturating_sub(self.rcv_queue.len() as u32) as u16
+    }
+
+    fn write(&mut self, buffer: &mut Vec<u8>, seg: &Segment) {
+        if !buffer.is_empty()
+            && buffer.len() + (OVERHEAD as usize) + seg.data.len() > self.mtu as usize
+        {
+            self.output.push(std::mem::take(buffer));
+        }
+        seg.encode(buffer);
+    }
+
+    /// 发送确认、窗口探测及数据
+    pub fn flush(&mut self) {
+        if !self.updated {
+            return;
+        }
+        let current = self.current;
+        let mut buffer = Vec::with_capacity(self.mtu as usize);
+        let mut seg = Segment {
+            conv: self.conv,
+            cmd: CMD_ACK,
+            wnd: self.wnd_unused(),
+            una: self.rcv_nxt,
+            ..Default::default()
+        };
+
+        for (sn, ts) in std::mem::take(&mut self.acklist) {
+            seg.sn = sn;
+            seg.ts = ts;
+            self.write(&mut buffer, &seg);
+        }
+
+        // 对端接收窗口为0时定期探测
+        if self.rmt_wnd == 0 {
+            if self.probe_wait == 0 {
+                self.probe_wait = PROBE_INIT;
+                self.ts_probe = current.wrapping_add(self.probe_wait);
+            } else if timediff(current, self.ts_probe) >= 0 {
+                self.probe_wait = self.probe_wait.max(PROBE_INIT);
+                self.probe_wait += self.probe_wait / 2;
+                self.probe_wait = self.probe_wait.min(PROBE_LIMIT);
+                self.ts_probe = current.wrapping_add(self.probe_wait);
+                self.probe |= ASK_SEND;
+            }
+        } else {
+            self.ts_probe = 0;
+            self.probe_wait = 0;
+        }
+
+        seg.sn = 0;
+        seg.ts = 0;
+        if self.probe & ASK_SEND != 0 {
+            seg.cmd = CMD_WASK;
+            self.write(&mut buffer, &seg);
+        }
+        if self.probe & ASK_TELL != 0 {
+            seg.cmd = CMD_WINS;
+            self.write(&mut buffer, &seg);
+        }
+        self.probe = 0;
+
+        let mut cwnd = self.snd_wnd.min(self.rmt_wnd);
+        if !self.nocwnd {
+            cwnd = cwnd.min(self.cwnd);
+        }
+
+        // 发送窗口内的新数据移入 snd_buf
+        while timediff(self.snd_nxt, self.snd_una.wrapping_add(cwnd)) < 0 {
+            let mut new_seg = match self.snd_queue.pop_front() {
+                Some(seg) => seg,
+                None => break,
+            };
+            new_seg.conv = self.conv;
+            new_seg.cmd = CMD_PUSH;
+            new_seg.wnd = seg.wnd;
+            new_seg.ts = current;
+            new_seg.sn = self.snd_nxt;
+            new_seg.una = self.rcv_nxt;
+            new_seg.resendts = current;
+            new_seg.rto = self.rx_rto;
+            new_seg.fastack = 0;
+            new_seg.xmit = 0;
+            self.snd_nxt = self.snd_nxt.wrapping_add(1);
+            self.snd_buf.push_back(new_seg);
+        }
+
+        let resent = if self.fastresend > 0 {
+            self.fastresend
+        } else {
+            u32::MAX
+        };
+        let rtomin = if self.nodelay == 0 {
+            self.rx_rto >> 3
+        } else {
+            0
+        };
+
+        let mut lost = false;
+        let mut change = false;
+        let mut snd_buf = std::mem::take(&mut self.snd_buf);
+        for seg in snd_buf.iter_mut() {
+            let mut needsend = false;
+            if seg.xmit == 0 {
+                needsend = true;
+                seg.xmit += 1;
+                seg.rto = self.rx_rto;
+                seg.resendts = current.wrapping_add(seg.rto + rtomin);
+            } else if timediff(current, seg.resendts) >= 0 {
+                // 超时重传
+                needsend = true;
+                seg.xmit += 1;
+                self.xmit += 1;
+                if self.nodelay == 0 {
+                    seg.rto += seg.rto.max(self.rx_rto);
+                } else {
+                    let step = if self.nodelay < 2 {
+                        seg.rto
+                    } else {
+                        self.rx_rto
+                    };
+                    seg.rto += step / 2;
+                }
+                seg.resendts = current.wrapping_add(seg.rto);
+                lost = true;
+            } else if seg.fastack >= resent && (seg.xmit <= self.fastlimit || self.fastlimit == 0) {
+                // 快速重传
+                needsend = true;
+                seg.xmit += 1;
+                seg.fastack = 0;
+                seg.resendts = current.wrapping_add(seg.rto);
+                change = true;
+            }
+
+            if needsend {
+                seg.ts = current;
+                seg.wnd = self.wnd_unused();
+                seg.una = self.rcv_nxt;
+                self.write(&mut buffer, seg);
+                if seg.xmit >= self.dead_link {
+                    self.dead = true;
+                }
+            }
+        }
+        self.snd_buf = snd_buf;
+
+        if !buffer.is_empty() {
+            self.output.push(buffer);
+        }
+
+        if change {
+            let inflight = self.snd_nxt.wrapping_sub(self.snd_una);
+            self.ssthresh = (inflight / 2).max(THRESH_MIN);
+            self.cwnd = self.ssthresh.saturating_add(resent);
+            self.incr = self.cwnd.saturating_mul(self.mss);
+        }
+        if lost {
+            self.ssthresh = (cwnd / 2).max(THRESH_MIN);
+            self.cwnd = 1;
+            self.incr = self.mss;
+        }
+        if self.cwnd < 1 {
+            self.cwnd = 1;
+            self.incr = self.mss;
+        }
+    }
+
+    /// 按时钟驱动, current 为毫秒时间戳
+    pub fn update(&mut self, current: u32) {
+        self.current = current;
+        if !self.updated {
+            self.updated = true;
+            self.ts_flush = current;
+        }
+        let mut slap = timediff(current, self.ts_flush);
+        if !(-10000..10000).contains(&slap) {
+            self.ts_flush = current;
+            slap = 0;
+        }
+        if slap >= 0 {
+            self.ts_flush = self.ts_flush.wrapping_add(self.interval);
+            if timediff(current, self.ts_flush) >= 0 {
+                self.ts_flush = current.wrapping_add(self.interval);
+            }
+            self.flush();
+        }
+    }
+
+    /// 下一次需要调用 update 的时间
+    pub fn check(&self, current: u32) -> u32 {
+        if !self.updated {
+            return current;
+        }
+        let mut ts_flush = self.ts_flush;
+        if !(-10000..10000).contains(&timediff(current, ts_flush)) {
+            ts_flush = current;
+        }
+        if timediff(current, ts_flush) >= 0 {
+            return current;
+        }
+        let tm_flush = timediff(ts_flush, current);
+        let mut tm_packet = i32::MAX;
+        for seg in &self.snd_buf {
+            let diff = timediff(seg.resendts, current);
+            if diff <= 0 {
+                return current;
+            }
+            tm_packet = tm_packet.min(diff);
+        }
+        let minimal = tm_packet.min(tm_flush).min(self.interval as i32);
+        current.wrapping_add(minimal as u32)
+    }
+
+    /// 取出待发送的UDP数据报
+    pub fn take_output(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.output)
+    }
+
+    pub fn set_mtu(&mut self, mtu: u32) -> Result<(), KcpError> {
+        if mtu < 50 {
+            return Err(KcpError::InvalidMtu(mtu));
+        }
+        self.mtu = mtu;
+        self.mss = mtu - OVERHEAD;
+        Ok(())
+    }
+
+    /// nodelay: 0关闭 1开启 2更激进的超时重传
+    /// interval: 内部时钟间隔(毫秒)
+    /// resend: 收到多少次跨越确认后快速重传 0表示关闭
+    /// nocwnd: 关闭拥塞控制
+    pub fn set_nodelay(&mut self, nodelay: u32, interval: u32, resend: u32, nocwnd: bool) {
+        self.nodelay = nodelay;
+        self.rx_minrto = if nodelay > 0 { RTO_NDL } else { RTO_MIN };
+        self.interval = interval.clamp(10, 5000);
+        self.fastresend = resend;
+        self.nocwnd = nocwnd;
+    }
+
+    pub fn set_wndsize(&mut self, snd_wnd: u32, rcv_wnd: u32) {
+        if snd_wnd > 0 {
+            self.snd_wnd = snd_wnd;
+        }
+        if rcv_wnd > 0 {
+            // 接收窗口不能小于最大分片数
+            self.rcv_wnd = rcv_wnd.max(WND_RCV);
+        }
+    }
+
+    pub fn set_dead_link(&mut self, dead_link: u32) {
+        if dead_link > 0 {
+            self.dead_link = dead_link;
+        }
+    }
+
+    /// 某个分片重传次数达到上限, 认为连接已断开
+    pub fn is_dead_link(&self) -> bool {
+        self.dead
+    }
+
+    /// 等待发送及等待确认的分片数
+    pub fn wait_snd(&self) -> usize {
+        self.snd_buf.len() + self.snd_queue.len()
+    }
+
+    pub fn srtt(&self) -> u32 {
+        self.rx_srtt
+    }
+
+    pub fn rttvar(&self) -> u32 {
+        self.rx_rttval
+    }
+
+    pub fn rto(&self) -> u32 {
+        self.rx_rto
+    }
+
+    /// 累计超时重传次数
+    pub fn retransmits(&self) -> u32 {
+        self.xmit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fast(conv: u32) -> Kcp {
+        let mut kcp = Kcp::new(conv);
+        kcp.set_nodelay(1, 10, 2, true);
+        kcp
+    }
+
+    /// 以10毫秒为步长驱动两端 duration 毫秒, lose 返回true时丢弃a发往b的数据报
+    fn run(
+        a: &mut Kcp,
+        b: &mut Kcp,
+        current: &mut u32,
+        duration: u32,
+        mut lose: impl FnMut() -> bool,
+    ) -> Vec<Vec<u8>> {
+        let mut received = Vec::new();
+        let end = *current + duration;
+        while *current < end {
+            a.update(*current);
+            b.update(*current);
+            for datagram in a.take_output() {
+                if !lose() {
+                    b.input(&datagram).unwrap();
+                }
+            }
+            for datagram in b.take_output() {
+                a.input(&datagram).unwrap();
+            }
+            while let Some(data) = b.recv() {
+                received.push(data);
+            }
+            *current += 10;
+        }
+        received
+    }
+
+    #[test]
+    fn fragmented_message() {
+        let mut a = fast(1);
+        let mut b = fast(1);
+        a.set_mtu(100).unwrap();
+        b.set_mtu(100).unwrap();
+
+        let message = (0..5000).map(|i| i as u8).collect::<Vec<_>>();
+        a.send(&message).unwrap();
+        a.send(b"").unwrap();
+        assert_eq!(a.snd_queue.len(), 5000usize.div_ceil(76) + 1);
+
+        let received = run(&mut a, &mut b, &mut 0, 1000, || false);
+        assert_eq!(received, vec![message, Vec::new()]);
+        assert_eq!(a.wait_snd(), 0);
+    }
+
+    #[test]
+    fn message_too_large() {
+        let mut kcp = Kcp::new(1);
+        let max = kcp.max_message_size();
+        assert!(kcp.send(&vec![0; max]).is_ok());
+        assert!(matches!(
+            kcp.send(&vec![0; max + 1]),
+            Err(KcpError::MessageTooLarge(_))
+        ));
+        assert!(matches!(kcp.set_mtu(49), Err(KcpError::InvalidMtu(49))));
+    }
+
+    #[test]
+    fn retransmit_lost() {
+        let mut a = fast(1);
+        let mut b = fast(1);
+        let messages = (0..50u32)
+            .map(|i| i.to_le_bytes().repeat(100))
+            .collect::<Vec<_>>();
+        for message in &messages {
+            a.send(message).unwrap();
+        }
+
+        // 丢弃三分之一的数据报
+        let mut count = 0;
+        let received = run(&mut a, &mut b, &mut 0, 5000, || {
+            count += 1;
+            count % 3 == 0
+        });
+        assert_eq!(received, messages);
+        assert!(a.retransmits() > 0);
+        assert_eq!(a.wait_snd(), 0);
+        assert!(!a.is_dead_link());
+    }
+
+    #[test]
+    fn send_window() {
+        let mut a = fast(1);
+        let mut b = fast(1);
+        a.set_wndsize(4, 128);
+        for i in 0..20u8 {
+            a.send(&[i]).unwrap();
+        }
+
+        // 对端未确认时最多发出 send_window 个分片
+        a.update(0);
+        assert_eq!(a.snd_buf.len(), 4);
+        assert_eq!(a.snd_queue.len(), 16);
+        assert_eq!(a.wait_snd(), 20);
+
+        let mut current = 0;
+        let mut received = Vec::new();
+        for output in a.take_output() {
+            b.input(&output).unwrap();
+        }
+        while let Some(data) = b.recv() {
+            received.push(data);
+        }
+        assert_eq!(received.len(), 4);
+
+        received.extend(run(&mut a, &mut b, &mut current, 1000, || false));
+        assert_eq!(received, (0..20u8).map(|i| vec![i]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn recv_window() {
+        let mut a = fast(1);
+        let mut b = fast(1);
+        for i in 0..200u8 {
+            a.send(&[i]).unwrap();
+        }
+
+        // b 不取出消息时, a 发出的数据不超过 b 的接收窗口
+        let mut current = 0;
+        while current < 2000 {
+            a.update(current);
+            b.update(current);
+            for datagram in a.take_output() {
+                b.input(&datagram).unwrap();
+            }
+            for datagram in b.take_output() {
+                a.input(&datagram).unwrap();
+            }
+            current += 10;
+        }
+        assert_eq!(b.rcv_queue.len(), WND_RCV as usize);
+        assert_eq!(a.rmt_wnd, 0);
+        assert_eq!(a.wait_snd(), 200 - WND_RCV as usize);
+
+        let mut received = Vec::new();
+        while let Some(data) = b.recv() {
+            received.push(data);
+        }
+        received.extend(run(&mut a, &mut b, &mut current, 2000, || false));
+        assert_eq!(received, (0..200u8).map(|i| vec![i]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn dead_link() {
+        let mut a = fast(1);
+        let mut b = fast(1);
+        a.set_dead_link(5);
+        a.send(b"hello").unwrap();
+
+        // 对端收不到任何数据
+        let mut current = 0;
+        let mut sent = 0;
+        while !a.is_dead_link() && current < 60000 {
+            a.update(current);
+            sent += a.take_output().len();
+            current += 10;
+        }
+        assert!(a.is_dead_link());
+        assert_eq!(sent, 5);
+        assert_eq!(a.retransmits(), 4);
+        assert!(b.recv().is_none());
+    }
+}
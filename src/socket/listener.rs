@@ -0,0 +1,166 @@
+use super::framing::FramingConfig;
+use super::{run, set_options, TcpContext};
+use crate::TokioContext;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString};
+use std::net::SocketAddr;
+use std::os::raw::c_char;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio_util::codec::LengthDelimitedCodec;
+
+/// TCP监听
+pub struct TcpListenerContext {
+    local_addr: SocketAddr,
+    // 已接受但还未被 rust_net_tcp_listener_accept 取走的连接
+    pending: Arc<Mutex<VecDeque<TcpContext>>>,
+    accept_task: JoinHandle<()>,
+}
+
+/// 监听配置, 以json形式传入 rust_net_tcp_listen
+/// 应用于所有接受的连接, 含义同 rust_net_tcp_connect 的配置
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct TcpListenConfig {
+    nodelay: bool,
+    keepalive_ms: u64,
+    framing: Option<FramingConfig>,
+}
+
+/// 监听 addr, 例如 "0.0.0.0:9000", 端口为0时由系统分配, config 可以为空
+/// {
+///     "nodelay": true,
+///     "keepalive_ms": 30000,
+///     "framing": {"header_size": 4, "byte_order": "big"}
+/// }
+/// 绑定或配置失败时返回空指针
+///
+/// # Safety
+/// addr 必须是以\0结尾的UTF-8字符串
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_tcp_listen(
+    context: &mut TokioContext,
+    addr: *const c_char,
+    config: *const c_char,
+) -> *mut TcpListenerContext {
+    let addr = CStr::from_ptr(addr).to_str().unwrap();
+    let config = if config.is_null() {
+        TcpListenConfig::default()
+    } else {
+        let config = CStr::from_ptr(config).to_str().unwrap();
+        match serde_json::from_str::<TcpListenConfig>(config) {
+            Ok(config) => config,
+            Err(err) => {
+                log::error!("tcp listen config error: {}", err);
+                return std::ptr::null_mut();
+            }
+        }
+    };
+    let codec = match config.framing.as_ref().map(FramingConfig::codec) {
+        Some(Ok(codec)) => Some(codec),
+        Some(Err(err)) => {
+            log::error!("tcp listen config error: {}", err);
+            return std::ptr::null_mut();
+        }
+        None => None,
+    };
+
+    let _guard = context.runtime.enter();
+    let listener = std::net::TcpListener::bind(addr)
+        .and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            TcpListener::from_std(listener)
+        })
+        .and_then(|listener| Ok((listener.local_addr()?, listener)));
+    let (local_addr, listener) = match listener {
+        Ok(listener) => listener,
+        Err(err) => {
+            log::error!("tcp listen {} failed: {}", addr, err);
+            return std::ptr::null_mut();
+        }
+    };
+
+    let pending: Arc<Mutex<VecDeque<TcpContext>>> = Default::default();
+    let accept_task = context
+        .runtime
+        .spawn(accept_loop(listener, config, codec, pending.clone()));
+    log::info!("tcp listening on {}", local_addr);
+
+    Box::into_raw(Box::new(TcpListenerContext {
+        local_addr,
+        pending,
+        accept_task,
+    }))
+}
+
+/// 取出一个新接受的连接, 没有时返回空指针
+/// 返回的连接与 rust_net_tcp_connect 的返回值用法一致, 第一个事件为连接成功, data为客户端地址
+/// 使用完成之后 调用 rust_net_tcp_free 释放
+#[no_mangle]
+pub extern "C" fn rust_net_tcp_listener_accept(
+    listener: &mut TcpListenerContext,
+) -> *mut TcpContext {
+    match listener.pending.lock().unwrap().pop_front() {
+        Some(tcp_context) => Box::into_raw(Box::new(tcp_context)),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// 实际监听的地址
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_tcp_listener_local_addr(
+    listener: &mut TcpListenerContext,
+) -> *mut c_char {
+    match CString::new(listener.local_addr.to_string()) {
+        Ok(cstr) => cstr.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// 停止监听, 未取走的连接会被断开, 已取走的连接不受影响
+///
+/// # Safety
+/// listener 必须是 rust_net_tcp_listen 返回的指针, 释放后不能再使用
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_tcp_listener_free(listener: *mut TcpListenerContext) {
+    let listener = Box::from_raw(listener);
+    listener.accept_task.abort();
+    drop(listener)
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    config: TcpListenConfig,
+    codec: Option<LengthDelimitedCodec>,
+    pending: Arc<Mutex<VecDeque<TcpContext>>>,
+) {
+    loop {
+        let (stream, remote_addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                log::warn!("tcp accept failed: {}", err);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        if let Err(err) = set_options(&stream, config.nodelay, config.keepalive_ms) {
+            log::warn!("tcp set options for {} failed: {}", remote_addr, err);
+        }
+        log::info!("tcp accepted {}", remote_addr);
+
+        let tcp_context = TcpContext::new();
+        tokio::spawn(run(
+            stream,
+            remote_addr,
+            codec.clone(),
+            tcp_context.tx.clone(),
+            tcp_context.msg_queue.clone(),
+        ));
+        pending.lock().unwrap().push_back(tcp_context);
+    }
+}
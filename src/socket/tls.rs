@@ -0,0 +1,46 @@
+use crate::http::tls::client_config;
+use anyhow::{anyhow, Result};
+use rustls::ServerName;
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+/// TLS配置, 作为 rust_net_tcp_connect 配置中的 tls 字段
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct TlsConfig {
+    // SNI及证书校验使用的域名, 为空时使用连接的host
+    pub server_name: String,
+    // PEM格式的自定义根证书
+    pub root_certificates: Vec<String>,
+    // ALPN协议列表
+    pub alpn_protocols: Vec<String>,
+}
+
+/// 在已建立的TCP连接上进行TLS握手
+pub async fn connect(
+    stream: TcpStream,
+    host: &str,
+    config: &TlsConfig,
+) -> Result<TlsStream<TcpStream>> {
+    let alpn_protocols = config
+        .alpn_protocols
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    let client_config =
+        client_config(&config.root_certificates, &alpn_protocols).map_err(|err| anyhow!(err))?;
+    let server_name = if config.server_name.is_empty() {
+        host
+    } else {
+        &config.server_name
+    };
+    let server_name = ServerName::try_from(server_name)
+        .map_err(|_| anyhow!("invalid tls server name: {}", server_name))?;
+    let stream = TlsConnector::from(Arc::new(client_config))
+        .connect(server_name, stream)
+        .await?;
+    Ok(stream)
+}
@@ -0,0 +1,375 @@
+use crate::TokioContext;
+use serde::Deserialize;
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::os::raw::c_char;
+use std::sync::{Arc, Mutex};
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+
+// UDP数据报的最大长度
+const MAX_DATAGRAM_SIZE: usize = 64 * 1024;
+
+enum UdpMessage {
+    // 接收出错, socket已停止接收
+    Error(String),
+    // 收到数据报, 附带来源地址
+    Recv(Vec<u8>, SocketAddr),
+}
+
+/// UDP socket
+pub struct UdpContext {
+    // 发送直接写入非阻塞socket, 不经过tokio的就绪状态
+    socket: Arc<UdpSocket>,
+    msg_queue: Arc<Mutex<VecDeque<UdpMessage>>>,
+    recv_task: JoinHandle<()>,
+}
+
+/// UDP配置, 以json形式传入 rust_net_udp_bind
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct UdpConfig {
+    // 是否允许发送广播
+    broadcast: bool,
+    // 是否允许多个socket绑定同一地址, 多个进程接收同一组播时需要开启
+    reuse_address: bool,
+    // 接收缓冲区大小(字节) 0表示系统默认
+    recv_buffer_size: usize,
+    // 发送缓冲区大小(字节) 0表示系统默认
+    send_buffer_size: usize,
+    // 加入的组播地址
+    multicast_groups: Vec<IpAddr>,
+    // 加入IPv4组播使用的本地网卡地址, 为空时由系统选择
+    multicast_interface: Option<Ipv4Addr>,
+    // 加入IPv6组播使用的本地网卡索引, 0表示由系统选择
+    multicast_interface_index: u32,
+    // 是否接收本机发出的组播
+    multicast_loop: Option<bool>,
+    // 组播的TTL, IPv6 socket设置为hop limit
+    multicast_ttl: Option<u32>,
+}
+
+/// 绑定本地地址, addr 例如 "0.0.0.0:9000", 端口为0时由系统分配, config 可以为空
+/// {
+///     "broadcast": false,
+///     "reuse_address": false,
+///     "recv_buffer_size": 0,
+///     "send_buffer_size": 0,
+///     "multicast_groups": ["239.255.0.1"],
+///     "multicast_interface": "192.168.1.10",
+///     "multicast_interface_index": 0,
+///     "multicast_loop": true,
+///     "multicast_ttl": 1
+/// }
+/// 绑定或配置失败时返回空指针
+///
+/// # Safety
+/// addr 必须是以\0结尾的UTF-8字符串
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_udp_bind(
+    context: &mut TokioContext,
+    addr: *const c_char,
+    config: *const c_char,
+) -> *mut UdpContext {
+    let addr = CStr::from_ptr(addr).to_str().unwrap();
+    let config = if config.is_null() {
+        UdpConfig::default()
+    } else {
+        let config = CStr::from_ptr(config).to_str().unwrap();
+        match serde_json::from_str::<UdpConfig>(config) {
+            Ok(config) => config,
+            Err(err) => {
+                log::error!("udp config error: {}", err);
+                return std::ptr::null_mut();
+            }
+        }
+    };
+
+    let _guard = context.runtime.enter();
+    let socket = match bind(addr, &config) {
+        Ok(socket) => Arc::new(socket),
+        Err(err) => {
+            log::error!("udp bind {} failed: {}", addr, err);
+            return std::ptr::null_mut();
+        }
+    };
+
+    let msg_queue: Arc<Mutex<VecDeque<UdpMessage>>> = Default::default();
+    let recv_task = context
+        .runtime
+        .spawn(poll_recv(socket.clone(), msg_queue.clone()));
+
+    Box::into_raw(Box::new(UdpContext {
+        socket,
+        msg_queue,
+        recv_task,
+    }))
+}
+
+/// 设置默认的目标地址, 之后可以使用 rust_net_udp_send 发送, 并且只接收该地址的数据报
+///
+/// # Safety
+/// addr 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_udp_connect(
+    udp_context: &mut UdpContext,
+    addr: *const c_char,
+) -> bool {
+    let addr = match parse_addr(addr) {
+        Some(addr) => addr,
+        None => return false,
+    };
+    match SockRef::from(&*udp_context.socket).connect(&addr.into()) {
+        Ok(_) => true,
+        Err(err) => {
+            log::warn!("udp connect {} failed: {}", addr, err);
+            false
+        }
+    }
+}
+
+/// 发送到 rust_net_udp_connect 设置的地址
+/// 发送缓冲区已满或出错时返回false
+///
+/// # Safety
+/// data 可以为空, 不为空时必须指向至少 length 字节的可读内存
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_udp_send(
+    udp_context: &mut UdpContext,
+    data: *const u8,
+    length: usize,
+) -> bool {
+    if data.is_null() {
+        return false;
+    }
+    let data = std::slice::from_raw_parts(data, length);
+    match SockRef::from(&*udp_context.socket).send(data) {
+        Ok(_) => true,
+        Err(err) => {
+            log::debug!("udp send failed: {}", err);
+            false
+        }
+    }
+}
+
+/// 发送到指定地址, addr 例如 "192.168.1.255:9000"
+/// 发送缓冲区已满或出错时返回false
+///
+/// # Safety
+/// addr 必须是以\0结尾的UTF-8字符串
+/// data 可以为空, 不为空时必须指向至少 length 字节的可读内存
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_udp_send_to(
+    udp_context: &mut UdpContext,
+    addr: *const c_char,
+    data: *const u8,
+    length: usize,
+) -> bool {
+    let addr = match parse_addr(addr) {
+        Some(addr) => addr,
+        None => return false,
+    };
+    if data.is_null() {
+        return false;
+    }
+    let data = std::slice::from_raw_parts(data, length);
+    match SockRef::from(&*udp_context.socket).send_to(data, &addr.into()) {
+        Ok(_) => true,
+        Err(err) => {
+            log::debug!("udp send to {} failed: {}", addr, err);
+            false
+        }
+    }
+}
+
+/// 实际绑定的地址
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_udp_local_addr(udp_context: &mut UdpContext) -> *mut c_char {
+    let addr = match udp_context.socket.local_addr() {
+        Ok(addr) => addr,
+        Err(_) => return std::ptr::null_mut(),
+    };
+    match CString::new(addr.to_string()) {
+        Ok(cstr) => cstr.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// 取出一个数据报, 没有数据报时 message_type 为0
+/// 使用完成之后 调用 rust_net_udp_free_message 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_udp_get_message(udp_context: &mut UdpContext) -> UdpMessageData {
+    match udp_context.msg_queue.lock().unwrap().pop_front() {
+        Some(message) => UdpMessageData::from(message),
+        None => UdpMessageData::default(),
+    }
+}
+
+/// # Safety
+/// udp_context 必须是 rust_net_udp_bind 返回的指针, 释放后不能再使用
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_udp_free(udp_context: *mut UdpContext) {
+    let udp_context = Box::from_raw(udp_context);
+    udp_context.recv_task.abort();
+    drop(udp_context)
+}
+
+#[no_mangle]
+pub extern "C" fn rust_net_udp_free_message(message: UdpMessageData) {
+    unsafe {
+        if !message.remote_addr.is_null() {
+            drop(CString::from_raw(message.remote_addr));
+        }
+        if !message.data.is_null() && message.cap > 0 {
+            let buffer = Vec::from_raw_parts(message.data as *mut u8, message.len, message.cap);
+            // Rust 会在这里清理内存
+            drop(buffer);
+        }
+    }
+}
+
+fn bind(addr: &str, config: &UdpConfig) -> std::io::Result<UdpSocket> {
+    let addr = addr
+        .parse::<SocketAddr>()
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(config.reuse_address)?;
+    socket.set_broadcast(config.broadcast)?;
+    if config.recv_buffer_size > 0 {
+        socket.set_recv_buffer_size(config.recv_buffer_size)?;
+    }
+    if config.send_buffer_size > 0 {
+        socket.set_send_buffer_size(config.send_buffer_size)?;
+    }
+    if let Some(ttl) = config.multicast_ttl {
+        match addr {
+            SocketAddr::V4(_) => socket.set_multicast_ttl_v4(ttl)?,
+            SocketAddr::V6(_) => socket.set_multicast_hops_v6(ttl)?,
+        }
+    }
+    socket.bind(&addr.into())?;
+    socket.set_nonblocking(true)?;
+    let socket = UdpSocket::from_std(socket.into())?;
+
+    for group in &config.multicast_groups {
+        match group {
+            IpAddr::V4(group) => {
+                let interface = config.multicast_interface.unwrap_or(Ipv4Addr::UNSPECIFIED);
+                socket.join_multicast_v4(*group, interface)?;
+            }
+            IpAddr::V6(group) => {
+                socket.join_multicast_v6(group, config.multicast_interface_index)?
+            }
+        }
+    }
+    if let Some(multicast_loop) = config.multicast_loop {
+        match addr {
+            SocketAddr::V4(_) => socket.set_multicast_loop_v4(multicast_loop)?,
+            SocketAddr::V6(_) => socket.set_multicast_loop_v6(multicast_loop)?,
+        }
+    }
+    Ok(socket)
+}
+
+async fn poll_recv(socket: Arc<UdpSocket>, msg_queue: Arc<Mutex<VecDeque<UdpMessage>>>) {
+    let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+    loop {
+        match socket.recv_from(&mut buffer).await {
+            Ok((len, addr)) => {
+                let data = buffer[..len].to_vec();
+                msg_queue
+                    .lock()
+                    .unwrap()
+                    .push_back(UdpMessage::Recv(data, addr));
+            }
+            // 收到ICMP端口不可达时会返回ConnectionRefused, Windows上为ConnectionReset, 不影响后续接收
+            Err(err) if is_port_unreachable(&err) => {
+                log::debug!("udp recv failed: {}", err);
+            }
+            Err(err) => {
+                log::warn!("udp recv failed: {}", err);
+                msg_queue
+                    .lock()
+                    .unwrap()
+                    .push_back(UdpMessage::Error(err.to_string()));
+                break;
+            }
+        }
+    }
+}
+
+/// ICMP端口不可达, Linux上已连接的socket返回ConnectionRefused, Windows上返回WSAECONNRESET
+pub(crate) fn is_port_unreachable(err: &std::io::Error) -> bool {
+    matches!(
+        err.kind(),
+        std::io::ErrorKind::ConnectionRefused | std::io::ErrorKind::ConnectionReset
+    )
+}
+
+unsafe fn parse_addr(addr: *const c_char) -> Option<SocketAddr> {
+    if addr.is_null() {
+        return None;
+    }
+    let addr = CStr::from_ptr(addr).to_str().ok()?;
+    match addr.parse::<SocketAddr>() {
+        Ok(addr) => Some(addr),
+        Err(_) => {
+            log::warn!("invalid udp address: {}", addr);
+            None
+        }
+    }
+}
+
+/// UDP事件
+/// message_type:
+/// 0: 没有数据报
+/// 3: 接收出错, data为原因, 之后不会再收到数据报
+/// 7: 收到数据报, remote_addr为来源地址
+#[repr(C)]
+pub struct UdpMessageData {
+    message_type: i32,
+    data: *const u8,
+    len: usize,
+    cap: usize,
+    // 来源地址, 例如 "192.168.1.2:9000"
+    remote_addr: *mut c_char,
+}
+
+impl UdpMessageData {
+    fn default() -> Self {
+        Self {
+            message_type: 0,
+            data: std::ptr::null(),
+            len: 0,
+            cap: 0,
+            remote_addr: std::ptr::null_mut(),
+        }
+    }
+
+    fn from(message: UdpMessage) -> Self {
+        let (message_type, buffer, remote_addr) = match message {
+            UdpMessage::Error(reason) => (3, reason.into_bytes(), std::ptr::null_mut()),
+            UdpMessage::Recv(data, addr) => (
+                7,
+                data,
+                CString::new(addr.to_string())
+                    .map(CString::into_raw)
+                    .unwrap_or(std::ptr::null_mut()),
+            ),
+        };
+        let ret = Self {
+            message_type,
+            data: buffer.as_ptr(),
+            len: buffer.len(),
+            cap: buffer.capacity(),
+            remote_addr,
+        };
+        // 防止 Rust 在离开这个函数时自动清理 buffer
+        std::mem::forget(buffer);
+        ret
+    }
+}
@@ -0,0 +1,142 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// 网络统计快照
+#[repr(C)]
+pub struct NetStats {
+    /// 发起的http请求数
+    requests_started: u64,
+    /// 成功的http请求数(状态码2xx)
+    requests_succeeded: u64,
+    /// 超时失败
+    requests_failed_timeout: u64,
+    /// 连接失败
+    requests_failed_connect: u64,
+    /// 响应状态码非2xx
+    requests_failed_status: u64,
+    /// 接收或解码body失败
+    requests_failed_body: u64,
+    /// 其它原因失败
+    requests_failed_other: u64,
+    /// http上传字节数(请求body)
+    bytes_uploaded: u64,
+    /// http下载字节数(响应body)
+    bytes_downloaded: u64,
+    /// 正在进行中的http请求数
+    active_requests: u64,
+    /// 当前打开的websocket连接数
+    ws_open_connections: u64,
+    /// websocket发送消息数
+    ws_messages_sent: u64,
+    /// websocket接收消息数
+    ws_messages_received: u64,
+    /// websocket发送字节数
+    ws_bytes_sent: u64,
+    /// websocket接收字节数
+    ws_bytes_received: u64,
+}
+
+/// 请求失败的原因
+pub enum FailKind {
+    Timeout,
+    Connect,
+    Status,
+    Body,
+    Other,
+}
+
+/// 统计计数器, 多线程共享
+#[derive(Default)]
+pub struct NetCounters {
+    requests_started: AtomicU64,
+    requests_succeeded: AtomicU64,
+    requests_failed_timeout: AtomicU64,
+    requests_failed_connect: AtomicU64,
+    requests_failed_status: AtomicU64,
+    requests_failed_body: AtomicU64,
+    requests_failed_other: AtomicU64,
+    bytes_uploaded: AtomicU64,
+    bytes_downloaded: AtomicU64,
+    active_requests: AtomicU64,
+    ws_open_connections: AtomicU64,
+    ws_messages_sent: AtomicU64,
+    ws_messages_received: AtomicU64,
+    ws_bytes_sent: AtomicU64,
+    ws_bytes_received: AtomicU64,
+}
+
+impl NetCounters {
+    pub fn request_started(&self) {
+        self.requests_started.fetch_add(1, Ordering::Relaxed);
+        self.active_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 请求结束(包括被取消), 与 request_started 成对调用
+    pub fn request_finished(&self) {
+        self.active_requests.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn request_succeeded(&self) {
+        self.requests_succeeded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn request_failed(&self, kind: FailKind) {
+        let counter = match kind {
+            FailKind::Timeout => &self.requests_failed_timeout,
+            FailKind::Connect => &self.requests_failed_connect,
+            FailKind::Status => &self.requests_failed_status,
+            FailKind::Body => &self.requests_failed_body,
+            FailKind::Other => &self.requests_failed_other,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_uploaded(&self, bytes: usize) {
+        self.bytes_uploaded
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_downloaded(&self, bytes: usize) {
+        self.bytes_downloaded
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn ws_opened(&self) {
+        self.ws_open_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn ws_closed(&self) {
+        self.ws_open_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn ws_sent(&self, bytes: usize) {
+        self.ws_messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.ws_bytes_sent
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn ws_received(&self, bytes: usize) {
+        self.ws_messages_received.fetch_add(1, Ordering::Relaxed);
+        self.ws_bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> NetStats {
+        NetStats {
+            requests_started: self.requests_started.load(Ordering::Relaxed),
+            requests_succeeded: self.requests_succeeded.load(Ordering::Relaxed),
+            requests_failed_timeout: self.requests_failed_timeout.load(Ordering::Relaxed),
+            requests_failed_connect: self.requests_failed_connect.load(Ordering::Relaxed),
+            requests_failed_status: self.requests_failed_status.load(Ordering::Relaxed),
+            requests_failed_body: self.requests_failed_body.load(Ordering::Relaxed),
+            requests_failed_other: self.requests_failed_other.load(Ordering::Relaxed),
+            bytes_uploaded: self.bytes_uploaded.load(Ordering::Relaxed),
+            bytes_downloaded: self.bytes_downloaded.load(Ordering::Relaxed),
+            active_requests: self.active_requests.load(Ordering::Relaxed),
+            ws_open_connections: self.ws_open_connections.load(Ordering::Relaxed),
+            ws_messages_sent: self.ws_messages_sent.load(Ordering::Relaxed),
+            ws_messages_received: self.ws_messages_received.load(Ordering::Relaxed),
+            ws_bytes_sent: self.ws_bytes_sent.load(Ordering::Relaxed),
+            ws_bytes_received: self.ws_bytes_received.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 令牌桶限速器, 速率可以在运行时调整
+/// 桶容量为1秒的流量, 超出的部分记为欠账, 后续调用需等待欠账还清
+#[derive(Default)]
+pub struct RateLimiter {
+    // 每秒字节数 0表示不限速
+    rate: AtomicU64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Default)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn set_rate(&self, bytes_per_sec: u64) {
+        self.rate.store(bytes_per_sec, Ordering::Relaxed);
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.tokens = bucket.tokens.min(bytes_per_sec as f64);
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.rate.load(Ordering::Relaxed) > 0
+    }
+
+    /// 预扣 bytes 个令牌, 返回需要等待的时间
+    fn reserve(&self, bytes: usize) -> Duration {
+        let rate = self.rate.load(Ordering::Relaxed);
+        if rate == 0 {
+            return Duration::ZERO;
+        }
+        let rate = rate as f64;
+
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        match bucket.last_refill {
+            Some(last_refill) => {
+                let elapsed = now.duration_since(last_refill).as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+            }
+            // 第一次使用时桶是满的
+            None => bucket.tokens = rate,
+        }
+        bucket.last_refill = Some(now);
+        bucket.tokens -= bytes as f64;
+
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / rate)
+        }
+    }
+}
+
+/// 上传与下载各自的限速
+#[derive(Default)]
+pub struct Bandwidth {
+    pub upload: RateLimiter,
+    pub download: RateLimiter,
+}
+
+impl Bandwidth {
+    pub fn new(upload_bytes_per_sec: u64, download_bytes_per_sec: u64) -> Arc<Self> {
+        let bandwidth = Bandwidth::default();
+        bandwidth.set_limit(upload_bytes_per_sec, download_bytes_per_sec);
+        Arc::new(bandwidth)
+    }
+
+    pub fn set_limit(&self, upload_bytes_per_sec: u64, download_bytes_per_sec: u64) {
+        self.upload.set_rate(upload_bytes_per_sec);
+        self.download.set_rate(download_bytes_per_sec);
+    }
+}
+
+/// 同时受多个限速器约束(请求、客户端、全局), 等待其中最慢的一个
+pub async fn throttle<'a>(limiters: impl IntoIterator<Item = &'a RateLimiter>, bytes: usize) {
+    let wait = limiters
+        .into_iter()
+        .map(|limiter| limiter.reserve(bytes))
+        .max()
+        .unwrap_or_default();
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+    }
+}
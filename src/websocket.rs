@@ -1,25 +1,30 @@
+use crate::http::dns::{DnsConfig, DnsResolveCallback, Resolver};
+use crate::stats::NetCounters;
 use crate::TokioContext;
-use anyhow::Result;
-use futures_util::stream::{SplitSink, SplitStream};
+use anyhow::{anyhow, Result};
+use futures_util::stream::{FuturesUnordered, SplitSink, SplitStream};
 use futures_util::{SinkExt, StreamExt};
 use http::header::COOKIE;
 use http::HeaderValue;
+use serde::Deserialize;
 use std::collections::{HashMap, VecDeque};
-use std::ffi::CStr;
+use std::ffi::{c_void, CStr};
+use std::net::{IpAddr, SocketAddr};
 use std::os::raw::c_char;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpStream;
 use tokio::select;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio::sync::{Mutex, OnceCell};
 use tokio_tungstenite::tungstenite::client::IntoClientRequest;
 use tokio_tungstenite::{
-    connect_async, tungstenite::protocol::Message, MaybeTlsStream, WebSocketStream,
+    client_async_tls_with_config, tungstenite::protocol::Message, MaybeTlsStream, WebSocketStream,
 };
 
 enum WsMessage {
-    // 连接成功
-    ConnectSuccess,
+    // 连接成功, 附带实际连接的远端地址
+    ConnectSuccess(SocketAddr),
     // 连接失败
     ConnectFailed(String),
     // 断开连接
@@ -44,14 +49,88 @@ pub struct WsContext {
     tx: Arc<OnceCell<UnboundedSender<WsWriterMessage>>>,
 }
 
+/// 连接配置, 以json形式传入 rust_net_ws_connect_with_config
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct WsConfig {
+    // DNS配置: 静态host映射、缓存时间及地址族选择
+    dns: DnsConfig,
+    // Happy Eyeballs: 上一个地址连接未完成时, 间隔多久(毫秒)开始尝试下一个地址 0表示默认250
+    happy_eyeballs_delay_ms: u64,
+}
+
+/// # Safety
+/// url、cookies 必须是以\0结尾的UTF-8字符串
 #[no_mangle]
 pub unsafe extern "C" fn rust_net_ws_connect(
     context: &mut TokioContext,
     url: *const c_char,
     cookies: *const c_char,
+) -> *mut WsContext {
+    rust_net_ws_connect_with_config(
+        context,
+        url,
+        cookies,
+        std::ptr::null(),
+        None,
+        std::ptr::null_mut(),
+    )
+}
+
+/// 使用json配置连接, config 可以为空
+/// {
+///     "dns": {
+///         "hosts": {"ws.example.com": ["10.0.0.1"]},
+///         "cache_ttl_ms": 0,
+///         "ip_family": "auto"
+///     },
+///     "happy_eyeballs_delay_ms": 250
+/// }
+/// ip_family: auto、prefer_ipv4、prefer_ipv6、ipv4_only、ipv6_only
+/// resolver 为自定义DNS解析回调, 可以为空, 参考 rust_net_http_set_dns_resolver
+/// dns 配置与 resolver、user_data 都相同的连接共享解析缓存
+/// config 解析失败或 dns 配置无效时返回空指针
+///
+/// # Safety
+/// url、cookies 必须是以\0结尾的UTF-8字符串
+/// config 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_ws_connect_with_config(
+    context: &mut TokioContext,
+    url: *const c_char,
+    cookies: *const c_char,
+    config: *const c_char,
+    resolver: DnsResolveCallback,
+    user_data: *mut c_void,
 ) -> *mut WsContext {
     let url = CStr::from_ptr(url).to_str().unwrap().to_string();
     let cookies = CStr::from_ptr(cookies).to_str().unwrap().to_string();
+    log::debug!("connect {}", url);
+
+    let config = if config.is_null() {
+        WsConfig::default()
+    } else {
+        let config = CStr::from_ptr(config).to_str().unwrap();
+        match serde_json::from_str::<WsConfig>(config) {
+            Ok(config) => config,
+            Err(err) => {
+                log::error!("ws connect config error: {}", err);
+                return std::ptr::null_mut();
+            }
+        }
+    };
+    let happy_eyeballs_delay = if config.happy_eyeballs_delay_ms > 0 {
+        Duration::from_millis(config.happy_eyeballs_delay_ms)
+    } else {
+        Duration::from_millis(250)
+    };
+    let dns_resolver = match context.resolvers.get(&config.dns, resolver, user_data) {
+        Ok(dns_resolver) => dns_resolver,
+        Err(err) => {
+            log::error!("ws connect config error: {}", err);
+            return std::ptr::null_mut();
+        }
+    };
 
     let ws_context = WsContext {
         tx: Arc::new(OnceCell::new()),
@@ -60,9 +139,10 @@ pub unsafe extern "C" fn rust_net_ws_connect(
 
     let tx_cloned = ws_context.tx.clone();
     let msg_queue = ws_context.msg_queue.clone();
+    let stats = context.stats.clone();
 
     context.runtime.spawn(async move {
-        let result = ws_connect(url, cookies).await;
+        let result = ws_connect(url, cookies, dns_resolver, happy_eyeballs_delay).await;
 
         // 调用了 rust_net_ws_connect 之后 立即调用 rust_net_ws_free 销毁了WsContext
         if Arc::strong_count(&tx_cloned) == 1 {
@@ -70,10 +150,14 @@ pub unsafe extern "C" fn rust_net_ws_connect(
         }
 
         match result {
-            Ok(ws_stream) => {
+            Ok((ws_stream, remote_addr)) => {
                 let (tx, rx) = unbounded_channel::<WsWriterMessage>();
-                if let Ok(_) = tx_cloned.set(tx) {
-                    msg_queue.lock().await.push_back(WsMessage::ConnectSuccess);
+                if tx_cloned.set(tx).is_ok() {
+                    log::info!("connected to {}", remote_addr);
+                    msg_queue
+                        .lock()
+                        .await
+                        .push_back(WsMessage::ConnectSuccess(remote_addr));
                 } else {
                     msg_queue
                         .lock()
@@ -81,13 +165,17 @@ pub unsafe extern "C" fn rust_net_ws_connect(
                         .push_back(WsMessage::ConnectFailed("init failed".to_string()));
                 }
 
+                stats.ws_opened();
                 let (writer, reader) = ws_stream.split();
                 select! {
-                    _ = poll_read(reader, msg_queue.clone()) => {}
-                    _ = poll_write(writer, rx, msg_queue) => {}
+                    _ = poll_read(reader, msg_queue.clone(), stats.clone()) => {}
+                    _ = poll_write(writer, rx, msg_queue, stats.clone()) => {}
                 }
+                stats.ws_closed();
+                log::info!("disconnected");
             }
             Err(err) => {
+                log::warn!("connect failed: {}", err);
                 msg_queue
                     .lock()
                     .await
@@ -99,6 +187,8 @@ pub unsafe extern "C" fn rust_net_ws_connect(
     Box::into_raw(Box::new(ws_context))
 }
 
+/// # Safety
+/// data 可以为空, 不为空时必须指向至少 length 字节的可读内存
 #[no_mangle]
 pub unsafe extern "C" fn rust_net_ws_send(
     ws_context: &mut WsContext,
@@ -117,7 +207,7 @@ pub unsafe extern "C" fn rust_net_ws_send(
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn rust_net_ws_get_message(ws_context: &mut WsContext) -> WsMessageData {
+pub extern "C" fn rust_net_ws_get_message(ws_context: &mut WsContext) -> WsMessageData {
     if let Ok(mut queue) = ws_context.msg_queue.try_lock() {
         if let Some(message) = queue.pop_front() {
             return WsMessageData::from(message);
@@ -128,12 +218,14 @@ pub unsafe extern "C" fn rust_net_ws_get_message(ws_context: &mut WsContext) ->
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn rust_net_ws_close(ws_context: &mut WsContext) {
+pub extern "C" fn rust_net_ws_close(ws_context: &mut WsContext) {
     if let Some(tx) = ws_context.tx.get() {
         let _ = tx.send(WsWriterMessage::Close);
     }
 }
 
+/// # Safety
+/// ws_context 必须是 rust_net_ws_connect 或 rust_net_ws_connect_with_config 返回的指针, 释放后不能再使用
 #[no_mangle]
 pub unsafe extern "C" fn rust_net_ws_free(ws_context: *mut WsContext) {
     let ws_context = Box::from_raw(ws_context);
@@ -147,7 +239,7 @@ pub unsafe extern "C" fn rust_net_ws_free(ws_context: *mut WsContext) {
 
 #[no_mangle]
 pub extern "C" fn rust_net_ws_free_message(resp: WsMessageData) {
-    if resp.data.is_null() || resp.cap <= 0 {
+    if resp.data.is_null() || resp.cap == 0 {
         return;
     }
     unsafe {
@@ -160,7 +252,9 @@ pub extern "C" fn rust_net_ws_free_message(resp: WsMessageData) {
 async fn ws_connect(
     url: String,
     cookies: String,
-) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+    resolver: Resolver,
+    happy_eyeballs_delay: Duration,
+) -> Result<(WebSocketStream<MaybeTlsStream<TcpStream>>, SocketAddr)> {
     let mut req = url.into_client_request()?;
 
     match serde_json::from_str::<HashMap<String, String>>(&cookies) {
@@ -176,22 +270,94 @@ async fn ws_connect(
             }
         }
         Err(err) => {
-            println!("[wss] json decode error: {}", err);
+            log::warn!("cookies json decode error: {}", err);
+        }
+    }
+
+    let uri = req.uri();
+    let host = uri
+        .host()
+        .ok_or_else(|| anyhow!("url has no host"))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = match uri.port_u16() {
+        Some(port) => port,
+        None if uri.scheme_str() == Some("wss") => 443,
+        None => 80,
+    };
+
+    let addrs = match host.parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => resolver
+            .lookup(&host)
+            .await?
+            .into_iter()
+            .map(|addr| SocketAddr::new(addr.ip(), port))
+            .collect(),
+    };
+    let (stream, remote_addr) = tcp_connect(addrs, happy_eyeballs_delay).await?;
+
+    let (ws_stream, _) = client_async_tls_with_config(req, stream, None, None).await?;
+    Ok((ws_stream, remote_addr))
+}
+
+/// Happy Eyeballs(RFC 8305): 按顺序尝试地址, 上一个地址在 delay 内未连接成功或已失败时开始尝试下一个
+/// 地址已由解析器按地址族交替排序, 第一个连接成功的地址胜出
+pub(crate) async fn tcp_connect(
+    addrs: Vec<SocketAddr>,
+    delay: Duration,
+) -> Result<(TcpStream, SocketAddr)> {
+    let mut addrs = addrs.into_iter().peekable();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_err = None;
+
+    loop {
+        if attempts.is_empty() {
+            match addrs.next() {
+                Some(addr) => attempts.push(connect_addr(addr)),
+                None => break,
+            }
+        }
+        let has_next = addrs.peek().is_some();
+        select! {
+            Some((addr, result)) = attempts.next() => match result {
+                Ok(stream) => return Ok((stream, addr)),
+                Err(err) => {
+                    log::debug!("connect {} failed: {}", addr, err);
+                    last_err = Some(err);
+                    if let Some(addr) = addrs.next() {
+                        attempts.push(connect_addr(addr));
+                    }
+                }
+            },
+            _ = tokio::time::sleep(delay), if has_next => {
+                if let Some(addr) = addrs.next() {
+                    attempts.push(connect_addr(addr));
+                }
+            }
         }
     }
+    match last_err {
+        Some(err) => Err(err.into()),
+        None => Err(anyhow!("dns resolved no address")),
+    }
+}
 
-    let (ws_stream, _) = connect_async(req).await?;
-    Ok(ws_stream)
+async fn connect_addr(addr: SocketAddr) -> (SocketAddr, std::io::Result<TcpStream>) {
+    (addr, TcpStream::connect(addr).await)
 }
 
 async fn poll_write(
     mut writer: SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>,
     mut rx: UnboundedReceiver<WsWriterMessage>,
     msg_queue: Arc<Mutex<VecDeque<WsMessage>>>,
+    stats: Arc<NetCounters>,
 ) {
     while let Some(message) = rx.recv().await {
         match message {
             WsWriterMessage::Send(data) => {
+                let len = data.len();
                 if let Err(err) = writer.send(Message::from(data)).await {
                     msg_queue
                         .lock()
@@ -199,6 +365,7 @@ async fn poll_write(
                         .push_back(WsMessage::Disconnect(err.to_string()));
                     break;
                 }
+                stats.ws_sent(len);
             }
             WsWriterMessage::Close => {
                 msg_queue
@@ -215,6 +382,7 @@ async fn poll_write(
 async fn poll_read(
     mut reader: SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>,
     msg_queue: Arc<Mutex<VecDeque<WsMessage>>>,
+    stats: Arc<NetCounters>,
 ) {
     while let Some(result) = reader.next().await {
         let msg = {
@@ -231,9 +399,11 @@ async fn poll_read(
         };
         match msg {
             Message::Text(data) => {
+                stats.ws_received(data.len());
                 msg_queue.lock().await.push_back(WsMessage::RecvText(data));
             }
             Message::Binary(data) => {
+                stats.ws_received(data.len());
                 msg_queue
                     .lock()
                     .await
@@ -278,7 +448,10 @@ impl WsMessageData {
         let message_type;
         let mut buffer: Option<Vec<u8>> = None;
         match msg {
-            WsMessage::ConnectSuccess => message_type = 1,
+            WsMessage::ConnectSuccess(remote_addr) => {
+                message_type = 1;
+                buffer = Some(remote_addr.to_string().into());
+            }
             WsMessage::ConnectFailed(data) => {
                 message_type = 2;
                 buffer = Some(data.into());
@@ -0,0 +1,407 @@
+use crate::stats::NetCounters;
+use crate::TokioContext;
+use futures_util::{SinkExt, StreamExt};
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{CStr, CString};
+use std::net::SocketAddr;
+use std::os::raw::c_char;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::select;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+
+// WebSocket握手超时
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+enum WsServerMessage {
+    // 新连接, 附带客户端地址
+    Accept(SocketAddr),
+    // 连接断开
+    Disconnect(String),
+    // 收到文本消息
+    RecvText(String),
+    // 收到二进制消息
+    RecvBinary(Vec<u8>),
+}
+
+enum WsServerWriterMessage {
+    Send(Message),
+    // 踢出连接, 附带原因
+    Close(String),
+}
+
+/// WebSocket服务器
+pub struct WsServerContext {
+    local_addr: SocketAddr,
+    shared: Arc<Shared>,
+    accept_task: JoinHandle<()>,
+}
+
+struct Shared {
+    msg_queue: Mutex<VecDeque<(u64, WsServerMessage)>>,
+    connections: Mutex<HashMap<u64, UnboundedSender<WsServerWriterMessage>>>,
+    next_id: AtomicU64,
+    // 服务器已释放, 之后完成握手的连接直接关闭
+    closed: AtomicBool,
+    stats: Arc<NetCounters>,
+}
+
+/// 启动WebSocket服务器, addr 例如 "0.0.0.0:9000", 端口为0时由系统分配
+/// 绑定失败时返回空指针
+///
+/// # Safety
+/// addr 必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_ws_server_new(
+    context: &mut TokioContext,
+    addr: *const c_char,
+) -> *mut WsServerContext {
+    let addr = CStr::from_ptr(addr).to_str().unwrap();
+    let _guard = context.runtime.enter();
+    let listener = std::net::TcpListener::bind(addr)
+        .and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            TcpListener::from_std(listener)
+        })
+        .and_then(|listener| Ok((listener.local_addr()?, listener)));
+    let (local_addr, listener) = match listener {
+        Ok(listener) => listener,
+        Err(err) => {
+            log::error!("ws server bind {} failed: {}", addr, err);
+            return std::ptr::null_mut();
+        }
+    };
+
+    let shared = Arc::new(Shared {
+        msg_queue: Mutex::new(VecDeque::new()),
+        connections: Mutex::new(HashMap::new()),
+        next_id: AtomicU64::new(1),
+        closed: AtomicBool::new(false),
+        stats: context.stats.clone(),
+    });
+    let accept_task = context.runtime.spawn(accept_loop(listener, shared.clone()));
+    log::info!("ws server listening on {}", local_addr);
+
+    Box::into_raw(Box::new(WsServerContext {
+        local_addr,
+        shared,
+        accept_task,
+    }))
+}
+
+/// 停止监听并断开所有连接
+///
+/// # Safety
+/// server 必须是 rust_net_ws_server_new 返回的指针, 释放后不能再使用
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_ws_server_free(server: *mut WsServerContext) {
+    let server = Box::from_raw(server);
+    server.accept_task.abort();
+    server.shared.closed.store(true, Ordering::Relaxed);
+    // 发送端全部释放后连接的写任务会关闭连接
+    server.shared.connections.lock().unwrap().clear();
+    drop(server)
+}
+
+/// 实际监听的地址, 例如 "0.0.0.0:9000"
+/// 使用完成之后 调用 rust_net_http_free_string 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_ws_server_local_addr(server: &mut WsServerContext) -> *mut c_char {
+    match CString::new(server.local_addr.to_string()) {
+        Ok(cstr) => cstr.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// 取出一个事件, 没有事件时 event_type 为0
+/// 使用完成之后 调用 rust_net_ws_server_free_event 释放内存
+#[no_mangle]
+pub extern "C" fn rust_net_ws_server_get_event(server: &mut WsServerContext) -> WsServerEvent {
+    match server.shared.msg_queue.lock().unwrap().pop_front() {
+        Some((connection_id, message)) => WsServerEvent::from(connection_id, message),
+        None => WsServerEvent::default(),
+    }
+}
+
+/// 向一个连接发送消息, text 为true时以文本消息发送(需要是UTF-8)
+/// 连接不存在时返回false
+///
+/// # Safety
+/// data 必须指向至少 length 字节的可读内存
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_ws_server_send(
+    server: &mut WsServerContext,
+    connection_id: u64,
+    data: *const u8,
+    length: usize,
+    text: bool,
+) -> bool {
+    let message = match to_message(data, length, text) {
+        Some(message) => message,
+        None => return false,
+    };
+    match server
+        .shared
+        .connections
+        .lock()
+        .unwrap()
+        .get(&connection_id)
+    {
+        Some(tx) => tx.send(WsServerWriterMessage::Send(message)).is_ok(),
+        None => false,
+    }
+}
+
+/// 向所有连接发送消息, 返回发送的连接数
+///
+/// # Safety
+/// data 必须指向至少 length 字节的可读内存
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_ws_server_broadcast(
+    server: &mut WsServerContext,
+    data: *const u8,
+    length: usize,
+    text: bool,
+) -> u32 {
+    let message = match to_message(data, length, text) {
+        Some(message) => message,
+        None => return 0,
+    };
+    let connections = server.shared.connections.lock().unwrap();
+    connections
+        .values()
+        .filter(|tx| {
+            tx.send(WsServerWriterMessage::Send(message.clone()))
+                .is_ok()
+        })
+        .count() as u32
+}
+
+/// 断开一个连接, reason 可以为空, 连接不存在时返回false
+/// 断开后会收到该连接的断开事件
+///
+/// # Safety
+/// reason 可以为空, 不为空时必须是以\0结尾的UTF-8字符串
+#[no_mangle]
+pub unsafe extern "C" fn rust_net_ws_server_kick(
+    server: &mut WsServerContext,
+    connection_id: u64,
+    reason: *const c_char,
+) -> bool {
+    let reason = if reason.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(reason).to_str().unwrap().to_string()
+    };
+    match server
+        .shared
+        .connections
+        .lock()
+        .unwrap()
+        .get(&connection_id)
+    {
+        Some(tx) => tx.send(WsServerWriterMessage::Close(reason)).is_ok(),
+        None => false,
+    }
+}
+
+/// 当前的连接数
+#[no_mangle]
+pub extern "C" fn rust_net_ws_server_connection_count(server: &mut WsServerContext) -> u32 {
+    server.shared.connections.lock().unwrap().len() as u32
+}
+
+#[no_mangle]
+pub extern "C" fn rust_net_ws_server_free_event(event: WsServerEvent) {
+    if event.data.is_null() || event.cap == 0 {
+        return;
+    }
+    unsafe {
+        let buffer = Vec::from_raw_parts(event.data as *mut u8, event.len, event.cap);
+        // Rust 会在这里清理内存
+        drop(buffer);
+    }
+}
+
+unsafe fn to_message(data: *const u8, length: usize, text: bool) -> Option<Message> {
+    let data = if data.is_null() {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(data, length).to_vec()
+    };
+    if text {
+        String::from_utf8(data).ok().map(Message::Text)
+    } else {
+        Some(Message::Binary(data))
+    }
+}
+
+async fn accept_loop(listener: TcpListener, shared: Arc<Shared>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, remote_addr)) => {
+                tokio::spawn(serve_connection(stream, remote_addr, shared.clone()));
+            }
+            Err(err) => {
+                log::warn!("ws server accept failed: {}", err);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
+
+async fn serve_connection(stream: TcpStream, remote_addr: SocketAddr, shared: Arc<Shared>) {
+    let handshake =
+        tokio::time::timeout(HANDSHAKE_TIMEOUT, tokio_tungstenite::accept_async(stream));
+    let ws_stream = match handshake.await {
+        Ok(Ok(ws_stream)) => ws_stream,
+        Ok(Err(err)) => {
+            log::debug!("ws server handshake with {} failed: {}", remote_addr, err);
+            return;
+        }
+        Err(_) => {
+            log::debug!("ws server handshake with {} timed out", remote_addr);
+            return;
+        }
+    };
+
+    let id = shared.next_id.fetch_add(1, Ordering::Relaxed);
+    let (tx, mut rx) = unbounded_channel();
+    {
+        let mut connections = shared.connections.lock().unwrap();
+        if shared.closed.load(Ordering::Relaxed) {
+            return;
+        }
+        connections.insert(id, tx);
+    }
+    shared.push(id, WsServerMessage::Accept(remote_addr));
+    shared.stats.ws_opened();
+    log::info!("ws server accepted {} from {}", id, remote_addr);
+
+    let (mut writer, mut reader) = ws_stream.split();
+    let reason = select! {
+        reason = poll_read(id, &mut reader, &shared) => reason,
+        reason = poll_write(&mut writer, &mut rx, &shared) => reason,
+    };
+    let _ = writer.close().await;
+
+    shared.connections.lock().unwrap().remove(&id);
+    shared.push(id, WsServerMessage::Disconnect(reason));
+    shared.stats.ws_closed();
+    log::info!("ws server connection {} closed", id);
+}
+
+async fn poll_read(
+    id: u64,
+    reader: &mut (impl StreamExt<Item = tokio_tungstenite::tungstenite::Result<Message>> + Unpin),
+    shared: &Shared,
+) -> String {
+    while let Some(result) = reader.next().await {
+        let msg = match result {
+            Ok(msg) => msg,
+            Err(err) => return err.to_string(),
+        };
+        match msg {
+            Message::Text(data) => {
+                shared.stats.ws_received(data.len());
+                shared.push(id, WsServerMessage::RecvText(data));
+            }
+            Message::Binary(data) => {
+                shared.stats.ws_received(data.len());
+                shared.push(id, WsServerMessage::RecvBinary(data));
+            }
+            Message::Close(_) => return "close".into(),
+            // Ping由tungstenite自动回复
+            Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => {}
+        }
+    }
+    "close".into()
+}
+
+async fn poll_write(
+    writer: &mut (impl SinkExt<Message, Error = tokio_tungstenite::tungstenite::Error> + Unpin),
+    rx: &mut UnboundedReceiver<WsServerWriterMessage>,
+    shared: &Shared,
+) -> String {
+    while let Some(message) = rx.recv().await {
+        match message {
+            WsServerWriterMessage::Send(message) => {
+                let len = message.len();
+                if let Err(err) = writer.send(message).await {
+                    return err.to_string();
+                }
+                shared.stats.ws_sent(len);
+            }
+            WsServerWriterMessage::Close(reason) => {
+                let frame = CloseFrame {
+                    code: CloseCode::Normal,
+                    reason: reason.clone().into(),
+                };
+                let _ = writer.send(Message::Close(Some(frame))).await;
+                return format!("kicked: {}", reason);
+            }
+        }
+    }
+    // 服务器已释放
+    "server closed".into()
+}
+
+impl Shared {
+    fn push(&self, id: u64, message: WsServerMessage) {
+        self.msg_queue.lock().unwrap().push_back((id, message));
+    }
+}
+
+/// 服务器事件
+/// event_type:
+/// 0: 没有事件
+/// 1: 新连接, data为客户端地址
+/// 3: 连接断开, data为原因
+/// 6: 收到文本消息
+/// 7: 收到二进制消息
+/// 与 rust_net_ws_get_message 的 message_type 一致
+#[repr(C)]
+pub struct WsServerEvent {
+    event_type: i32,
+    connection_id: u64,
+    data: *const u8,
+    len: usize,
+    cap: usize,
+}
+
+impl WsServerEvent {
+    fn default() -> Self {
+        Self {
+            event_type: 0,
+            connection_id: 0,
+            data: std::ptr::null(),
+            len: 0,
+            cap: 0,
+        }
+    }
+
+    fn from(connection_id: u64, message: WsServerMessage) -> Self {
+        let (event_type, buffer): (i32, Vec<u8>) = match message {
+            WsServerMessage::Accept(remote_addr) => (1, remote_addr.to_string().into()),
+            WsServerMessage::Disconnect(reason) => (3, reason.into()),
+            WsServerMessage::RecvText(data) => (6, data.into()),
+            WsServerMessage::RecvBinary(data) => (7, data),
+        };
+        let event = Self {
+            event_type,
+            connection_id,
+            data: buffer.as_ptr(),
+            len: buffer.len(),
+            cap: buffer.capacity(),
+        };
+        // 防止 Rust 在离开这个函数时自动清理 buffer
+        std::mem::forget(buffer);
+        event
+    }
+}